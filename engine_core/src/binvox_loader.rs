@@ -0,0 +1,230 @@
+/// Loads .binvox files, see https://www.patrickmin.com/binvox/binvox.html for the format.
+
+use std::fs;
+
+use glam::*;
+
+use voxel_dag::volume::Volume;
+
+use crate::material::MaterialTable;
+use crate::vox_loader::{LoadError, PlacedVolume, voxel_count};
+
+//Binvox only stores occupancy, so every solid voxel gets the first material
+const SOLID: u8 = 1;
+
+/// Loads a .binvox file, keeping its translation and scale.
+pub fn load_binvox(filename: &str) -> Result<PlacedVolume, LoadError> {
+    let bytes = fs::read(filename)?;
+    parse_binvox(&bytes)
+}
+
+/// Parses the contents of a .binvox file.
+pub fn parse_binvox(bytes: &[u8]) -> Result<PlacedVolume, LoadError> {
+    //The header is text, one line per field, up to a line that just says data
+    let mut dims = None;
+    let mut translation = Vec3::zero();
+    let mut scale = 1.0;
+    let mut pos = 0;
+    let mut first = true;
+    loop {
+        let end = bytes[pos..].iter().position(|b| *b == b'\n').ok_or_else(|| parse_error("Header never ends"))?;
+        let line = std::str::from_utf8(&bytes[pos..pos + end]).map_err(|_| parse_error("Header isn't text"))?.trim();
+        pos += end + 1;
+
+        if first {
+            if !line.starts_with("#binvox") { return Err(parse_error("Missing #binvox magic")); }
+            first = false;
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("dim") => {
+                let values = parse_values::<u32>(fields, "dim")?;
+                dims = Some((values[0], values[1], values[2]));
+            },
+            Some("translate") => {
+                let values = parse_values::<f32>(fields, "translate")?;
+                translation = Vec3::new(values[0], values[1], values[2]);
+            },
+            Some("scale") => {
+                scale = fields.next().and_then(|value| value.parse().ok()).ok_or_else(|| parse_error("Invalid scale"))?;
+            },
+            Some("data") => break,
+            _ => return Err(parse_error(&format!("Unknown header line {:?}", line))),
+        }
+    }
+    let (depth, width, height) = dims.ok_or_else(|| parse_error("Missing dim"))?;
+
+    //Every run covers at most 255 voxels, so a short file can be rejected before allocating anything
+    let total = voxel_count((depth, height, width))? as u64;
+    if total > (bytes.len() - pos) as u64 / 2 * 255 {
+        return Err(parse_error("Runs cover fewer voxels than dim"));
+    }
+
+    //Voxels are ordered with y running fastest, then z, then x
+    let mut volume = Volume::new((depth, height, width));
+    let mut idx: u64 = 0;
+    for pair in bytes[pos..].chunks(2) {
+        if idx >= total { break; }
+        if pair.len() < 2 { return Err(parse_error("Run is missing its length")); }
+        let (value, count) = (pair[0], pair[1] as u64);
+        if idx + count > total { return Err(parse_error("Runs cover more voxels than dim")); }
+        if value != 0 {
+            for i in idx..idx + count {
+                let y = (i % height as u64) as u32;
+                let z = (i / height as u64 % width as u64) as u32;
+                let x = (i / (height as u64 * width as u64)) as u32;
+                volume.set(x, y, z, SOLID);
+            }
+        }
+        idx += count;
+    }
+    if idx != total {
+        return Err(parse_error("Runs cover fewer voxels than dim"));
+    }
+
+    Ok(PlacedVolume {
+        volume: volume,
+        translation: translation,
+        scale: scale,
+        materials: MaterialTable::default(),
+    })
+}
+
+fn parse_values<'a, T: std::str::FromStr>(fields: impl Iterator<Item = &'a str>, name: &str) -> Result<Vec<T>, LoadError> {
+    let values = fields.map(|value| value.parse()).collect::<Result<Vec<T>, _>>().map_err(|_| parse_error(&format!("Invalid {}", name)))?;
+    if values.len() != 3 {
+        return Err(parse_error(&format!("{} needs 3 values", name)));
+    }
+    Ok(values)
+}
+
+fn parse_error(msg: &str) -> LoadError {
+    LoadError::Parse(msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binvox(header: &str, runs: &[(u8, u8)]) -> Vec<u8> {
+        let mut bytes = format!("#binvox 1\n{}data\n", header).into_bytes();
+        for &(value, count) in runs {
+            bytes.push(value);
+            bytes.push(count);
+        }
+        bytes
+    }
+
+    //Run length encodes voxels given in file order, splitting runs longer than 255
+    fn runs(voxels: &[bool]) -> Vec<(u8, u8)> {
+        let mut runs: Vec<(u8, u8)> = Vec::new();
+        for &solid in voxels {
+            match runs.last_mut() {
+                Some(run) if run.0 == solid as u8 && run.1 < 255 => run.1 += 1,
+                _ => runs.push((solid as u8, 1)),
+            }
+        }
+        runs
+    }
+
+    #[test]
+    fn voxels_are_ordered_y_then_z_then_x() {
+        //dim is depth (x), width (z) and height (y)
+        let (depth, width, height) = (3, 5, 4);
+        let solid = |x: u32, y: u32, z: u32| (x * 7 + y * 3 + z * 11) % 5 < 2;
+        let mut voxels = Vec::new();
+        for x in 0..depth {
+            for z in 0..width {
+                for y in 0..height {
+                    voxels.push(solid(x, y, z));
+                }
+            }
+        }
+        let placed = parse_binvox(&binvox(&format!("dim {} {} {}\n", depth, width, height), &runs(&voxels))).unwrap();
+        assert_eq!(placed.volume.size(), (depth, height, width));
+        for z in 0..width {
+            for y in 0..height {
+                for x in 0..depth {
+                    assert_eq!(placed.volume.get(x, y, z), if solid(x, y, z) { Some(SOLID) } else { None }, "({}, {}, {})", x, y, z);
+                }
+            }
+        }
+        assert_eq!(placed.materials, MaterialTable::default());
+    }
+
+    #[test]
+    fn runs_longer_than_255_voxels_are_split() {
+        let placed = parse_binvox(&binvox("dim 2 1 300\n", &runs(&[true; 600]))).unwrap();
+        assert_eq!(placed.volume.size(), (2, 300, 1));
+        assert!(placed.volume.data().iter().all(|material| *material == SOLID));
+    }
+
+    #[test]
+    fn translate_and_scale_are_kept() {
+        let placed = parse_binvox(&binvox("dim 1 1 1\ntranslate -1.5 2 0.25\nscale 3.5\n", &[(1, 1)])).unwrap();
+        assert_eq!(placed.translation, Vec3::new(-1.5, 2.0, 0.25));
+        assert_eq!(placed.scale, 3.5);
+
+        //Both are optional
+        let placed = parse_binvox(&binvox("dim 1 1 1\n", &[(1, 1)])).unwrap();
+        assert_eq!(placed.translation, Vec3::zero());
+        assert_eq!(placed.scale, 1.0);
+    }
+
+    #[test]
+    fn runs_have_to_cover_dim_exactly() {
+        //Anything after the last voxel is ignored
+        assert!(parse_binvox(&binvox("dim 2 2 2\n", &[(1, 8), (0, 3)])).is_ok());
+
+        let short = binvox("dim 2 2 2\n", &[(1, 4), (0, 3)]);
+        let long = binvox("dim 2 2 2\n", &[(1, 4), (0, 5)]);
+        let mut missing_length = binvox("dim 2 2 2\n", &[(1, 4)]);
+        missing_length.push(0);
+        for bytes in &[short, long, missing_length] {
+            match parse_binvox(bytes) {
+                Err(LoadError::Parse(_)) => {},
+                other => panic!("{:?}: {:?}", String::from_utf8_lossy(bytes), other.map(|placed| placed.volume.size())),
+            }
+        }
+        //Truncated anywhere, including in the header
+        let bytes = binvox("dim 4 4 4\ntranslate 0 0 0\n", &[(1, 60), (0, 4)]);
+        for len in 0..bytes.len() - 2 {
+            assert!(parse_binvox(&bytes[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn bad_headers_are_errors() {
+        let headers: &[&[u8]] = &[
+            b"binvox 1\ndim 1 1 1\ndata\n",
+            b"#binvox 1\ndata\n",
+            b"#binvox 1\ndim 1 1\ndata\n",
+            b"#binvox 1\ndim 1 1 1 1\ndata\n",
+            b"#binvox 1\ndim 1 -1 1\ndata\n",
+            b"#binvox 1\ndim 1 1 1\ntranslate 0 x 0\ndata\n",
+            b"#binvox 1\ndim 1 1 1\nscale\ndata\n",
+            b"#binvox 1\ndim 1 1 1\ncolour 1 2 3\ndata\n",
+            b"#binvox 1\ndim 1 1 1\n\xff\ndata\n",
+            b"#binvox 1\ndim 1 1 1\n",
+        ];
+        for header in headers {
+            let mut bytes = header.to_vec();
+            bytes.extend_from_slice(&[1, 1]);
+            match parse_binvox(&bytes) {
+                Err(LoadError::Parse(_)) => {},
+                other => panic!("{:?}: {:?}", String::from_utf8_lossy(header), other.map(|placed| placed.volume.size())),
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_dims_are_too_large() {
+        for dim in &["2048 1024 1024", "4294967295 4294967295 4294967295", "1 1 1073741825"] {
+            match parse_binvox(&binvox(&format!("dim {}\n", dim), &[(1, 255)])) {
+                Err(LoadError::TooLarge(_)) => {},
+                other => panic!("{}: {:?}", dim, other.map(|placed| placed.volume.size())),
+            }
+        }
+    }
+}
@@ -31,6 +31,36 @@ pub fn get_workgroup_invocations() -> i32 {
     value
 }
 
+/// Uploads a node buffer to a new SSBO and binds it to the given binding point.
+/// https://www.khronos.org/opengl/wiki/Shader_Storage_Buffer_Object
+pub fn create_ssbo(data: &[u32], binding: u32) -> u32 {
+    let mut ssbo: u32 = 0;
+    unsafe {
+        gl::GenBuffers(1, &mut ssbo);
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, ssbo);
+        gl::BufferData(gl::SHADER_STORAGE_BUFFER, std::mem::size_of_val(data) as isize, data.as_ptr() as *const std::ffi::c_void, gl::DYNAMIC_DRAW);
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, ssbo);
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0); //unbind ssbo
+    }
+    ssbo
+}
+
+/// Uploads one byte per voxel, like DAG materials, packed 4 to a u32 with the first byte in the lowest bits.
+pub fn create_byte_ssbo(data: &[u8], binding: u32) -> u32 {
+    let packed: Vec<u32> = data.chunks(4).map(|chunk| {
+        let mut bytes = [0u8; 4];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        u32::from_le_bytes(bytes)
+    }).collect();
+    create_ssbo(&packed, binding)
+}
+
+/// Uploads floats, like the material table, as-is.
+pub fn create_float_ssbo(data: &[f32], binding: u32) -> u32 {
+    let bits: Vec<u32> = data.iter().map(|value| value.to_bits()).collect();
+    create_ssbo(&bits, binding)
+}
+
 pub fn get_compute_program(cs: &str) -> <glow::Context as glow::HasContext>::Program {
     unsafe {
         let shader = gl::CreateShader(glow::COMPUTE_SHADER);
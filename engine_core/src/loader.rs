@@ -0,0 +1,138 @@
+/// Opens any supported voxel file, picking the loader by file extension.
+
+use std::path::Path;
+
+use glam::*;
+
+use crate::vox_loader::{self, LoadError, PlacedVolume};
+use crate::binvox_loader;
+use crate::raw_loader;
+
+/// Loads a .vox, .binvox or .raw file (with its .json sidecar, see raw_loader).
+pub fn load_volume(filename: &str) -> Result<PlacedVolume, LoadError> {
+    let extension = Path::new(filename).extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("vox") => load_vox_volume(filename),
+        Some("binvox") => binvox_loader::load_binvox(filename),
+        Some("raw") => raw_loader::load_raw(filename),
+        _ => Err(LoadError::UnknownFormat(filename.to_string())),
+    }
+}
+
+//Every model of the scene pasted into a single volume. MagicaVoxel has no units, so a voxel is one world unit
+fn load_vox_volume(filename: &str) -> Result<PlacedVolume, LoadError> {
+    let scene = vox_loader::load_vox_scene(filename)?;
+    let (volume, origin) = scene.assemble()?;
+    let size = volume.size();
+    Ok(PlacedVolume {
+        volume: volume,
+        translation: Vec3::new(origin.0 as f32, origin.1 as f32, origin.2 as f32),
+        scale: size.0.max(size.1).max(size.2) as f32,
+        materials: scene.materials,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    use voxel_dag::volume::Volume;
+
+    use crate::material::MaterialTable;
+    use crate::vox_writer;
+
+    //Files in the temp directory, removed again when dropped
+    struct TempFiles(Vec<PathBuf>);
+
+    impl TempFiles {
+        fn write(&mut self, name: &str, contents: &[u8]) -> String {
+            let path = std::env::temp_dir().join(format!("loader_test_{}_{}", std::process::id(), name));
+            fs::write(&path, contents).unwrap();
+            self.0.push(path.clone());
+            path.to_str().unwrap().to_string()
+        }
+    }
+
+    impl Drop for TempFiles {
+        fn drop(&mut self) {
+            for path in &self.0 {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    const BINVOX: &[u8] = b"#binvox 1\ndim 2 2 2\ntranslate 1 2 3\nscale 4\ndata\n\x01\x03\x00\x05";
+
+    fn vox() -> (Volume, Vec<u8>) {
+        let mut volume = Volume::new((3, 2, 4));
+        volume.set(0, 0, 0, 5);
+        volume.set(2, 1, 3, 9);
+        let mut bytes = Vec::new();
+        vox_writer::write_vox(&mut bytes, &volume, &MaterialTable::default()).unwrap();
+        (volume, bytes)
+    }
+
+    #[test]
+    fn formats_are_picked_by_extension() {
+        let mut files = TempFiles(Vec::new());
+        let (volume, vox_bytes) = vox();
+
+        for name in &["a.vox", "b.VOX"] {
+            let placed = load_volume(&files.write(name, &vox_bytes)).unwrap();
+            assert!(placed.volume == volume, "{}", name);
+            assert_eq!(placed.translation, Vec3::zero(), "{}", name);
+            assert_eq!(placed.scale, 4.0, "{}", name);
+        }
+        for name in &["c.binvox", "d.BinVox"] {
+            let placed = load_volume(&files.write(name, BINVOX)).unwrap();
+            assert_eq!(placed.volume.size(), (2, 2, 2), "{}", name);
+            assert_eq!(placed.translation, Vec3::new(1.0, 2.0, 3.0), "{}", name);
+            assert_eq!(placed.scale, 4.0, "{}", name);
+        }
+        for &(name, sidecar) in &[("e.raw", "e.json"), ("f.RAW", "f.json")] {
+            files.write(sidecar, br#"{ "size": [1, 2, 3] }"#);
+            let placed = load_volume(&files.write(name, &[1, 2, 3, 4, 5, 6])).unwrap();
+            assert_eq!(placed.volume.data(), &[1, 2, 3, 4, 5, 6], "{}", name);
+        }
+
+        //The contents don't matter, a binvox file with the wrong extension doesn't load
+        match load_volume(&files.write("g.vox", BINVOX)) {
+            Err(LoadError::Parse(_)) => {},
+            other => panic!("{:?}", other.map(|placed| placed.volume.size())),
+        }
+    }
+
+    #[test]
+    fn unknown_extensions_are_errors() {
+        let mut files = TempFiles(Vec::new());
+        for name in &["h.txt", "i", "j.vox.bak", "k."] {
+            let filename = files.write(name, BINVOX);
+            match load_volume(&filename) {
+                Err(LoadError::UnknownFormat(unknown)) => assert_eq!(unknown, filename),
+                other => panic!("{}: {:?}", name, other.map(|placed| placed.volume.size())),
+            }
+        }
+        //Nothing is read for an unknown format, so a missing file isn't an IO error either
+        match load_volume("missing.obj") {
+            Err(LoadError::UnknownFormat(_)) => {},
+            other => panic!("{:?}", other.map(|placed| placed.volume.size())),
+        }
+    }
+
+    #[test]
+    fn oversized_volumes_are_rejected() {
+        let mut files = TempFiles(Vec::new());
+        let binvox = files.write("l.binvox", b"#binvox 1\ndim 2048 1024 1024\ndata\n\x01\xff");
+        files.write("m.json", br#"{ "size": [1024, 1024, 1025] }"#);
+        let raw = files.write("m.raw", &[1; 16]);
+        for filename in &[binvox, raw] {
+            match load_volume(filename) {
+                Err(LoadError::TooLarge(_)) => {},
+                other => panic!("{}: {:?}", filename, other.map(|placed| placed.volume.size())),
+            }
+        }
+    }
+}
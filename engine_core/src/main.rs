@@ -1,5 +1,3 @@
-#![feature(box_syntax)]
-
 #[macro_use] extern crate log;
 #[macro_use] extern crate imgui;
 
@@ -13,6 +11,8 @@ use sdl2::event::Event;
 
 use glow::HasContext;
 
+use glam::*;
+
 use voxel_dag::*;
 
 mod camera;
@@ -21,7 +21,12 @@ mod mesh;
 mod compute;
 
 mod ui;
+mod material;
 mod vox_loader;
+mod vox_writer;
+mod binvox_loader;
+mod raw_loader;
+mod loader;
 mod rasterizer;
 
 pub fn initialize(width: u32, height: u32) -> Result<(SDL2Surface, glow::Context, sdl2::video::GLContext), &'static str> {
@@ -48,6 +53,34 @@ pub fn initialize(width: u32, height: u32) -> Result<(SDL2Surface, glow::Context
     }
 }
 
+//Command line: [file] [--symmetric] [--export file.vox]
+struct Options {
+    filename: String, //Any .vox, .binvox or .raw file
+    symmetric: bool, //Trace a symmetry-aware DAG instead of the plain one
+    export: Option<String>, //Write the loaded volume to this .vox file
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        filename: "teapot.vox".to_string(),
+        symmetric: false,
+        export: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--symmetric" => options.symmetric = true,
+            "--export" => match args.next() {
+                Some(path) => options.export = Some(path),
+                None => warn!("--export needs a file to write to"),
+            },
+            flag if flag.starts_with("--") => warn!("Unknown option {}", flag),
+            _ => options.filename = arg,
+        }
+    }
+    options
+}
+
 fn main() {
     // let level_filter = log::LevelFilter::max();
     let level_filter = log::LevelFilter::Debug;
@@ -58,10 +91,19 @@ fn main() {
 
     debug!("Hello, world!");
 
-    let vox_data = vox_loader::load_vox("teapot.vox");
-    debug!("Vox data loaded!");
-    // let dag = dag::DAG::from_voxel_data(&vox_data[..], (126, 126, 126));
-    // let mut octree = octree::Octree::from_voxel_data(&vox_data[..], (126, 126, 126), 2).expect("Failed to create octree!");
+    let options = parse_args();
+    let placed = loader::load_volume(&options.filename).unwrap_or_else(|e| panic!("Failed to load {}!\n{}", options.filename, e));
+    let volume = &placed.volume;
+    let volume_size = volume.size();
+    debug!("Voxel data loaded from {}! size: {:?}, voxel size: {}", options.filename, volume_size, placed.voxel_size());
+    if let Some(path) = &options.export {
+        match vox_writer::save_vox(path, volume, &placed.materials) {
+            Ok(()) => debug!("Exported volume to {}", path),
+            Err(e) => error!("Failed to export volume to {}!\n{}", path, e),
+        }
+    }
+    // let dag = dag::DAG::from_volume(&volume);
+    // let mut octree = octree::Octree::from_volume(&volume, 2).expect("Failed to create octree!");
     // octree.generate_level();
     // octree.generate_level();
     // octree.generate_level();
@@ -81,9 +123,30 @@ fn main() {
 
     gl::load_with(|s| surface.video.gl_get_proc_address(s) as _);
 
-    let test_shader = shader::RawShader::from_compute(include_str!("shaders/compute.glsl"));
-
-    let new_octree = voxel_data_structure::VoxelDAG::from_voxel_data(&vox_data[..], (126, 126, 126), 6);
+    let trace_shader = shader::RawShader::from_compute(include_str!("shaders/compute.glsl"));
+
+    //Only the DAG that gets traced is built, its node buffer, voxel counts and materials go to bindings 3, 4 and 5
+    let (dag_depth, dag_leaf_levels) = if options.symmetric {
+        let symmetric_dag = symmetry::SymmetricDAG::from_volume(volume).expect("Failed to create symmetric DAG!");
+        debug!("Symmetry-aware DAG: {} nodes", symmetric_dag.get_len() / 2);
+        compute::create_ssbo(symmetric_dag.get_data(), 3);
+        compute::create_ssbo(symmetric_dag.voxel_counts(), 4);
+        compute::create_byte_ssbo(symmetric_dag.materials(), 5);
+        //Symmetric DAGs always have voxel leaves
+        (symmetric_dag.depth(), 1)
+    } else {
+        let dag = dag::DAG::from_volume_parallel(volume).expect("Failed to create DAG!");
+        let dag = dag.with_leaf_format(bricks::LeafFormat::Bricks).expect("Failed to convert DAG to brick leaves!");
+        dag.validate().expect("DAG failed validation!");
+        let stats = dag.stats();
+        debug!("DAG with brick leaves: {} unique nodes, {} referenced ({:.2}x), {} bricks, {} node bytes", stats.unique_nodes, stats.referenced_nodes, stats.dedup_ratio(), stats.leaf_count, stats.node_bytes);
+        compute::create_ssbo(dag.get_data(), 3);
+        compute::create_ssbo(dag.voxel_counts(), 4);
+        compute::create_byte_ssbo(dag.materials(), 5);
+        (dag.depth(), dag.leaf_format().leaf_levels())
+    };
+    let _material_table_ssbo = compute::create_float_ssbo(&placed.materials.gpu_data(), 6);
+    let dag_offset = Vec3::new(volume_size.0 as f32, volume_size.1 as f32, volume_size.2 as f32) * -0.5; //Same offset the voxel mesh uses
 
     //quick debug for max ssbo size
     let mut ssbo_max_size = 0;
@@ -93,7 +156,7 @@ fn main() {
     debug!("MAX_COMPUTE_WORK_GROUP_SIZE: {:?}", compute::get_workgroup_size());
     debug!("MAX_COMPUTE_WORK_GROUP_INVOCATIONS: {}", compute::get_workgroup_invocations());
 
-    //let render_texture = rasterizer::create_texture((1280, 720));
+    let render_texture = rasterizer::create_texture((1280, 720));
     // rasterizer::create_frame_buffer((1280, 720));
 
     let renderer = imgui_opengl_renderer::Renderer::new(&mut imgui, |s| surface.video.gl_get_proc_address(s) as *const c_void);
@@ -104,7 +167,7 @@ fn main() {
     let mut delta_s: f32 = 1.0;
 
     //test shit
-    let mesh = mesh::RenderMesh::from_vox_data(&mut surface, &vox_data[..], (126, 126, 126)).expect("Failed to create mesh!");
+    let mesh = mesh::RenderMesh::from_volume(&mut surface, volume).expect("Failed to create mesh!");
     // let octree_mesh = mesh::RenderMesh::from_octants(&mut surface, &new_octree, 128.0).expect("Failed to create mesh!");
     let mut camera = camera::Camera::default();
 
@@ -157,13 +220,21 @@ fn main() {
             // rasterizer::draw_mesh(&mut surface, &gl, &camera, &shader, &octree_mesh);
         }*/
 
+        if let Err(e) = rasterizer::trace_dag(&trace_shader, &camera, render_texture, (1280, 720), dag_depth, dag_leaf_levels, dag_offset, 1.0) {
+            error!("Failed to trace the DAG!\n{}", e);
+            break 'main;
+        }
+
         //Render textured quad
         unsafe {
             let handle = quad_shader.program().deref().handle();
             gl::UseProgram(handle);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, render_texture);
             gl::BindVertexArray(quad_va);
             gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
             gl::BindVertexArray(0);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
             gl::UseProgram(0);
         }
 
@@ -180,6 +251,7 @@ fn main() {
             ui.text(format!("fps: {:.2}", 1.0 / delta_s));
             ui.separator();
             ui.text(format!("cam pos: {:?}", camera.position));
+            ui.text(format!("tracing: {}", if options.symmetric { "symmetric DAG" } else { "DAG" }));
         });
 
         imgui_sdl2.prepare_render(&ui, &surface.window);
@@ -0,0 +1,274 @@
+//Colours and shading properties for the 255 materials a voxel can have.
+//Materials are indexed by the u8 stored in every voxel, 0 is empty and never shaded.
+//These match MagicaVoxel's palette indices, which start at 1 in the file.
+
+/// Colour of every material, as RGBA.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colours: Vec<[u8; 4]>,
+}
+
+impl Palette {
+    /// Every material gets the same colour.
+    pub fn uniform(colour: [u8; 4]) -> Self {
+        Self {
+            colours: vec![colour; 256],
+        }
+    }
+
+    /// Reads the palette of a .vox file, dot_vox already fills in MagicaVoxel's default palette if the file has none.
+    pub fn from_vox(palette: &[dot_vox::Color]) -> Self {
+        let mut result = Self::default();
+        //dot_vox's palette starts at the first material, not at empty
+        for (i, colour) in palette.iter().take(255).enumerate() {
+            result.colours[i + 1] = [colour.r, colour.g, colour.b, colour.a];
+        }
+        result
+    }
+
+    pub fn colour(&self, material: u8) -> [u8; 4] {
+        self.colours[material as usize]
+    }
+
+    pub fn set_colour(&mut self, material: u8, colour: [u8; 4]) {
+        self.colours[material as usize] = colour;
+    }
+
+    /// All 256 colours, including the unused one for empty voxels.
+    pub fn colours(&self) -> &[[u8; 4]] {
+        &self.colours
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::uniform([255, 255, 255, 255])
+    }
+}
+
+/// How a material is shaded, taken from MagicaVoxel's material types.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaterialKind {
+    Diffuse,
+    Metal,
+    Glass,
+    Emissive,
+}
+
+/// Shading properties of a single material, besides its colour.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    pub kind: MaterialKind,
+    pub roughness: f32,
+    pub metalness: f32,
+    /// Index of refraction, only used by glass
+    pub ior: f32,
+    /// How much light glass lets through, 0 is opaque
+    pub transparency: f32,
+    /// Strength of emitted light, 0 doesn't emit anything
+    pub emission: f32,
+    /// Brightens the emission exponentially, 0 to 4 in MagicaVoxel
+    pub flux: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            kind: MaterialKind::Diffuse,
+            roughness: 1.0,
+            metalness: 0.0,
+            ior: 1.0,
+            transparency: 0.0,
+            emission: 0.0,
+            flux: 0.0,
+        }
+    }
+}
+
+impl Material {
+    /// Reads the properties of a MATL chunk. Missing or unknown properties keep their defaults.
+    pub fn from_vox(properties: &dot_vox::Dict) -> Self {
+        let property = |name: &str| properties.get(name).and_then(|value| value.parse::<f32>().ok());
+        let mut material = Self::default();
+        material.kind = match properties.get("_type").map(|kind| kind.as_str()) {
+            Some("_metal") => MaterialKind::Metal,
+            Some("_glass") => MaterialKind::Glass,
+            Some("_emit") => MaterialKind::Emissive,
+            _ => MaterialKind::Diffuse,
+        };
+        if let Some(rough) = property("_rough") { material.roughness = rough; }
+        if material.kind == MaterialKind::Metal {
+            material.metalness = property("_metal").unwrap_or(1.0);
+        }
+        if material.kind == MaterialKind::Glass {
+            //Older files only store the index of refraction minus 1
+            material.ior = property("_ri").or(property("_ior").map(|ior| ior + 1.0)).unwrap_or(1.0);
+            material.transparency = property("_trans").or(property("_alpha")).unwrap_or(0.5);
+        }
+        if material.kind == MaterialKind::Emissive {
+            material.emission = property("_emit").unwrap_or(1.0);
+            material.flux = property("_flux").unwrap_or(0.0);
+        }
+        material
+    }
+}
+
+/// Colours and properties of every material, attached to loaded voxel assets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialTable {
+    pub palette: Palette,
+    materials: Vec<Material>,
+}
+
+impl Default for MaterialTable {
+    fn default() -> Self {
+        Self {
+            palette: Palette::default(),
+            materials: vec![Material::default(); 256],
+        }
+    }
+}
+
+impl MaterialTable {
+    /// Reads the palette and MATL chunks of a .vox file.
+    pub fn from_vox(vox_data: &dot_vox::DotVoxData) -> Self {
+        let mut table = Self {
+            palette: Palette::from_vox(&vox_data.palette),
+            ..Self::default()
+        };
+        //MATL ids are palette indices as stored in the file, which is what materials use as well
+        for material in &vox_data.materials {
+            if material.id == 0 || material.id > 255 { continue; }
+            table.materials[material.id as usize] = Material::from_vox(&material.properties);
+        }
+        table
+    }
+
+    pub fn material(&self, material: u8) -> &Material {
+        &self.materials[material as usize]
+    }
+
+    pub fn set_material(&mut self, material: u8, properties: Material) {
+        self.materials[material as usize] = properties;
+    }
+
+    /// Two vec4s per material for the material table SSBO, see shaders/compute.glsl.
+    /// The first is the colour with transparency in alpha, the second (emission, metalness, roughness, ior).
+    pub fn gpu_data(&self) -> Vec<f32> {
+        let mut data = Vec::with_capacity(256 * 8);
+        for (colour, material) in self.palette.colours().iter().zip(&self.materials) {
+            data.push(colour[0] as f32 / 255.0);
+            data.push(colour[1] as f32 / 255.0);
+            data.push(colour[2] as f32 / 255.0);
+            data.push(material.transparency);
+            data.push(material.emission * 2.0f32.powf(material.flux));
+            data.push(material.metalness);
+            data.push(material.roughness);
+            data.push(material.ior);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colour(i: u8) -> dot_vox::Color {
+        dot_vox::Color { r: i, g: 255 - i, b: i / 2, a: 200 }
+    }
+
+    fn matl(id: u32, properties: &[(&str, &str)]) -> dot_vox::Material {
+        dot_vox::Material {
+            id,
+            properties: properties.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+        }
+    }
+
+    fn vox_data(palette: Vec<dot_vox::Color>, materials: Vec<dot_vox::Material>) -> dot_vox::DotVoxData {
+        dot_vox::DotVoxData {
+            version: 150,
+            models: Vec::new(),
+            palette,
+            materials,
+            scenes: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    //The file's first palette entry belongs to material 1, and the 256th entry has no material to go to
+    #[test]
+    fn palette_is_shifted_by_one() {
+        let palette = Palette::from_vox(&(0..=255).map(colour).collect::<Vec<_>>());
+        assert_eq!(palette.colour(0), [255, 255, 255, 255]);
+        for i in 1..=255u8 {
+            let expected = colour(i - 1);
+            assert_eq!(palette.colour(i), [expected.r, expected.g, expected.b, expected.a], "material {}", i);
+        }
+        assert_eq!(palette.colours().len(), 256);
+
+        //Short palettes leave the rest at the default
+        let palette = Palette::from_vox(&[colour(9), colour(10)]);
+        assert_eq!(palette.colour(2), [10, 245, 5, 200]);
+        assert_eq!(palette.colour(3), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn matl_properties_per_kind() {
+        let metal = Material::from_vox(&matl(1, &[("_type", "_metal"), ("_rough", "0.25"), ("_metal", "0.75")]).properties);
+        assert_eq!((metal.kind, metal.roughness, metal.metalness), (MaterialKind::Metal, 0.25, 0.75));
+        assert_eq!(Material::from_vox(&matl(1, &[("_type", "_metal")]).properties).metalness, 1.0);
+
+        //_ri is the index of refraction itself, _ior is stored minus 1, and _ri wins when both are there
+        let glass = Material::from_vox(&matl(1, &[("_type", "_glass"), ("_ior", "0.3"), ("_alpha", "0.4")]).properties);
+        assert_eq!(glass.kind, MaterialKind::Glass);
+        assert!((glass.ior - 1.3).abs() < 1e-6);
+        assert_eq!(glass.transparency, 0.4);
+        let glass = Material::from_vox(&matl(1, &[("_type", "_glass"), ("_ri", "1.5"), ("_ior", "0.3"), ("_trans", "0.1"), ("_alpha", "0.4")]).properties);
+        assert_eq!((glass.ior, glass.transparency), (1.5, 0.1));
+
+        let emit = Material::from_vox(&matl(1, &[("_type", "_emit"), ("_emit", "0.5"), ("_flux", "2")]).properties);
+        assert_eq!((emit.kind, emit.emission, emit.flux), (MaterialKind::Emissive, 0.5, 2.0));
+
+        //Properties of other kinds are ignored, unknown kinds and unparsable values keep the defaults
+        let diffuse = Material::from_vox(&matl(1, &[("_type", "_diffuse"), ("_metal", "1"), ("_emit", "1"), ("_ior", "0.5")]).properties);
+        assert_eq!(diffuse, Material::default());
+        assert_eq!(Material::from_vox(&matl(1, &[("_type", "_cloud")]).properties), Material::default());
+        assert_eq!(Material::from_vox(&matl(1, &[("_type", "_metal"), ("_rough", "rough")]).properties).roughness, 1.0);
+    }
+
+    #[test]
+    fn matl_ids_are_materials() {
+        let materials = vec![
+            matl(0, &[("_type", "_metal")]),
+            matl(1, &[("_type", "_glass")]),
+            matl(255, &[("_type", "_emit")]),
+            matl(256, &[("_type", "_metal")]),
+        ];
+        let table = MaterialTable::from_vox(&vox_data(vec![colour(1); 256], materials));
+        assert_eq!(table.material(0).kind, MaterialKind::Diffuse);
+        assert_eq!(table.material(1).kind, MaterialKind::Glass);
+        assert_eq!(table.material(2).kind, MaterialKind::Diffuse);
+        assert_eq!(table.material(255).kind, MaterialKind::Emissive);
+        assert_eq!(table.palette.colour(1), [1, 254, 0, 200]);
+    }
+
+    #[test]
+    fn gpu_data_layout() {
+        let mut table = MaterialTable::default();
+        table.palette.set_colour(3, [255, 0, 51, 255]);
+        table.set_material(3, Material {
+            kind: MaterialKind::Glass,
+            roughness: 0.5,
+            metalness: 0.25,
+            ior: 1.5,
+            transparency: 0.75,
+            emission: 0.5,
+            flux: 2.0,
+        });
+        let data = table.gpu_data();
+        assert_eq!(data.len(), 256 * 8);
+        assert_eq!(&data[3 * 8..4 * 8], &[1.0, 0.0, 0.2, 0.75, 2.0, 0.25, 0.5, 1.5]);
+        assert_eq!(&data[4 * 8..5 * 8], &[1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0]);
+    }
+}
@@ -1,5 +1,6 @@
 use voxel_dag::octree::Octree;
 use voxel_dag::voxel_data_structure;
+use voxel_dag::volume::Volume;
 
 use glam::*;
 
@@ -62,6 +63,13 @@ impl RenderMesh {
         })
     }
 
+    pub fn from_volume<C>(ctx: &mut C, volume: &Volume) -> Result<RenderMesh, TessError>
+    where
+        C: GraphicsContext,
+    {
+        Self::from_vox_data(ctx, volume.data(), volume.size())
+    }
+
     pub fn from_vox_data<C>(ctx: &mut C, vox_data: &[u8], data_size: (u32, u32, u32)) -> Result<RenderMesh, TessError>
     where
         C: GraphicsContext,
@@ -74,12 +82,13 @@ impl RenderMesh {
         // vertices.append(&mut get_cube_lines(Vec3::new(0.0, 1.0, 0.0)));
 
         let mut vox_count = 0;
+        let center = Vec3::new(data_size.0 as f32, data_size.1 as f32, data_size.2 as f32) * 0.5;
 
         for x in 0..data_size.0 {
             for y in 0..data_size.1 {
                 for z in 0..data_size.2 {
                     if vox_data[(x + y * data_size.0 + z * data_size.0 * data_size.1) as usize] > 0 {
-                        vertices.append(&mut get_cube_lines(Vec3::new(x as f32,y as f32,z as f32) - Vec3::new(63.0, 63.0, 63.0), 1.0));
+                        vertices.append(&mut get_cube_lines(Vec3::new(x as f32,y as f32,z as f32) - center, 1.0));
                         vox_count += 1;
                     }
                 }
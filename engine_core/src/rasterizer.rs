@@ -26,7 +26,7 @@ use luminance_derive::{Semantics, Vertex, UniformInterface};
 
 use crate::{
     camera::Camera,
-    shader::Shader,
+    shader::{Shader, RawShader},
     mesh::RenderMesh,
 };
 
@@ -87,6 +87,47 @@ pub fn draw_mesh(surface: &mut luminance_sdl2::SDL2Surface, gl: &glow::Context,
     );
 }
 
+/// Deepest DAG trace_dag can render, has to match MAX_DEPTH in shaders/compute.glsl.
+pub const MAX_TRACE_DEPTH: u32 = 16;
+
+/// Traces the DAG into the texture with the compute shader. The DAG should already be uploaded to SSBO binding 3.
+/// Both DAG and SymmetricDAG node buffers work.
+/// dag_offset is the world position of voxel (0, 0, 0), and dag_scale the world size of a voxel, like lod_cell_size for LODs.
+/// dag_leaf_levels is LeafFormat::leaf_levels of the DAG, symmetric DAGs always have voxel leaves (1).
+/// Returns an error for DAGs deeper than MAX_TRACE_DEPTH, the shader's stack can't hold them.
+pub fn trace_dag(shader: &RawShader, camera: &Camera, texture: u32, resolution: (i32, i32), dag_depth: u32, dag_leaf_levels: u32, dag_offset: Vec3, dag_scale: f32) -> Result<(), String> {
+    if dag_depth > MAX_TRACE_DEPTH {
+        return Err(format!("Can't trace a DAG of depth {}, the shader supports up to {}", dag_depth, MAX_TRACE_DEPTH));
+    }
+
+    let projection = camera.get_proj(resolution.0 as u32, resolution.1 as u32);
+    let view = camera.get_view();
+    let inv_view_proj = (projection * view).inverse().to_cols_array();
+
+    unsafe {
+        gl::UseProgram(shader.program);
+
+        let inv_loc = gl::GetUniformLocation(shader.program, b"inv_view_proj\0".as_ptr() as *const gl::types::GLchar);
+        gl::UniformMatrix4fv(inv_loc, 1, gl::FALSE, inv_view_proj.as_ptr());
+        let offset_loc = gl::GetUniformLocation(shader.program, b"dag_offset\0".as_ptr() as *const gl::types::GLchar);
+        gl::Uniform3f(offset_loc, dag_offset.x(), dag_offset.y(), dag_offset.z());
+        let scale_loc = gl::GetUniformLocation(shader.program, b"dag_scale\0".as_ptr() as *const gl::types::GLchar);
+        gl::Uniform1f(scale_loc, dag_scale);
+        let depth_loc = gl::GetUniformLocation(shader.program, b"dag_depth\0".as_ptr() as *const gl::types::GLchar);
+        gl::Uniform1ui(depth_loc, dag_depth);
+        let leaf_levels_loc = gl::GetUniformLocation(shader.program, b"dag_leaf_levels\0".as_ptr() as *const gl::types::GLchar);
+        gl::Uniform1ui(leaf_levels_loc, dag_leaf_levels);
+
+        gl::BindImageTexture(0, texture, 0, gl::FALSE, 0, gl::WRITE_ONLY, gl::RGBA32F);
+        gl::DispatchCompute((resolution.0 as u32 + 7) / 8, (resolution.1 as u32 + 7) / 8, 1); //Matches the 8x8 local size in compute.glsl
+        gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT | gl::TEXTURE_FETCH_BARRIER_BIT);
+
+        gl::UseProgram(0);
+    }
+
+    Ok(())
+}
+
 pub fn create_texture(resolution: (i32, i32)) -> u32 {
     let mut texture = 0;
 
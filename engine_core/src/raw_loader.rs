@@ -0,0 +1,152 @@
+/// Loads raw u8 volume dumps, with their size and placement in a json sidecar next to them.
+/// scan.raw is described by scan.json, which looks like
+/// { "size": [256, 128, 256], "translation": [0.0, 0.0, 0.0], "scale": 256.0 }
+/// translation and scale are optional, and mean the same as in PlacedVolume.
+
+use std::fs;
+use std::path::Path;
+
+use glam::*;
+use serde::Deserialize;
+
+use voxel_dag::volume::Volume;
+
+use crate::material::MaterialTable;
+use crate::vox_loader::{LoadError, PlacedVolume, voxel_count};
+
+/// Contents of the sidecar file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawHeader {
+    pub size: (u32, u32, u32),
+    #[serde(default)]
+    pub translation: [f32; 3],
+    /// Defaults to one world unit per voxel
+    #[serde(default)]
+    pub scale: Option<f32>,
+}
+
+/// Loads a raw dump, one material per voxel indexed like Volume, with the sidecar header next to it.
+pub fn load_raw(filename: &str) -> Result<PlacedVolume, LoadError> {
+    let header_path = Path::new(filename).with_extension("json");
+    let header = fs::read_to_string(&header_path)?;
+    let header: RawHeader = serde_json::from_str(&header).map_err(|err| LoadError::Parse(format!("Invalid header {}: {}", header_path.display(), err)))?;
+    load_raw_with_header(filename, &header)
+}
+
+/// Loads a raw dump, with the header given directly.
+pub fn load_raw_with_header(filename: &str, header: &RawHeader) -> Result<PlacedVolume, LoadError> {
+    //Checked before reading, so a wrong header can't make us read a huge file for nothing
+    let count = voxel_count(header.size)?;
+    let file_size = fs::metadata(filename)?.len();
+    if file_size != count as u64 {
+        return Err(LoadError::Parse(format!("{} is {} bytes, but its header describes {} voxels", filename, file_size, count)));
+    }
+    let data = fs::read(filename)?;
+    let volume = Volume::from_data(data, header.size)?;
+    let size = header.size;
+    Ok(PlacedVolume {
+        volume: volume,
+        translation: Vec3::new(header.translation[0], header.translation[1], header.translation[2]),
+        scale: header.scale.unwrap_or(size.0.max(size.1).max(size.2) as f32),
+        materials: MaterialTable::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    //A .raw file and its sidecar in the temp directory, removed again when dropped
+    struct TempRaw {
+        raw: PathBuf,
+        json: PathBuf,
+    }
+
+    impl TempRaw {
+        fn new(name: &str, data: &[u8], header: Option<&str>) -> Self {
+            let raw = std::env::temp_dir().join(format!("raw_loader_test_{}_{}.raw", std::process::id(), name));
+            let json = raw.with_extension("json");
+            fs::write(&raw, data).unwrap();
+            if let Some(header) = header {
+                fs::write(&json, header).unwrap();
+            }
+            Self { raw, json }
+        }
+
+        fn load(&self) -> Result<PlacedVolume, LoadError> {
+            load_raw(self.raw.to_str().unwrap())
+        }
+    }
+
+    impl Drop for TempRaw {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.raw);
+            let _ = fs::remove_file(&self.json);
+        }
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 11) as u8).collect()
+    }
+
+    #[test]
+    fn sidecar_places_the_volume() {
+        let raw = TempRaw::new("placed", &data(24), Some(r#"{ "size": [3, 2, 4], "translation": [1.0, -2.0, 0.5], "scale": 6.0 }"#));
+        let placed = raw.load().unwrap();
+        assert_eq!(placed.volume.size(), (3, 2, 4));
+        assert_eq!(placed.volume.data(), &data(24)[..]);
+        assert_eq!(placed.volume.get(2, 1, 0), Some(data(24)[5]));
+        assert_eq!(placed.translation, Vec3::new(1.0, -2.0, 0.5));
+        assert_eq!(placed.scale, 6.0);
+        assert_eq!(placed.materials, MaterialTable::default());
+    }
+
+    #[test]
+    fn placement_defaults_to_a_voxel_per_unit() {
+        let raw = TempRaw::new("defaults", &data(30), Some(r#"{ "size": [2, 5, 3] }"#));
+        let placed = raw.load().unwrap();
+        assert_eq!(placed.translation, Vec3::zero());
+        assert_eq!(placed.scale, 5.0);
+    }
+
+    #[test]
+    fn file_size_has_to_match_the_header() {
+        for &len in &[0, 23, 25, 48] {
+            let raw = TempRaw::new(&format!("size_{}", len), &data(len), Some(r#"{ "size": [3, 2, 4] }"#));
+            match raw.load() {
+                Err(LoadError::Parse(_)) => {},
+                other => panic!("{} bytes: {:?}", len, other.map(|placed| placed.volume.size())),
+            }
+        }
+    }
+
+    #[test]
+    fn bad_sidecars_are_errors() {
+        for (i, header) in [r#"{ "size": [3, 2] }"#, r#"{ "size": [3, 2, -4] }"#, r#"{ "translation": [0, 0, 0] }"#, "size 3 2 4", ""].iter().enumerate() {
+            let raw = TempRaw::new(&format!("header_{}", i), &data(24), Some(header));
+            match raw.load() {
+                Err(LoadError::Parse(_)) => {},
+                other => panic!("{:?}: {:?}", header, other.map(|placed| placed.volume.size())),
+            }
+        }
+
+        let raw = TempRaw::new("no_header", &data(24), None);
+        match raw.load() {
+            Err(LoadError::Io(_)) => {},
+            other => panic!("{:?}", other.map(|placed| placed.volume.size())),
+        }
+    }
+
+    #[test]
+    fn oversized_headers_are_too_large() {
+        for (i, header) in [r#"{ "size": [2048, 1024, 1024] }"#, r#"{ "size": [4294967295, 4294967295, 4294967295] }"#].iter().enumerate() {
+            let raw = TempRaw::new(&format!("too_large_{}", i), &data(24), Some(header));
+            match raw.load() {
+                Err(LoadError::TooLarge(_)) => {},
+                other => panic!("{:?}: {:?}", header, other.map(|placed| placed.volume.size())),
+            }
+        }
+    }
+}
@@ -50,9 +50,7 @@ impl RawShader {
             let c_source = c_source_tmp.as_c_str();
 
             let shader = gl::CreateShader(gl::COMPUTE_SHADER);
-            debug!("yep");
             gl::ShaderSource(shader, 1, &c_source.as_ptr(), std::ptr::null());
-            debug!("nope");
             gl::CompileShader(shader);
 
             let mut is_compiled = 0;
@@ -68,10 +66,29 @@ impl RawShader {
                 panic!("shader broken uwu");
             }
 
-            unimplemented!();
-            // Self {
-            //     program: 0
-            // }
+            let program = gl::CreateProgram();
+            gl::AttachShader(program, shader);
+            gl::LinkProgram(program);
+
+            let mut is_linked = 0;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut is_linked);
+            if is_linked == 0 {
+                let mut len = 0;
+                gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
+                let mut buffer: Vec<u8> = Vec::with_capacity(len as usize + 1);
+                buffer.extend([b' '].iter().cycle().take(len as usize));
+                let error: CString = CString::from_vec_unchecked(buffer);
+                gl::GetProgramInfoLog(program, len, std::ptr::null_mut(), error.as_ptr() as *mut gl::types::GLchar);
+                error!("{}", error.to_string_lossy().into_owned());
+                panic!("program broken uwu");
+            }
+
+            gl::DetachShader(program, shader);
+            gl::DeleteShader(shader);
+
+            Self {
+                program: program
+            }
         }
     }
 }
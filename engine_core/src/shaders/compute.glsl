@@ -3,10 +3,230 @@
 layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
 layout(rgba32f, binding = 0) uniform image2D img_output;
 
+//Packed DAG node buffer, see voxel_dag/src/dag.rs for the layout.
+//The top 3 bits of the child word mirror the node, see voxel_dag/src/symmetry.rs. Plain DAGs leave them at 0.
+//With brick leaves, the bottom nodes are 64-bit occupancy words instead, see voxel_dag/src/bricks.rs.
+layout(std430, binding = 3) readonly buffer DAG {
+    uint nodes[];
+};
+
+//Solid voxels below every node, and one material per solid voxel packed 4 to a uint.
+//See voxel_dag/src/attributes.rs for how they're indexed.
+layout(std430, binding = 4) readonly buffer VoxelCounts {
+    uint voxel_counts[];
+};
+layout(std430, binding = 5) readonly buffer Materials {
+    uint materials[];
+};
+
+//Two vec4s per material, the colour with transparency in alpha, then (emission, metalness, roughness, ior).
+//See engine_core/src/material.rs.
+layout(std430, binding = 6) readonly buffer MaterialTable {
+    vec4 material_table[];
+};
+
+uniform mat4 inv_view_proj;
+uniform vec3 dag_offset; //World position of voxel (0, 0, 0)
+uniform float dag_scale; //World size of a voxel, above 1 for LODs (see voxel_dag/src/lod.rs)
+uniform uint dag_depth;
+uniform uint dag_leaf_levels; //1 for 2x2x2 voxel leaves, 2 for 4x4x4 bricks
+
+//Deepest DAG we can trace, the stack lives in registers. rasterizer::trace_dag refuses deeper DAGs,
+//so keep MAX_TRACE_DEPTH in rasterizer.rs in sync with this
+#define MAX_DEPTH 16
+
+#define MIRROR_SHIFT 29u
+#define INDEX_MASK 0x1FFFFFFFu
+
+const vec3 BACKGROUND = vec3(127.0 / 255.0, 103.0 / 255.0, 181.0 / 255.0);
+const vec3 LIGHT_DIR = normalize(vec3(0.4, 1.0, 0.3));
+
+//Slab test against an axis aligned cube, returns (t_near, t_far)
+vec2 intersect(vec3 origin, vec3 inv_dir, vec3 cube_min, float size) {
+    vec3 t0 = (cube_min - origin) * inv_dir;
+    vec3 t1 = (cube_min + size - origin) * inv_dir;
+    vec3 t_min = min(t0, t1);
+    vec3 t_max = max(t0, t1);
+    return vec2(max(max(t_min.x, t_min.y), t_min.z), min(min(t_max.x, t_max.y), t_max.z));
+}
+
+//Moves bit j of a childmask to bit j ^ mirror
+uint mirror_mask(uint mask, uint mirror) {
+    if (mirror == 0u) { return mask; }
+    uint mirrored = 0u;
+    for (uint j = 0u; j < 8u; j++) {
+        if ((mask & (1u << j)) != 0u) { mirrored |= 1u << (j ^ mirror); }
+    }
+    return mirrored;
+}
+
+//Position of child j among the stored children of a node
+uint rank(uint childmask, uint j) {
+    return uint(bitCount(childmask & ((1u << j) - 1u)));
+}
+
+//Position of voxel j among the solid voxels of a brick
+uint brick_rank(uint lo, uint hi, uint j) {
+    if (j < 32u) { return rank(lo, j); }
+    return uint(bitCount(lo)) + rank(hi, j - 32u);
+}
+
+uint get_material(uint idx) {
+    return (materials[idx >> 2u] >> ((idx & 3u) * 8u)) & 0xFFu;
+}
+
+//There are no secondary rays yet, so glass only lets the background through and ior goes unused
+vec3 shade(uint material, vec3 normal, vec3 dir) {
+    vec4 colour = material_table[material * 2u];
+    vec4 properties = material_table[material * 2u + 1u];
+    float emission = properties.x;
+    float metalness = properties.y;
+    float roughness = properties.z;
+
+    float diffuse = max(dot(normal, LIGHT_DIR), 0.0);
+    float specular = pow(max(dot(reflect(-LIGHT_DIR, normal), -dir), 0.0), mix(64.0, 2.0, roughness)) * (1.0 - roughness);
+    vec3 lit = colour.rgb * (0.15 + 0.85 * diffuse) * (1.0 - metalness) + colour.rgb * specular * metalness + vec3(specular) * 0.04;
+    lit += colour.rgb * emission;
+    return mix(lit, BACKGROUND, colour.a);
+}
+
+//Normal of the face a ray enters a cube through
+vec3 entry_normal(vec3 origin, vec3 dir, vec3 inv_dir, vec3 cube_min, float size) {
+    vec3 t_min = min((cube_min - origin) * inv_dir, (cube_min + size - origin) * inv_dir);
+    vec3 axis = step(max(t_min.yzx, t_min.zxy), t_min);
+    return -sign(dir) * axis;
+}
+
+//Finds the closest solid voxel in a brick. Bits are Morton codes, so flipping every bit of an axis
+//visits the voxels front to back, same as child indices.
+bool trace_brick(uint idx, vec3 brick_pos, uint offset, vec3 origin, vec3 dir, vec3 inv_dir, uint octant_mask, out float t_hit, out vec3 normal, out uint material) {
+    uint lo = nodes[idx * 2u];
+    uint hi = nodes[idx * 2u + 1u];
+    uint flip = octant_mask | (octant_mask << 3u);
+    for (uint i = 0u; i < 64u; i++) {
+        uint j = i ^ flip;
+        uint word = j < 32u ? lo : hi;
+        if ((word & (1u << (j & 31u))) == 0u) { continue; }
+
+        vec3 voxel_pos = brick_pos + vec3(j & 1u, (j >> 1u) & 1u, (j >> 2u) & 1u) + vec3((j >> 3u) & 1u, (j >> 4u) & 1u, (j >> 5u) & 1u) * 2.0;
+        vec2 t = intersect(origin, inv_dir, voxel_pos, 1.0);
+        if (t.x > t.y || t.y < 0.0) { continue; }
+
+        normal = entry_normal(origin, dir, inv_dir, voxel_pos, 1.0);
+        t_hit = max(t.x, 0.0);
+        material = get_material(offset + brick_rank(lo, hi, j));
+        return true;
+    }
+    return false;
+}
+
+//Same traversal as voxel_dag/src/raycast.rs, but with an explicit stack instead of recursion.
+//Children are visited front to back, so the first leaf voxel we hit is the closest one.
+bool trace(vec3 origin, vec3 dir, out float t_hit, out vec3 normal, out uint material) {
+    vec3 inv_dir = 1.0 / dir;
+    uint octant_mask = (dir.x < 0.0 ? 1u : 0u) | (dir.y < 0.0 ? 2u : 0u) | (dir.z < 0.0 ? 4u : 0u);
+
+    vec2 root = intersect(origin, inv_dir, vec3(0.0), float(1u << dag_depth));
+    if (root.x > root.y || root.y < 0.0) { return false; }
+
+    //Level of the bricks, bricks are traced on their own when they're reached
+    bool bricks = dag_leaf_levels == 2u;
+    uint brick_level = dag_depth - dag_leaf_levels;
+    if (bricks && brick_level == 0u) {
+        return trace_brick(0u, vec3(0.0), 0u, origin, dir, inv_dir, octant_mask, t_hit, normal, material);
+    }
+
+    uint stack_node[MAX_DEPTH];
+    vec3 stack_pos[MAX_DEPTH];
+    uint stack_i[MAX_DEPTH];
+    uint stack_offset[MAX_DEPTH]; //Attribute offset of the node
+    uint stack_mirror[MAX_DEPTH]; //Actual mirror of the node, all the mirrors above it combined
+
+    int level = 0;
+    stack_node[0] = 0u;
+    stack_pos[0] = vec3(0.0);
+    stack_i[0] = 0u;
+    stack_offset[0] = 0u;
+    stack_mirror[0] = nodes[1] >> MIRROR_SHIFT;
+
+    while (level >= 0) {
+        if (stack_i[level] >= 8u) {
+            level--;
+            continue;
+        }
+
+        uint node = stack_node[level];
+        uint mirror = stack_mirror[level];
+        uint j = stack_i[level] ^ octant_mask;
+        stack_i[level]++;
+
+        //Child j of a mirrored node is its stored child j ^ mirror
+        uint stored_mask = nodes[node * 2u];
+        uint childmask = mirror_mask(stored_mask, mirror);
+        uint child = nodes[node * 2u + 1u] & INDEX_MASK;
+        if ((childmask & (1u << j)) == 0u) { continue; }
+
+        float half_size = float(1u << (dag_depth - uint(level) - 1u));
+        vec3 child_pos = stack_pos[level] + vec3(j & 1u, (j >> 1u) & 1u, (j >> 2u) & 1u) * half_size;
+        vec2 t = intersect(origin, inv_dir, child_pos, half_size);
+        if (t.x > t.y || t.y < 0.0) { continue; }
+
+        if (child == 0u) {
+            //Leaf node, so the children are the voxels themselves
+            normal = entry_normal(origin, dir, inv_dir, child_pos, half_size);
+            t_hit = max(t.x, 0.0);
+            material = get_material(stack_offset[level] + rank(childmask, j));
+            return true;
+        }
+
+        uint offset = stack_offset[level];
+        for (uint k = 0u; k < j; k++) {
+            if ((childmask & (1u << k)) != 0u) {
+                offset += voxel_counts[child + rank(stored_mask, k ^ mirror)];
+            }
+        }
+        uint child_idx = child + rank(stored_mask, j ^ mirror);
+        if (bricks && uint(level) + 1u == brick_level) {
+            if (trace_brick(child_idx, child_pos, offset, origin, dir, inv_dir, octant_mask, t_hit, normal, material)) { return true; }
+            continue;
+        }
+
+        if (level + 1 >= MAX_DEPTH) { continue; }
+        level++;
+        stack_node[level] = child_idx;
+        stack_pos[level] = child_pos;
+        stack_i[level] = 0u;
+        stack_offset[level] = offset;
+        stack_mirror[level] = mirror ^ (nodes[child_idx * 2u + 1u] >> MIRROR_SHIFT);
+    }
+
+    return false;
+}
+
 void main() {
-    ivec3 pixel_coords = ivec3(gl_GlobalInvocationID.xyz);
+    ivec2 pixel_coords = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 resolution = imageSize(img_output);
+    if (pixel_coords.x >= resolution.x || pixel_coords.y >= resolution.y) { return; }
+
+    //Unproject the pixel onto the near and far plane to get a world space ray
+    vec2 ndc = (vec2(pixel_coords) + 0.5) / vec2(resolution) * 2.0 - 1.0;
+    vec4 near = inv_view_proj * vec4(ndc, -1.0, 1.0);
+    vec4 far = inv_view_proj * vec4(ndc, 1.0, 1.0);
+    near /= near.w;
+    far /= far.w;
+
+    vec3 origin = (near.xyz - dag_offset) / dag_scale;
+    vec3 dir = normalize(far.xyz - near.xyz);
+    //Avoid dividing by zero in the slab tests
+    dir = mix(dir, vec3(1e-7), equal(dir, vec3(0.0)));
 
-    vec2 uv = pixel_coords.xy / vec2(1280.0, 720.0);
+    float t_hit;
+    vec3 normal;
+    uint material;
+    vec4 result = vec4(BACKGROUND, -1.0); //Depth of -1 means the ray missed
+    if (trace(origin, dir, t_hit, normal, material)) {
+        result = vec4(shade(material, normal, dir), t_hit * dag_scale);
+    }
 
-    imageStore(img_output, pixel_coords.xy, vec4(uv, 0.0, 1.0));
+    imageStore(img_output, pixel_coords, result);
 }
@@ -1,23 +1,622 @@
 /// If this project goes anywhere, this should definitely be moved to a VFS or something
 
+use std::fmt;
+use std::io;
+
 use glam::*;
 
-pub fn load_vox(filename: &str) -> Box<[u8; 126*126*126]> { //1d array of voxels
-    use dot_vox::load;
-    let vox_data = load(filename).expect("Failed to open file!"); //No error checking because chad testing code
-    //Lets only use the first model for now
-    let model = &vox_data.models[0];
+use dot_vox::SceneNode;
+
+use voxel_dag::volume::Volume;
+
+use crate::material::MaterialTable;
+
+/// Everything that can go wrong while loading a voxel file.
+#[derive(Debug)]
+pub enum LoadError {
+    /// Reading the file failed.
+    Io(io::Error),
+    /// The file isn't valid for its format.
+    Parse(String),
+    /// The file doesn't contain a single model.
+    NoModels,
+    /// A voxel lies outside of the size its model says it has.
+    VoxelOutOfBounds {
+        x: u8,
+        y: u8,
+        z: u8,
+    },
+    /// The scene graph references missing nodes or models, or has an invalid transform.
+    Scene(String),
+    /// The loaded voxels don't form a valid volume.
+    Volume(voxel_dag::Error),
+    /// The file describes a volume with more than MAX_VOXELS voxels.
+    TooLarge((u32, u32, u32)),
+    /// The file extension doesn't belong to any supported format.
+    UnknownFormat(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "IO error: {}", err),
+            LoadError::Parse(msg) => write!(f, "Failed to parse voxel file: {}", msg),
+            LoadError::NoModels => write!(f, "Voxel file contains no models"),
+            LoadError::VoxelOutOfBounds { x, y, z } => write!(f, "Voxel ({}, {}, {}) lies outside of its model", x, y, z),
+            LoadError::Scene(msg) => write!(f, "Invalid scene graph: {}", msg),
+            LoadError::Volume(err) => write!(f, "Invalid volume: {}", err),
+            LoadError::TooLarge(size) => write!(f, "Volume of {:?} voxels is larger than the limit of {} voxels", size, MAX_VOXELS),
+            LoadError::UnknownFormat(filename) => write!(f, "Unknown voxel file format: {}", filename),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::Volume(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<voxel_dag::Error> for LoadError {
+    fn from(err: voxel_dag::Error) -> Self {
+        LoadError::Volume(err)
+    }
+}
+
+/// Largest volume the loaders accept. Volumes are dense, a byte per voxel, so this keeps a bad header
+/// from allocating more than a gigabyte.
+pub const MAX_VOXELS: u64 = 1 << 30;
+
+/// Amount of voxels in a volume of the given size, checked against MAX_VOXELS.
+pub fn voxel_count(size: (u32, u32, u32)) -> Result<usize, LoadError> {
+    let count = (size.0 as u64).checked_mul(size.1 as u64).and_then(|count| count.checked_mul(size.2 as u64));
+    match count {
+        Some(count) if count <= MAX_VOXELS => Ok(count as usize),
+        _ => Err(LoadError::TooLarge(size)),
+    }
+}
+
+/// A volume together with where it was placed in the world, for formats that store that (see binvox_loader and raw_loader).
+/// World positions are translation + (voxel + 0.5) * voxel_size().
+pub struct PlacedVolume {
+    pub volume: Volume,
+    /// World position of the corner of voxel (0, 0, 0)
+    pub translation: Vec3,
+    /// World size of the longest axis of the volume, as .binvox stores it
+    pub scale: f32,
+    /// Materials of the voxels, the default table for formats that don't store any
+    pub materials: MaterialTable,
+}
+
+impl PlacedVolume {
+    /// World size of a single voxel.
+    pub fn voxel_size(&self) -> f32 {
+        let size = self.volume.size();
+        self.scale / size.0.max(size.1).max(size.2).max(1) as f32
+    }
+}
+
+//MagicaVoxel is z-up, so its y and z axes are swapped to match the engine
+fn model_to_volume(model: &dot_vox::Model) -> Result<Volume, LoadError> {
+    let size = (model.size.x, model.size.z, model.size.y);
+    voxel_count(size)?;
+    let mut volume = Volume::new(size);
+    for voxel in &model.voxels {
+        let pos = (voxel.x as u32, voxel.z as u32, voxel.y as u32);
+        if pos.0 >= size.0 || pos.1 >= size.1 || pos.2 >= size.2 {
+            return Err(LoadError::VoxelOutOfBounds { x: voxel.x, y: voxel.y, z: voxel.z });
+        }
+        //dot_vox stores palette indices starting at 0, but material 0 means empty
+        volume.set(pos.0, pos.1, pos.2, voxel.i.saturating_add(1));
+    }
+    Ok(volume)
+}
+
+/// A model placed in the world by the scene graph, with its rotation already applied.
+pub struct Instance {
+    pub volume: Volume,
+    /// World position of voxel (0, 0, 0) of the volume
+    pub position: (i32, i32, i32),
+    /// Name given to the object in MagicaVoxel, if any
+    pub name: Option<String>,
+}
+
+/// Every visible model of a .vox file, in engine coordinates.
+pub struct Scene {
+    pub instances: Vec<Instance>,
+    /// Palette and material properties shared by all instances
+    pub materials: MaterialTable,
+}
+
+impl Scene {
+    /// Smallest and largest world positions covered by any instance, the max is exclusive.
+    pub fn bounds(&self) -> Option<((i32, i32, i32), (i32, i32, i32))> {
+        let mut instances = self.instances.iter();
+        let first = instances.next()?;
+        let mut min = first.position;
+        let mut max = instance_max(first);
+        for instance in instances {
+            let instance_max = instance_max(instance);
+            min = (min.0.min(instance.position.0), min.1.min(instance.position.1), min.2.min(instance.position.2));
+            max = (max.0.max(instance_max.0), max.1.max(instance_max.1), max.2.max(instance_max.2));
+        }
+        Some((min, max))
+    }
+
+    /// Pastes every instance into a single volume, later instances overwrite earlier ones where they overlap.
+    /// Returns the volume and the world position of its voxel (0, 0, 0).
+    /// Models can be placed far apart, so the combined extents are checked against MAX_VOXELS first.
+    pub fn assemble(&self) -> Result<(Volume, (i32, i32, i32)), LoadError> {
+        let (min, max) = match self.bounds() {
+            Some(bounds) => bounds,
+            None => return Ok((Volume::new((0, 0, 0)), (0, 0, 0))),
+        };
+        let extent = |min: i32, max: i32| (max as i64 - min as i64).min(u32::MAX as i64) as u32;
+        let size = (extent(min.0, max.0), extent(min.1, max.1), extent(min.2, max.2));
+        voxel_count(size)?;
+        let mut volume = Volume::new(size);
+        for instance in &self.instances {
+            let offset = (
+                (instance.position.0 - min.0) as u32,
+                (instance.position.1 - min.1) as u32,
+                (instance.position.2 - min.2) as u32,
+            );
+            let size = instance.volume.size();
+            for z in 0..size.2 {
+                for y in 0..size.1 {
+                    for x in 0..size.0 {
+                        if let Some(material) = instance.volume.get(x, y, z) {
+                            volume.set(x + offset.0, y + offset.1, z + offset.2, material);
+                        }
+                    }
+                }
+            }
+        }
+        Ok((volume, min))
+    }
+}
+
+fn instance_max(instance: &Instance) -> (i32, i32, i32) {
+    let size = instance.volume.size();
+    (instance.position.0 + size.0 as i32, instance.position.1 + size.1 as i32, instance.position.2 + size.2 as i32)
+}
+
+/// Loads every model of a MagicaVoxel file, placed by its scene graph.
+pub fn load_vox_scene(filename: &str) -> Result<Scene, LoadError> {
+    let vox_data = dot_vox::load(filename).map_err(|err| LoadError::Parse(err.to_string()))?;
+    scene_from_vox(&vox_data)
+}
+
+/// Same as load_vox_scene, for a .vox file that's already in memory.
+pub fn parse_vox_scene(bytes: &[u8]) -> Result<Scene, LoadError> {
+    let vox_data = dot_vox::load_bytes(bytes).map_err(|err| LoadError::Parse(err.to_string()))?;
+    scene_from_vox(&vox_data)
+}
+
+/// Places every model of an already parsed .vox file. Files without a scene graph get every model at the origin.
+pub fn scene_from_vox(vox_data: &dot_vox::DotVoxData) -> Result<Scene, LoadError> {
+    if vox_data.models.is_empty() {
+        return Err(LoadError::NoModels);
+    }
+
+    let mut instances = Vec::new();
+    if vox_data.scenes.is_empty() {
+        for model in &vox_data.models {
+            instances.push(Instance {
+                volume: model_to_volume(model)?,
+                position: (0, 0, 0),
+                name: None,
+            });
+        }
+    } else {
+        let mut loader = SceneLoader {
+            vox_data,
+            instances: &mut instances,
+        };
+        loader.node(0, Transform::IDENTITY, None, 0)?;
+    }
+    Ok(Scene {
+        instances: instances,
+        materials: MaterialTable::from_vox(vox_data),
+    })
+}
+
+//MagicaVoxel transforms, in MagicaVoxel's z-up coordinates.
+//Rotations only ever permute and flip axes, so every row has a single axis and sign.
+#[derive(Clone, Copy)]
+struct Transform {
+    rows: [(usize, i32); 3], //Axis of the source that ends up on every axis of the result, and its sign
+    translation: [i32; 3],
+}
+
+impl Transform {
+    const IDENTITY: Transform = Transform {
+        rows: [(0, 1), (1, 1), (2, 1)],
+        translation: [0, 0, 0],
+    };
+
+    //Reads the _t and _r attributes of a frame
+    fn from_frame(frame: &dot_vox::Frame) -> Result<Self, LoadError> {
+        let mut transform = Self::IDENTITY;
+        if let Some(t) = frame.attributes.get("_t") {
+            let values: Vec<i32> = t.split_whitespace().map(|v| v.parse()).collect::<Result<_, _>>()
+                .map_err(|_| LoadError::Scene(format!("Invalid translation {:?}", t)))?;
+            if values.len() != 3 {
+                return Err(LoadError::Scene(format!("Invalid translation {:?}", t)));
+            }
+            transform.translation = [values[0], values[1], values[2]];
+        }
+        if let Some(r) = frame.attributes.get("_r") {
+            let r: u8 = r.parse().map_err(|_| LoadError::Scene(format!("Invalid rotation {:?}", r)))?;
+            //Bits 0-1 and 2-3 are the axes of the first two rows, bits 4-6 flip the rows
+            let first = (r & 3) as usize;
+            let second = ((r >> 2) & 3) as usize;
+            if first > 2 || second > 2 || first == second {
+                return Err(LoadError::Scene(format!("Invalid rotation {}", r)));
+            }
+            let axes = [first, second, 3 - first - second];
+            for row in 0..3 {
+                let sign = if r & (1 << (4 + row)) == 0 { 1 } else { -1 };
+                transform.rows[row] = (axes[row], sign);
+            }
+        }
+        Ok(transform)
+    }
+
+    //Translations come straight from the file, so positions can end up outside of i32
+    fn apply(&self, pos: [i32; 3]) -> Result<[i32; 3], LoadError> {
+        let mut result = self.translation;
+        for row in 0..3 {
+            let (axis, sign) = self.rows[row];
+            result[row] = pos[axis].checked_mul(sign).and_then(|moved| moved.checked_add(result[row])).ok_or_else(out_of_range)?;
+        }
+        Ok(result)
+    }
+
+    //This transform, applied after child
+    fn then(&self, child: &Transform) -> Result<Self, LoadError> {
+        let mut rows = [(0, 1); 3];
+        for row in 0..3 {
+            let (axis, sign) = self.rows[row];
+            rows[row] = (child.rows[axis].0, sign * child.rows[axis].1);
+        }
+        Ok(Transform {
+            rows: rows,
+            translation: self.apply(child.translation)?,
+        })
+    }
+}
+
+struct SceneLoader<'a> {
+    vox_data: &'a dot_vox::DotVoxData,
+    instances: &'a mut Vec<Instance>,
+}
+
+impl<'a> SceneLoader<'a> {
+    //depth guards against cycles, a valid graph can't be deeper than it has nodes
+    fn node(&mut self, idx: u32, transform: Transform, name: Option<&str>, depth: usize) -> Result<(), LoadError> {
+        let scenes = &self.vox_data.scenes;
+        if depth > scenes.len() {
+            return Err(LoadError::Scene("The scene graph contains a cycle".to_string()));
+        }
+        let node = scenes.get(idx as usize).ok_or_else(|| LoadError::Scene(format!("Node {} doesn't exist", idx)))?;
+        if node_attributes(node).get("_hidden").map_or(false, |hidden| hidden == "1") {
+            return Ok(());
+        }
+
+        match node {
+            SceneNode::Transform { attributes, frames, child, .. } => {
+                //Animated transforms are placed at their first frame
+                let local = match frames.first() {
+                    Some(frame) => Transform::from_frame(frame)?,
+                    None => Transform::IDENTITY,
+                };
+                let name = attributes.get("_name").map(|name| name.as_str()).or(name);
+                self.node(*child, transform.then(&local)?, name, depth + 1)
+            },
+            SceneNode::Group { children, .. } => {
+                for child in children {
+                    self.node(*child, transform, name, depth + 1)?;
+                }
+                Ok(())
+            },
+            SceneNode::Shape { models, .. } => {
+                //Shapes only have more than one model when they're animated
+                if let Some(shape_model) = models.first() {
+                    let model = self.vox_data.models.get(shape_model.model_id as usize)
+                        .ok_or_else(|| LoadError::Scene(format!("Model {} doesn't exist", shape_model.model_id)))?;
+                    let instance = place_model(model, &transform, name)?;
+                    self.instances.push(instance);
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+fn node_attributes(node: &SceneNode) -> &dot_vox::Dict {
+    match node {
+        SceneNode::Transform { attributes, .. } => attributes,
+        SceneNode::Group { attributes, .. } => attributes,
+        SceneNode::Shape { attributes, .. } => attributes,
+    }
+}
+
+//Models rotate around their center voxel, at size / 2. The rotation is baked into the volume,
+//and the result is converted to engine coordinates the same way model_to_volume does.
+fn place_model(model: &dot_vox::Model, transform: &Transform, name: Option<&str>) -> Result<Instance, LoadError> {
+    //Also keeps every size well inside of i32
+    voxel_count((model.size.x, model.size.y, model.size.z))?;
+    let size = [model.size.x as i32, model.size.y as i32, model.size.z as i32];
+    let pivot = [size[0] / 2, size[1] / 2, size[2] / 2];
 
-    //Creates an array first on the stack, then copies it to the heap. Yikes
-    // let mut voxels: Box<[u8; 126*126*126]> = Box::new([0; 126*126*126]);
-    //Much better :)
-    let mut voxels: Box<[u8; 126*126*126]> = box[0; 126*126*126];
+    //Both corners of the placed model have to fit, so the voxels in between do too
+    let mut min = [0; 3];
+    let mut placed_size = [0; 3];
+    for row in 0..3 {
+        let (axis, sign) = transform.rows[row];
+        let low = if sign > 0 { -pivot[axis] } else { pivot[axis] - (size[axis] - 1) };
+        min[row] = transform.translation[row].checked_add(low).ok_or_else(out_of_range)?;
+        min[row].checked_add(size[axis]).ok_or_else(out_of_range)?;
+        placed_size[row] = size[axis] as u32;
+    }
 
+    let mut volume = Volume::new((placed_size[0], placed_size[2], placed_size[1]));
     for voxel in &model.voxels {
-        // let pos = Vec3A::new(voxel.x as f32, voxel.y as f32, voxel.z as f32);
-        // let mat_id = voxel.i;
-        voxels[voxel.x as usize + voxel.z as usize * 126 + voxel.y as usize * 126 * 126] = voxel.i;
+        let pos = [voxel.x as i32, voxel.y as i32, voxel.z as i32];
+        if pos[0] >= size[0] || pos[1] >= size[1] || pos[2] >= size[2] {
+            return Err(LoadError::VoxelOutOfBounds { x: voxel.x, y: voxel.y, z: voxel.z });
+        }
+        let world = transform.apply([pos[0] - pivot[0], pos[1] - pivot[1], pos[2] - pivot[2]])?;
+        let local = [(world[0] - min[0]) as u32, (world[1] - min[1]) as u32, (world[2] - min[2]) as u32];
+        volume.set(local[0], local[2], local[1], voxel.i.saturating_add(1));
+    }
+
+    Ok(Instance {
+        volume: volume,
+        position: (min[0], min[2], min[1]),
+        name: name.map(|name| name.to_string()),
+    })
+}
+
+fn out_of_range() -> LoadError {
+    LoadError::Scene("A model is placed outside of the range of i32 positions".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(size: (u32, u32, u32), voxels: &[(u8, u8, u8, u8)]) -> dot_vox::Model {
+        dot_vox::Model {
+            size: dot_vox::Size { x: size.0, y: size.1, z: size.2 },
+            voxels: voxels.iter().map(|&(x, y, z, i)| dot_vox::Voxel { x, y, z, i }).collect(),
+        }
+    }
+
+    //Sizes that aren't cubes and aren't the old fixed 126, with voxels on the far corners
+    #[test]
+    fn models_keep_their_size_with_y_and_z_swapped() {
+        for &size in &[(2, 7, 3), (200, 1, 130), (256, 256, 2)] {
+            let far = ((size.0 - 1) as u8, (size.1 - 1) as u8, (size.2 - 1) as u8);
+            let voxels = [(0, 0, 0, 4), (far.0, far.1, far.2, 9), (far.0, 0, far.2, 254), (1, far.1, 0, 0)];
+            let volume = model_to_volume(&model(size, &voxels)).unwrap();
+            assert_eq!(volume.size(), (size.0, size.2, size.1));
+
+            //Palette indices are shifted up by one, so 0 stays empty and 254 becomes the last material
+            let mut expected = Volume::new((size.0, size.2, size.1));
+            for &(x, y, z, i) in &voxels {
+                expected.set(x as u32, z as u32, y as u32, i + 1);
+            }
+            assert_eq!(volume.data(), expected.data(), "{:?}", size);
+            assert_eq!(volume.get(far.0 as u32, far.2 as u32, 0), Some(255));
+        }
+    }
+
+    #[test]
+    fn voxels_outside_of_the_model_are_errors() {
+        for &(x, y, z) in &[(3, 0, 0), (0, 2, 0), (0, 0, 5), (255, 255, 255)] {
+            match model_to_volume(&model((3, 2, 5), &[(0, 0, 0, 1), (x, y, z, 1)])) {
+                Err(LoadError::VoxelOutOfBounds { x: ex, y: ey, z: ez }) => assert_eq!((ex, ey, ez), (x, y, z)),
+                other => panic!("{:?}: {:?}", (x, y, z), other.map(|volume| volume.size())),
+            }
+        }
     }
 
-    voxels
+    #[test]
+    fn oversized_models_are_errors() {
+        //A bad SIZE chunk must not allocate anything
+        for &size in &[(u32::MAX, u32::MAX, u32::MAX), (2048, 2048, 257), (u32::MAX, 1, 2)] {
+            match model_to_volume(&model(size, &[])) {
+                Err(LoadError::TooLarge(too_large)) => assert_eq!(too_large, (size.0, size.2, size.1)),
+                other => panic!("{:?}: {:?}", size, other.map(|volume| volume.size())),
+            }
+        }
+        assert_eq!(voxel_count((1024, 1024, 1024)).unwrap(), 1 << 30);
+        assert!(voxel_count((1024, 1024, 1025)).is_err());
+        assert_eq!(voxel_count((0, u32::MAX, u32::MAX)).unwrap(), 0);
+    }
+
+    fn dict(pairs: &[(&str, &str)]) -> dot_vox::Dict {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn transform(t: &str, r: Option<u8>, child: u32, extra: &[(&str, &str)]) -> SceneNode {
+        let r = r.map(|r| r.to_string());
+        let mut frame = vec![("_t", t)];
+        if let Some(r) = &r { frame.push(("_r", r)); }
+        SceneNode::Transform { attributes: dict(extra), frames: vec![dot_vox::Frame { attributes: dict(&frame) }], child, layer_id: 0 }
+    }
+
+    fn shape(model_id: u32) -> SceneNode {
+        SceneNode::Shape { attributes: dict(&[]), models: vec![dot_vox::ShapeModel { model_id, attributes: dict(&[]) }] }
+    }
+
+    fn vox_data(models: Vec<dot_vox::Model>, scenes: Vec<SceneNode>) -> dot_vox::DotVoxData {
+        dot_vox::DotVoxData {
+            version: 150,
+            models,
+            palette: Vec::new(),
+            materials: Vec::new(),
+            scenes,
+            layers: Vec::new(),
+        }
+    }
+
+    //Every valid _r, including the mirrored ones: the axis of the first two rows, and a flip per row
+    fn rotations() -> Vec<u8> {
+        let mut rotations = Vec::new();
+        for first in 0..3u8 {
+            for second in 0..3u8 {
+                if first == second { continue; }
+                for flips in 0..8u8 {
+                    rotations.push(first | second << 2 | flips << 4);
+                }
+            }
+        }
+        rotations
+    }
+
+    //The rotation as a matrix, straight from MagicaVoxel's description of _r
+    fn matrix(r: u8) -> [[i32; 3]; 3] {
+        let first = (r & 3) as usize;
+        let second = ((r >> 2) & 3) as usize;
+        let mut matrix = [[0; 3]; 3];
+        for (row, &axis) in [first, second, 3 - first - second].iter().enumerate() {
+            matrix[row][axis] = if r & (1 << (4 + row)) != 0 { -1 } else { 1 };
+        }
+        matrix
+    }
+
+    fn transformed(matrix: &[[i32; 3]; 3], translation: [i32; 3], pos: [i32; 3]) -> [i32; 3] {
+        let mut result = translation;
+        for row in 0..3 {
+            for column in 0..3 {
+                result[row] += matrix[row][column] * pos[column];
+            }
+        }
+        result
+    }
+
+    //Checks the assembled volume voxel by voxel against world positions in MagicaVoxel coordinates
+    fn assert_assembled(scene: &Scene, expected: &[([i32; 3], u8)], what: &str) {
+        let (volume, origin) = scene.assemble().unwrap();
+        let size = volume.size();
+        let mut solid = 0;
+        for z in 0..size.2 {
+            for y in 0..size.1 {
+                for x in 0..size.0 {
+                    if let Some(material) = volume.get(x, y, z) {
+                        solid += 1;
+                        //Engine y and z are MagicaVoxel's z and y
+                        let world = [x as i32 + origin.0, z as i32 + origin.2, y as i32 + origin.1];
+                        assert!(expected.contains(&(world, material)), "{}: unexpected {} at {:?}", what, material, world);
+                    }
+                }
+            }
+        }
+        assert_eq!(solid, expected.len(), "{}", what);
+    }
+
+    #[test]
+    fn multi_model_scenes_are_rotated_and_translated() {
+        let first = model((3, 2, 4), &[(0, 0, 0, 0), (2, 1, 3, 5), (1, 0, 2, 9)]);
+        let second = model((1, 1, 1), &[(0, 0, 0, 3)]);
+        let rotations = rotations();
+        assert_eq!(rotations.len(), 48);
+        for &outer in &rotations {
+            for &inner in &[4, 4 | 16, 1 | 2 << 2, 2 | 32 | 64, 2 | 1 << 2 | 16 | 32 | 64] {
+                //A rotated group with a rotated model, a translated model and a hidden one
+                let scenes = vec![
+                    transform("5 -3 2", Some(outer), 1, &[]),
+                    SceneNode::Group { attributes: dict(&[]), children: vec![2, 4, 6] },
+                    transform("-1 7 0", Some(inner), 3, &[("_name", "thing")]),
+                    shape(0),
+                    transform("0 0 20", None, 5, &[]),
+                    shape(1),
+                    transform("9 9 9", None, 5, &[("_hidden", "1")]),
+                ];
+                let scene = scene_from_vox(&vox_data(vec![first.clone(), second.clone()], scenes)).unwrap();
+                assert_eq!(scene.instances.len(), 2);
+                assert_eq!(scene.instances[0].name.as_deref(), Some("thing"));
+                assert_eq!(scene.instances[1].name, None);
+
+                //Models rotate around their center voxel, size / 2 rounded down
+                let mut expected = Vec::new();
+                for voxel in &first.voxels {
+                    let local = [voxel.x as i32 - 1, voxel.y as i32 - 1, voxel.z as i32 - 2];
+                    let in_group = transformed(&matrix(inner), [-1, 7, 0], local);
+                    expected.push((transformed(&matrix(outer), [5, -3, 2], in_group), voxel.i + 1));
+                }
+                expected.push((transformed(&matrix(outer), [5, -3, 2], [0, 0, 20]), 4));
+                assert_assembled(&scene, &expected, &format!("_r {} and {}", outer, inner));
+            }
+        }
+    }
+
+    #[test]
+    fn models_without_a_scene_graph_overlap_at_the_origin() {
+        let scene = scene_from_vox(&vox_data(vec![model((3, 2, 4), &[(2, 1, 3, 5), (0, 0, 0, 1)]), model((1, 1, 1), &[(0, 0, 0, 7)])], Vec::new())).unwrap();
+        let (volume, origin) = scene.assemble().unwrap();
+        assert_eq!(origin, (0, 0, 0));
+        assert_eq!(volume.size(), (3, 4, 2));
+        assert_eq!(volume.get(2, 3, 1), Some(6));
+        //Later models win
+        assert_eq!(volume.get(0, 0, 0), Some(8));
+
+        assert!(matches!(scene_from_vox(&vox_data(Vec::new(), Vec::new())), Err(LoadError::NoModels)));
+    }
+
+    #[test]
+    fn broken_scene_graphs_are_errors() {
+        let models = vec![model((3, 3, 3), &[(1, 1, 1, 0)])];
+        let broken = vec![
+            ("invalid translation", vec![transform("1 2", None, 1, &[]), shape(0)]),
+            ("translation past i32", vec![transform("2147483648 0 0", None, 1, &[]), shape(0)]),
+            ("invalid rotation", vec![transform("0 0 0", Some(3), 1, &[]), shape(0)]),
+            ("repeated rotation axis", vec![transform("0 0 0", Some(1 | 1 << 2), 1, &[]), shape(0)]),
+            ("missing node", vec![transform("0 0 0", None, 5, &[])]),
+            ("missing model", vec![transform("0 0 0", None, 1, &[]), shape(3)]),
+            ("cycle", vec![transform("0 0 0", None, 1, &[]), SceneNode::Group { attributes: dict(&[]), children: vec![0] }]),
+            //Nested translations that only overflow once they're added up
+            ("nested overflow", vec![transform("2000000000 0 0", None, 1, &[]), transform("2000000000 0 0", None, 2, &[]), shape(0)]),
+            ("negative overflow", vec![transform("0 -2000000000 0", None, 1, &[]), transform("0 -2000000000 0", None, 2, &[]), shape(0)]),
+            //The model itself fits, but its far corner doesn't
+            ("corner overflow", vec![transform("0 0 2147483647", None, 1, &[]), shape(0)]),
+            ("flipped corner overflow", vec![transform("-2147483648 0 0", Some(4 | 16), 1, &[]), shape(0)]),
+        ];
+        for (what, scenes) in broken {
+            match scene_from_vox(&vox_data(models.clone(), scenes)) {
+                Err(LoadError::Scene(_)) => {},
+                Err(err) => panic!("{}: wrong error {}", what, err),
+                Ok(_) => panic!("{}: scene was accepted", what),
+            }
+        }
+    }
+
+    //Single voxels placed far apart must not turn into a huge volume
+    #[test]
+    fn scenes_spread_out_too_far_are_too_large() {
+        let models = vec![model((1, 1, 1), &[(0, 0, 0, 0)])];
+        for &(a, b) in &[("-1000000000 0 0", "1000000000 0 0"), ("0 -2147483648 0", "0 2147483646 0"), ("0 0 0", "1024 1024 1024")] {
+            let scenes = vec![
+                SceneNode::Group { attributes: dict(&[]), children: vec![1, 2] },
+                transform(a, None, 3, &[]),
+                transform(b, None, 3, &[]),
+                shape(0),
+            ];
+            let scene = scene_from_vox(&vox_data(models.clone(), scenes)).unwrap();
+            assert!(matches!(scene.assemble(), Err(LoadError::TooLarge(_))), "{} and {}", a, b);
+        }
+    }
 }
@@ -0,0 +1,275 @@
+/// Writes MagicaVoxel files, the counterpart of vox_loader.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use voxel_dag::volume::Volume;
+
+use crate::material::{Material, MaterialKind, MaterialTable};
+
+//MagicaVoxel models can't be larger than this on any axis
+const MAX_MODEL_SIZE: u32 = 256;
+const VERSION: u32 = 150;
+
+/// Saves a volume as a MagicaVoxel file. Volumes larger than 256 voxels on any axis are split into
+/// several models, placed by the scene graph so they line up again when loaded with vox_loader::load_vox_scene.
+pub fn save_vox(filename: &str, volume: &Volume, materials: &MaterialTable) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filename)?);
+    write_vox(&mut writer, volume, materials)?;
+    writer.flush()
+}
+
+/// Same as save_vox, but writes to anything.
+pub fn write_vox<W: Write>(writer: &mut W, volume: &Volume, materials: &MaterialTable) -> io::Result<()> {
+    //MagicaVoxel is z-up, so y and z are swapped back, see vox_loader
+    let size = volume.size();
+    let vox_size = (size.0, size.2, size.1);
+    let counts = (
+        ((vox_size.0 + MAX_MODEL_SIZE - 1) / MAX_MODEL_SIZE).max(1),
+        ((vox_size.1 + MAX_MODEL_SIZE - 1) / MAX_MODEL_SIZE).max(1),
+        ((vox_size.2 + MAX_MODEL_SIZE - 1) / MAX_MODEL_SIZE).max(1),
+    );
+
+    let mut models = Vec::new();
+    let mut translations = Vec::new();
+    for cz in 0..counts.2 {
+        for cy in 0..counts.1 {
+            for cx in 0..counts.0 {
+                let min = (cx * MAX_MODEL_SIZE, cy * MAX_MODEL_SIZE, cz * MAX_MODEL_SIZE);
+                //Models need to be at least a voxel wide, even for an empty volume
+                let model_size = (
+                    vox_size.0.saturating_sub(min.0).min(MAX_MODEL_SIZE).max(1),
+                    vox_size.1.saturating_sub(min.1).min(MAX_MODEL_SIZE).max(1),
+                    vox_size.2.saturating_sub(min.2).min(MAX_MODEL_SIZE).max(1),
+                );
+                models.push(model_chunks(volume, min, model_size));
+                //Models are placed by their center voxel
+                translations.push((min.0 + model_size.0 / 2, min.1 + model_size.1 / 2, min.2 + model_size.2 / 2));
+            }
+        }
+    }
+
+    let mut children = Vec::new();
+    for model in &models {
+        children.extend_from_slice(model);
+    }
+    children.extend(scene_chunks(&translations));
+    children.extend(chunk(b"RGBA", &palette_content(materials), &[]));
+    for material in 1..=255u8 {
+        let properties = materials.material(material);
+        if *properties == Material::default() { continue; }
+        let mut content = Vec::new();
+        write_u32(&mut content, material as u32);
+        write_dict(&mut content, &material_properties(properties));
+        children.extend(chunk(b"MATL", &content, &[]));
+    }
+
+    writer.write_all(b"VOX ")?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&chunk(b"MAIN", &[], &children))
+}
+
+//SIZE and XYZI chunks for the part of the volume starting at min, in MagicaVoxel coordinates
+fn model_chunks(volume: &Volume, min: (u32, u32, u32), model_size: (u32, u32, u32)) -> Vec<u8> {
+    let mut size = Vec::new();
+    write_u32(&mut size, model_size.0);
+    write_u32(&mut size, model_size.1);
+    write_u32(&mut size, model_size.2);
+
+    let mut voxels = Vec::new();
+    for z in 0..model_size.2 {
+        for y in 0..model_size.1 {
+            for x in 0..model_size.0 {
+                if let Some(material) = volume.get(min.0 + x, min.2 + z, min.1 + y) {
+                    voxels.extend_from_slice(&[x as u8, y as u8, z as u8, material]);
+                }
+            }
+        }
+    }
+    let mut xyzi = Vec::with_capacity(voxels.len() + 4);
+    write_u32(&mut xyzi, (voxels.len() / 4) as u32);
+    xyzi.extend(voxels);
+
+    let mut result = chunk(b"SIZE", &size, &[]);
+    result.extend(chunk(b"XYZI", &xyzi, &[]));
+    result
+}
+
+//A root transform and group, with a transform and shape node for every model
+fn scene_chunks(translations: &[(u32, u32, u32)]) -> Vec<u8> {
+    let mut result = Vec::new();
+
+    let mut root = Vec::new();
+    write_transform(&mut root, 0, 1, u32::MAX, &[]);
+    result.extend(chunk(b"nTRN", &root, &[]));
+
+    let mut group = Vec::new();
+    write_u32(&mut group, 1);
+    write_dict(&mut group, &[]);
+    write_u32(&mut group, translations.len() as u32);
+    for i in 0..translations.len() as u32 {
+        write_u32(&mut group, 2 + i * 2);
+    }
+    result.extend(chunk(b"nGRP", &group, &[]));
+
+    for (i, t) in translations.iter().enumerate() {
+        let node = 2 + i as u32 * 2;
+        let mut transform = Vec::new();
+        write_transform(&mut transform, node, node + 1, 0, &[("_t".to_string(), format!("{} {} {}", t.0, t.1, t.2))]);
+        result.extend(chunk(b"nTRN", &transform, &[]));
+
+        let mut shape = Vec::new();
+        write_u32(&mut shape, node + 1);
+        write_dict(&mut shape, &[]);
+        write_u32(&mut shape, 1);
+        write_u32(&mut shape, i as u32);
+        write_dict(&mut shape, &[]);
+        result.extend(chunk(b"nSHP", &shape, &[]));
+    }
+    result
+}
+
+fn write_transform(content: &mut Vec<u8>, node: u32, child: u32, layer: u32, frame: &[(String, String)]) {
+    write_u32(content, node);
+    write_dict(content, &[]);
+    write_u32(content, child);
+    write_u32(content, u32::MAX); //Reserved, always -1
+    write_u32(content, layer);
+    write_u32(content, 1); //Frame count
+    write_dict(content, frame);
+}
+
+//The file's palette starts at the first material, and has an unused entry at the end
+fn palette_content(materials: &MaterialTable) -> Vec<u8> {
+    let mut content = Vec::with_capacity(256 * 4);
+    for material in 1..=255u8 {
+        content.extend_from_slice(&materials.palette.colour(material));
+    }
+    content.extend_from_slice(&[0, 0, 0, 0]);
+    content
+}
+
+//The inverse of Material::from_vox
+fn material_properties(material: &Material) -> Vec<(String, String)> {
+    let mut properties = Vec::new();
+    let kind = match material.kind {
+        MaterialKind::Diffuse => "_diffuse",
+        MaterialKind::Metal => "_metal",
+        MaterialKind::Glass => "_glass",
+        MaterialKind::Emissive => "_emit",
+    };
+    properties.push(("_type".to_string(), kind.to_string()));
+    properties.push(("_rough".to_string(), material.roughness.to_string()));
+    match material.kind {
+        MaterialKind::Diffuse => {},
+        MaterialKind::Metal => properties.push(("_metal".to_string(), material.metalness.to_string())),
+        MaterialKind::Glass => {
+            properties.push(("_ri".to_string(), material.ior.to_string()));
+            properties.push(("_ior".to_string(), (material.ior - 1.0).to_string()));
+            properties.push(("_trans".to_string(), material.transparency.to_string()));
+        },
+        MaterialKind::Emissive => {
+            properties.push(("_emit".to_string(), material.emission.to_string()));
+            properties.push(("_flux".to_string(), material.flux.to_string()));
+        },
+    }
+    properties
+}
+
+//Chunks are an id, the size of their content and children, and then the content and children themselves
+fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(12 + content.len() + children.len());
+    result.extend_from_slice(id);
+    write_u32(&mut result, content.len() as u32);
+    write_u32(&mut result, children.len() as u32);
+    result.extend_from_slice(content);
+    result.extend_from_slice(children);
+    result
+}
+
+fn write_u32(content: &mut Vec<u8>, value: u32) {
+    content.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_string(content: &mut Vec<u8>, value: &str) {
+    write_u32(content, value.len() as u32);
+    content.extend_from_slice(value.as_bytes());
+}
+
+fn write_dict(content: &mut Vec<u8>, dict: &[(String, String)]) {
+    write_u32(content, dict.len() as u32);
+    for (key, value) in dict {
+        write_string(content, key);
+        write_string(content, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vox_loader;
+
+    //Deterministic noise, with the far corner always solid so the size survives the round trip
+    fn volume(size: (u32, u32, u32)) -> Volume {
+        let mut volume = Volume::new(size);
+        let mut state: u32 = 0x2545_f491;
+        for z in 0..size.2 {
+            for y in 0..size.1 {
+                for x in 0..size.0 {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    if state % 3 == 0 {
+                        volume.set(x, y, z, (state >> 8) as u8 | 1);
+                    }
+                }
+            }
+        }
+        volume.set(0, 0, 0, 255);
+        volume.set(size.0 - 1, size.1 - 1, size.2 - 1, 1);
+        volume
+    }
+
+    fn materials() -> MaterialTable {
+        let mut materials = MaterialTable::default();
+        for material in 1..=255u8 {
+            materials.palette.set_colour(material, [material, 255 - material, material / 2, 255]);
+        }
+        materials.set_material(3, Material { kind: MaterialKind::Glass, roughness: 0.25, ior: 1.5, transparency: 0.75, ..Material::default() });
+        materials.set_material(7, Material { kind: MaterialKind::Emissive, emission: 2.0, flux: 1.5, ..Material::default() });
+        materials.set_material(255, Material { kind: MaterialKind::Metal, roughness: 0.5, metalness: 0.125, ..Material::default() });
+        materials
+    }
+
+    #[test]
+    fn large_volumes_are_split_and_load_back() {
+        let materials = materials();
+        //(size, models it has to be split into)
+        for &(size, models) in &[((300, 20, 270), 4), ((256, 1, 257), 2), ((513, 2, 1), 3), ((256, 256, 256), 1)] {
+            let volume = volume(size);
+            let mut bytes = Vec::new();
+            write_vox(&mut bytes, &volume, &materials).unwrap();
+
+            let scene = vox_loader::parse_vox_scene(&bytes).unwrap();
+            assert_eq!(scene.instances.len(), models, "{:?}", size);
+            for instance in &scene.instances {
+                let model_size = instance.volume.size();
+                assert!(model_size.0 <= MAX_MODEL_SIZE && model_size.1 <= MAX_MODEL_SIZE && model_size.2 <= MAX_MODEL_SIZE, "{:?}: {:?}", size, model_size);
+            }
+            let (assembled, origin) = scene.assemble().unwrap();
+            assert_eq!(origin, (0, 0, 0), "{:?}", size);
+            assert!(assembled == volume, "{:?} changed in the round trip", size);
+            assert_eq!(scene.materials, materials, "{:?}", size);
+        }
+    }
+
+    #[test]
+    fn default_materials_load_back() {
+        let volume = volume((3, 4, 5));
+        let mut bytes = Vec::new();
+        write_vox(&mut bytes, &volume, &MaterialTable::default()).unwrap();
+        let scene = vox_loader::parse_vox_scene(&bytes).unwrap();
+        assert_eq!(scene.materials, MaterialTable::default());
+        assert!(scene.assemble().unwrap().0 == volume);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::dag::DAG;
+use crate::error::Error;
+use crate::bricks::{LeafFormat, leaf_bits, leaf_voxel, leaf_voxel_index, leaf_rank};
+
+//Per-voxel attributes, stored separately from the geometry so they don't get in the way of deduplication.
+//Following "Geometry and Attribute Compression for Voxel Scenes" (Dado et al.), every solid voxel gets an index
+//by ordering them depth first, visiting children in childmask order. Every node stores how many
+//solid voxels there are below it, so the index can be found while descending: it's the sum of
+//the voxel counts of all siblings in front of the child we take, on every level.
+//Identical subtrees always have identical voxel counts, so this never breaks up shared nodes.
+//
+//voxel_counts runs parallel to the node buffer (one u32 per node), and materials holds one
+//MagicaVoxel palette index per solid voxel. Both can be uploaded to the GPU next to the nodes.
+
+/// Counts the solid voxels below every node reachable from the root.
+pub(crate) fn count_voxels(data: &[u32], depth: u32, leaves: LeafFormat) -> Result<Vec<u32>, Error> {
+    let mut counts = vec![0; data.len() / 2];
+    let mut visited = vec![false; data.len() / 2];
+    count_node(data, leaves.leaf_level(depth), leaves, 0, 0, &mut counts, &mut visited)?;
+    Ok(counts)
+}
+
+fn count_node(data: &[u32], leaf_level: u32, leaves: LeafFormat, idx: u32, level: u32, counts: &mut Vec<u32>, visited: &mut Vec<bool>) -> Result<u32, Error> {
+    if visited[idx as usize] { return Ok(counts[idx as usize]); }
+
+    let count = if level == leaf_level {
+        leaf_bits(data, idx, leaves).count_ones()
+    } else {
+        let childmask = data[idx as usize * 2];
+        let child = data[idx as usize * 2 + 1];
+        let mut sum: u32 = 0;
+        for i in 0..childmask.count_ones() {
+            let child_count = count_node(data, leaf_level, leaves, child + i, level + 1, counts, visited)?;
+            sum = sum.checked_add(child_count).ok_or(Error::NodeIndexOverflow)?;
+        }
+        sum
+    };
+
+    counts[idx as usize] = count;
+    visited[idx as usize] = true;
+    Ok(count)
+}
+
+/// Lists the material of every solid voxel, in attribute order.
+pub(crate) fn gather_materials<F: Fn(u32, u32, u32) -> u8>(data: &[u32], depth: u32, leaves: LeafFormat, material: F) -> Vec<u8> {
+    let mut materials = Vec::new();
+    gather_node(data, depth, leaves, 0, 0, (0, 0, 0), &mut |x, y, z| materials.push(material(x, y, z)));
+    materials
+}
+
+/// Lists the material of every solid voxel below a single node, in attribute order.
+pub(crate) fn gather_subtree_materials<F: Fn(u32, u32, u32) -> u8>(data: &[u32], depth: u32, leaves: LeafFormat, idx: u32, level: u32, pos: (u32, u32, u32), material: F) -> Vec<u8> {
+    let mut materials = Vec::new();
+    gather_node(data, depth, leaves, idx, level, pos, &mut |x, y, z| materials.push(material(x, y, z)));
+    materials
+}
+
+//Calls visit with the position of every solid voxel below the node at idx, in attribute order
+fn gather_node<F: FnMut(u32, u32, u32)>(data: &[u32], depth: u32, leaves: LeafFormat, idx: u32, level: u32, pos: (u32, u32, u32), visit: &mut F) {
+    if level == leaves.leaf_level(depth) {
+        let bits = leaf_bits(data, idx, leaves);
+        for i in 0..leaves.leaf_voxels() {
+            if bits & (1u64 << i) == 0 { continue; }
+            let voxel = leaf_voxel(i, leaves);
+            visit(pos.0 + voxel.0, pos.1 + voxel.1, pos.2 + voxel.2);
+        }
+        return;
+    }
+
+    let childmask = data[idx as usize * 2];
+    let child = data[idx as usize * 2 + 1];
+    let half = 1 << (depth - level - 1);
+
+    let mut child_idx = child;
+    for j in 0..8 {
+        if childmask & (0x0000_0001 << j) == 0 { continue; }
+        let child_pos = (pos.0 + (j % 2) * half, pos.1 + (j / 2 % 2) * half, pos.2 + (j / 4 % 2) * half);
+        gather_node(data, depth, leaves, child_idx, level + 1, child_pos, visit);
+        child_idx += 1;
+    }
+}
+
+/// Lists the material of every solid voxel in the cube of pow(2, level) voxels per axis starting at min.
+/// Attribute order is the same as Morton order, so this matches gather_materials for an aligned cube.
+pub(crate) fn gather_cube_materials<F: Fn(u32, u32, u32) -> u8>(min: (u32, u32, u32), level: u32, material: F) -> Vec<u8> {
+    let mut materials = Vec::new();
+    for code in 0..(1u64 << (level * 3)) {
+        let (x, y, z) = morton_decode(code, level);
+        let voxel_material = material(min.0 + x, min.1 + y, min.2 + z);
+        if voxel_material > 0 {
+            materials.push(voxel_material);
+        }
+    }
+    materials
+}
+
+//Morton codes interleave the bits of a position, with x in the lowest bit, same as child indices
+pub(crate) fn morton_encode(pos: (u32, u32, u32), bits: u32) -> u64 {
+    let mut code = 0;
+    for i in 0..bits {
+        code |= (((pos.0 >> i) & 1) as u64) << (i * 3);
+        code |= (((pos.1 >> i) & 1) as u64) << (i * 3 + 1);
+        code |= (((pos.2 >> i) & 1) as u64) << (i * 3 + 2);
+    }
+    code
+}
+
+pub(crate) fn morton_decode(code: u64, bits: u32) -> (u32, u32, u32) {
+    let mut pos = (0, 0, 0);
+    for i in 0..bits {
+        pos.0 |= (((code >> (i * 3)) & 1) as u32) << i;
+        pos.1 |= (((code >> (i * 3 + 1)) & 1) as u32) << i;
+        pos.2 |= (((code >> (i * 3 + 2)) & 1) as u32) << i;
+    }
+    pos
+}
+
+impl DAG {
+    /// Index into materials() of the voxel at a position, or None if it's empty.
+    pub fn attribute_index(&self, x: u32, y: u32, z: u32) -> Option<u32> {
+        if x >= self.size.0 || y >= self.size.1 || z >= self.size.2 { return None; }
+
+        let mut idx = 0;
+        let mut offset = 0;
+        for level in 0..self.leaf_level() {
+            let half = 1 << (self.depth - level - 1);
+            let mut j = 0;
+            if x & half != 0 { j |= 1; }
+            if y & half != 0 { j |= 2; }
+            if z & half != 0 { j |= 4; }
+
+            let childmask = self.data[idx as usize * 2];
+            let child = self.data[idx as usize * 2 + 1];
+            if childmask & (0x0000_0001 << j) == 0 { return None; }
+            let (child_idx, child_offset) = self.child_attribute_offset(childmask, child, j, offset);
+            idx = child_idx;
+            offset = child_offset;
+        }
+
+        let bits = leaf_bits(&self.data, idx, self.leaves);
+        let i = leaf_voxel_index((x, y, z), self.leaves);
+        if bits & (1u64 << i) == 0 { return None; }
+        Some(offset + leaf_rank(bits, i))
+    }
+
+    //Index and attribute offset of child j of an interior node, whose own attributes start at offset
+    pub(crate) fn child_attribute_offset(&self, childmask: u32, child: u32, j: u32, offset: u32) -> (u32, u32) {
+        let before = (childmask & ((0x0000_0001 << j) - 1)).count_ones();
+        let mut offset = offset;
+        for i in 0..before {
+            offset += self.voxel_counts[(child + i) as usize];
+        }
+        (child + before, offset)
+    }
+
+    /// Material of every solid voxel, see src/attributes.rs for the order.
+    pub fn materials(&self) -> &[u8] {
+        &self.materials
+    }
+
+    /// Amount of solid voxels below every node, parallel to the node buffer.
+    pub fn voxel_counts(&self) -> &[u32] {
+        &self.voxel_counts
+    }
+}
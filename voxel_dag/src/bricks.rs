@@ -0,0 +1,165 @@
+use serde::{Serialize, Deserialize};
+
+use std::collections::HashMap;
+
+use crate::attributes::morton_decode;
+use crate::dag::{DAG, Level, EMPTY, layout};
+use crate::error::Error;
+
+//Brick leaves. By default the bottom level of the DAG stores 2x2x2 voxels in the childmask of a node,
+//so leaves waste half of their 8 bytes. With brick leaves, the last two levels are merged into
+//4x4x4 bricks instead, where the whole node is a 64-bit occupancy word:
+// low word         [bits 0..32 of the brick]
+// high word        [bits 32..64 of the brick]
+//Bits are in Morton order (x in the lowest bit), so bit j * 8 + i is voxel i of octant j. That's the same
+//order attributes use (see src/attributes.rs), so the voxels of a brick have consecutive materials.
+//Nodes no longer tell whether they're a leaf, it's decided by the level: leaves sit at leaf_level.
+
+/// What the nodes at the bottom of a DAG look like.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LeafFormat {
+    /// 2x2x2 voxels, stored in the childmask of a node
+    #[default]
+    Voxels,
+    /// 4x4x4 voxels, stored in a 64-bit word taking up a whole node
+    Bricks,
+}
+
+impl LeafFormat {
+    /// Levels inside of a single leaf, a leaf spans pow(2, leaf_levels) voxels per axis.
+    pub fn leaf_levels(self) -> u32 {
+        match self {
+            LeafFormat::Voxels => 1,
+            LeafFormat::Bricks => 2,
+        }
+    }
+
+    /// Level of the leaf nodes in a DAG of the given depth.
+    pub fn leaf_level(self, depth: u32) -> u32 {
+        depth - self.leaf_levels()
+    }
+
+    /// Amount of voxels in a single leaf.
+    pub fn leaf_voxels(self) -> u32 {
+        1 << (self.leaf_levels() * 3)
+    }
+
+    /// Bits to flip in a leaf voxel index so voxels are visited front to back, like child indices.
+    pub(crate) fn octant_flip(self, octant_mask: u32) -> u32 {
+        match self {
+            LeafFormat::Voxels => octant_mask,
+            LeafFormat::Bricks => octant_mask | (octant_mask << 3),
+        }
+    }
+}
+
+/// Occupancy of the leaf node at idx, bit i is leaf voxel i.
+pub(crate) fn leaf_bits(data: &[u32], idx: u32, leaves: LeafFormat) -> u64 {
+    match leaves {
+        LeafFormat::Voxels => data[idx as usize * 2] as u64,
+        LeafFormat::Bricks => data[idx as usize * 2] as u64 | ((data[idx as usize * 2 + 1] as u64) << 32),
+    }
+}
+
+/// Leaf node with the given occupancy, as it's stored in the buffer.
+pub(crate) fn leaf_node(bits: u64, leaves: LeafFormat) -> (u32, u32) {
+    match leaves {
+        LeafFormat::Voxels => (bits as u32, 0),
+        LeafFormat::Bricks => (bits as u32, (bits >> 32) as u32),
+    }
+}
+
+/// Position of leaf voxel i, relative to the leaf.
+pub(crate) fn leaf_voxel(i: u32, leaves: LeafFormat) -> (u32, u32, u32) {
+    morton_decode(i as u64, leaves.leaf_levels())
+}
+
+/// Index of the leaf voxel at a position, only the lowest leaf_levels bits of the position are used.
+pub(crate) fn leaf_voxel_index(pos: (u32, u32, u32), leaves: LeafFormat) -> u32 {
+    let mut i = 0;
+    for bit in 0..leaves.leaf_levels() {
+        i |= ((pos.0 >> bit) & 1) << (bit * 3);
+        i |= ((pos.1 >> bit) & 1) << (bit * 3 + 1);
+        i |= ((pos.2 >> bit) & 1) << (bit * 3 + 2);
+    }
+    i
+}
+
+//Attribute index of leaf voxel i, relative to the first voxel of the leaf
+pub(crate) fn leaf_rank(bits: u64, i: u32) -> u32 {
+    (bits & ((1u64 << i) - 1)).count_ones()
+}
+
+impl DAG {
+    /// Rebuilds the DAG with different leaves. Bricks need a depth of at least 2.
+    pub fn with_leaf_format(&self, leaves: LeafFormat) -> Result<DAG, Error> {
+        if self.depth < leaves.leaf_levels() {
+            return Err(Error::InvalidLevel(self.depth));
+        }
+
+        let level_count = (leaves.leaf_level(self.depth) + 1) as usize;
+        let mut levels: Vec<Level> = (0..level_count).map(|_| Level::default()).collect();
+        let mut memo = HashMap::new();
+        let root = if self.voxel_counts[0] == 0 { EMPTY } else { self.convert_node(0, 0, leaves, &mut levels, &mut memo) };
+        let data = layout(&levels, root)?;
+        DAG::from_raw(data, self.depth, self.size, leaves, Some(self.materials.clone()))
+    }
+
+    //Node in levels for the node at idx, levels are bottom-up
+    fn convert_node(&self, idx: u32, level: u32, leaves: LeafFormat, levels: &mut [Level], memo: &mut HashMap<u32, u32>) -> u32 {
+        if let Some(id) = memo.get(&idx) {
+            return *id;
+        }
+
+        let childmask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1];
+        let bottom = levels.len() - 1 - level as usize;
+        let id = if self.depth - level == 2 {
+            let bits = self.brick_bits(idx);
+            match leaves {
+                LeafFormat::Bricks => levels[0].node_id(leaf_node(bits, leaves)),
+                LeafFormat::Voxels => {
+                    let mut mask = 0;
+                    let mut children = Vec::new();
+                    for j in 0..8 {
+                        let voxels = ((bits >> (j * 8)) & 0xFF) as u32;
+                        if voxels != 0 {
+                            mask |= 0x0000_0001 << j;
+                            children.push(levels[0].node_id(leaf_node(voxels as u64, LeafFormat::Voxels)));
+                        }
+                    }
+                    let block = levels[0].block_id(children);
+                    levels[1].node_id((mask, block))
+                },
+            }
+        } else if self.depth - level == 1 {
+            //Only happens for a DAG of depth 1, where the root is a leaf
+            levels[0].node_id((childmask, 0))
+        } else {
+            let children = (0..childmask.count_ones()).map(|i| self.convert_node(child + i, level + 1, leaves, levels, memo)).collect();
+            let block = levels[bottom - 1].block_id(children);
+            levels[bottom].node_id((childmask, block))
+        };
+        memo.insert(idx, id);
+        id
+    }
+
+    //Occupancy of the 4x4x4 voxels below a node at level depth - 2
+    fn brick_bits(&self, idx: u32) -> u64 {
+        if self.leaves == LeafFormat::Bricks {
+            return leaf_bits(&self.data, idx, LeafFormat::Bricks);
+        }
+
+        let childmask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1];
+        let mut bits = 0;
+        let mut i = 0;
+        for j in 0..8 {
+            if childmask & (0x0000_0001 << j) != 0 {
+                bits |= (self.data[(child + i) as usize * 2] as u64) << (j * 8);
+                i += 1;
+            }
+        }
+        bits
+    }
+}
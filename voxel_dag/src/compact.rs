@@ -0,0 +1,113 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::dag::DAG;
+
+//Garbage collection for the node buffer.
+//Edits never free old nodes, as they might still be shared, so long editing sessions keep growing the buffer.
+//Compacting marks every block reachable from the root, and copies only those into a fresh buffer.
+
+/// How much of the node buffer is still in use. Every node takes up 8 bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryStats {
+    pub live_nodes: u32,
+    pub dead_nodes: u32,
+    pub live_bytes: usize,
+    pub dead_bytes: usize,
+}
+
+/// Result of compacting a DAG.
+pub struct Compaction {
+    /// New index of every old node, or None if the node was dropped
+    pub remap: Vec<Option<u32>>,
+    /// Stats from before compacting
+    pub before: MemoryStats,
+    /// Stats after compacting, these never have any dead nodes
+    pub after: MemoryStats,
+}
+
+impl DAG {
+    pub fn memory_stats(&self) -> MemoryStats {
+        let live = self.reachable_blocks().iter().map(|(_, len, _)| *len).sum::<u32>() + 1; //+1 for the root
+        let total = (self.data.len() / 2) as u32;
+        MemoryStats {
+            live_nodes: live,
+            dead_nodes: total - live,
+            live_bytes: live as usize * 8,
+            dead_bytes: (total - live) as usize * 8,
+        }
+    }
+
+    /// Drops every node that can't be reached from the root anymore, and rewrites all child indices.
+    pub fn compact(&mut self) -> Compaction {
+        let before = self.memory_stats();
+        let blocks = self.reachable_blocks();
+
+        //Blocks keep the order they were found in, so parents stay in front of their children
+        let mut new_offsets = HashMap::new();
+        let mut offset = 1;
+        for (old, len, _) in &blocks {
+            new_offsets.insert(*old, offset);
+            offset += len;
+        }
+
+        let mut remap = vec![None; self.data.len() / 2];
+        remap[0] = Some(0);
+        let mut data = Vec::with_capacity(offset as usize * 2);
+        let mut voxel_counts = Vec::with_capacity(offset as usize);
+        //Leaves are copied as-is, their second word isn't a child index.
+        //Only the root can be an interior node without children, like after carving away every voxel
+        let leaf_level = self.leaf_level();
+        let remap_child = |childmask: u32, child: u32, level: u32| {
+            if level == leaf_level { child } else if childmask == 0 { 0 } else { new_offsets[&child] }
+        };
+        data.push(self.data[0]);
+        data.push(remap_child(self.data[0], self.data[1], 0));
+        voxel_counts.push(self.voxel_counts[0]);
+        for (old, len, level) in &blocks {
+            for i in 0..*len {
+                let idx = (old + i) as usize;
+                if remap[idx].is_none() { remap[idx] = Some(new_offsets[old] + i); }
+                data.push(self.data[idx * 2]);
+                data.push(remap_child(self.data[idx * 2], self.data[idx * 2 + 1], *level));
+                voxel_counts.push(self.voxel_counts[idx]);
+            }
+        }
+
+        //Attributes are ordered by the shape of the tree, not the buffer, so materials stay untouched
+        self.data = data;
+        self.voxel_counts = voxel_counts;
+        self.block_lookup = None;
+        let after = self.memory_stats();
+        debug!("Compacted DAG from {} to {} nodes", before.live_nodes + before.dead_nodes, after.live_nodes);
+
+        Compaction {
+            remap,
+            before,
+            after,
+        }
+    }
+
+    //Every child block reachable from the root as (first node, length, level of its nodes), breadth first
+    fn reachable_blocks(&self) -> Vec<(u32, u32, u32)> {
+        let mut blocks = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue = vec![(0, 0)];
+        let mut i = 0;
+        while i < queue.len() {
+            let (idx, level) = queue[i];
+            i += 1;
+            if level == self.leaf_level() { continue; }
+            let childmask = self.data[idx as usize * 2];
+            let child = self.data[idx as usize * 2 + 1];
+            if childmask == 0 || seen.contains(&child) { continue; }
+
+            let len = childmask.count_ones();
+            seen.insert(child);
+            blocks.push((child, len, level + 1));
+            for j in 0..len {
+                queue.push((child + j, level + 1));
+            }
+        }
+        blocks
+    }
+}
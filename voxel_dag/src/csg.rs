@@ -0,0 +1,303 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::dag::{DAG, Level, EMPTY, layout};
+use crate::error::Error;
+use crate::octree::required_level;
+use crate::attributes;
+use crate::bricks::LeafFormat;
+
+//Boolean operations between two DAGs, without decompressing either of them.
+//Both node buffers are traversed at the same time, top-down over the result, and the result is reduced
+//into levels like the builders in src/dag.rs, so it comes out deduplicated.
+//
+//The second DAG can be moved by any integer offset, so its nodes don't have to line up with the result.
+//For every result node we keep a window of the 2x2x2 nodes of the same size that overlap it. Where the
+//node sits inside of that window only depends on the offset and the level, so (level, windows) fully
+//describes a result node, and identical pairs of subtrees are only combined once.
+//DAGs smaller than the result are lifted into a bigger tree, with the original root as the first child.
+//
+//Materials are looked up per solid voxel afterwards, in attribute order. Union takes the material of
+//the second DAG where both are solid, so prefabs stamped into terrain keep their own materials.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOp {
+    /// Voxels that are solid in either DAG
+    Union,
+    /// Voxels that are solid in both DAGs
+    Intersection,
+    /// Voxels that are solid in the first DAG, but not in the second one
+    Difference,
+}
+
+impl CsgOp {
+    fn apply(self, a: u32, b: u32) -> u32 {
+        match self {
+            CsgOp::Union => a | b,
+            CsgOp::Intersection => a & b,
+            CsgOp::Difference => a & !b,
+        }
+    }
+}
+
+//A node of one of the operands, in the tree the result is built in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Cursor {
+    Empty,
+    Node(u32),
+    Lifted(u32), //Levels above the root of the operand, only child 0 is used
+}
+
+//The 2x2x2 operand nodes overlapping a result node, indexed like children
+type Window = [Cursor; 8];
+
+struct Operand<'a> {
+    dag: &'a DAG,
+    offset: (i64, i64, i64), //Position of the operand's voxel (0, 0, 0) in the result
+}
+
+impl DAG {
+    pub fn union(&self, other: &DAG) -> Result<DAG, Error> {
+        self.csg(other, CsgOp::Union, (0, 0, 0))
+    }
+
+    pub fn intersection(&self, other: &DAG) -> Result<DAG, Error> {
+        self.csg(other, CsgOp::Intersection, (0, 0, 0))
+    }
+
+    pub fn difference(&self, other: &DAG) -> Result<DAG, Error> {
+        self.csg(other, CsgOp::Difference, (0, 0, 0))
+    }
+
+    /// Combines this DAG with other, placed at offset. The result keeps this DAG's coordinates, so
+    /// voxels of other that end up at a negative position are dropped. A union grows to fit other,
+    /// the other operations never grow past size().
+    pub fn csg(&self, other: &DAG, op: CsgOp, offset: (i32, i32, i32)) -> Result<DAG, Error> {
+        //Leaves are combined as 2x2x2 masks, so bricks get split up first
+        if self.leaves != LeafFormat::Voxels || other.leaves != LeafFormat::Voxels {
+            let a = self.with_leaf_format(LeafFormat::Voxels)?;
+            let b = other.with_leaf_format(LeafFormat::Voxels)?;
+            let result = a.csg(&b, op, offset)?;
+            return if result.depth < self.leaves.leaf_levels() { Ok(result) } else { result.with_leaf_format(self.leaves) };
+        }
+
+        let now = Instant::now();
+
+        let offset = (offset.0 as i64, offset.1 as i64, offset.2 as i64);
+        let size = match op {
+            CsgOp::Union => {
+                let end = |size: u32, offset: i64| cmp::max(size as i64 + offset, 0);
+                let size = (
+                    cmp::max(self.size.0 as i64, end(other.size.0, offset.0)),
+                    cmp::max(self.size.1 as i64, end(other.size.1, offset.1)),
+                    cmp::max(self.size.2 as i64, end(other.size.2, offset.2)),
+                );
+                if size.0 > u32::MAX as i64 || size.1 > u32::MAX as i64 || size.2 > u32::MAX as i64 {
+                    return Err(Error::NodeIndexOverflow);
+                }
+                (size.0 as u32, size.1 as u32, size.2 as u32)
+            },
+            CsgOp::Intersection | CsgOp::Difference => self.size,
+        };
+        let depth = cmp::max(required_level(size), 1);
+
+        let a = Operand { dag: self, offset: (0, 0, 0) };
+        let b = Operand { dag: other, offset };
+        let mut combiner = Combiner {
+            op,
+            a: &a,
+            b: &b,
+            depth,
+            levels: (0..depth).map(|_| Level::default()).collect(),
+            memo: HashMap::new(),
+        };
+        let root = combiner.combine(0, a.root_window(depth), b.root_window(depth));
+        let data = layout(&combiner.levels, root)?;
+
+        let material = |x: u32, y: u32, z: u32| -> u8 {
+            let in_b = b.get(x, y, z);
+            let voxel_material = match op {
+                CsgOp::Union => in_b.or_else(|| self.get(x, y, z)),
+                CsgOp::Intersection | CsgOp::Difference => self.get(x, y, z),
+            };
+            voxel_material.unwrap_or(0)
+        };
+        let materials = attributes::gather_materials(&data, depth, LeafFormat::Voxels, material);
+
+        let duration = Instant::now() - now;
+        debug!("Time to combine DAGs: {}ms", duration.as_millis());
+        debug!("Node count: {}", data.len() / 2);
+
+        DAG::from_raw(data, depth, size, LeafFormat::Voxels, Some(materials))
+    }
+}
+
+//Both operands of a CSG operation, and the levels the result is reduced into
+struct Combiner<'a> {
+    op: CsgOp,
+    a: &'a Operand<'a>,
+    b: &'a Operand<'a>,
+    depth: u32, //Of the result
+    levels: Vec<Level>, //Bottom-up
+    memo: HashMap<(u32, Window, Window), u32>,
+}
+
+impl<'a> Combiner<'a> {
+    //Combines the nodes in two windows into a node in levels, or EMPTY
+    fn combine(&mut self, level: u32, window_a: Window, window_b: Window) -> u32 {
+        let op = self.op;
+        let a_empty = window_a.iter().all(|cursor| *cursor == Cursor::Empty);
+        let b_empty = window_b.iter().all(|cursor| *cursor == Cursor::Empty);
+        if a_empty && (b_empty || op != CsgOp::Union) { return EMPTY; }
+        if b_empty && op == CsgOp::Intersection { return EMPTY; }
+
+        let key = (level, window_a, window_b);
+        if let Some(id) = self.memo.get(&key) {
+            return *id;
+        }
+
+        let size = 1u64 << (self.depth - level);
+        let id = if level + 1 == self.depth {
+            let mask = op.apply(self.a.leaf_mask(&window_a), self.b.leaf_mask(&window_b));
+            if mask == 0 { EMPTY } else { self.levels[0].node_id((mask, 0)) }
+        } else {
+            let mut mask = 0;
+            let mut children = Vec::new();
+            for j in 0..8 {
+                let child_a = self.a.child_window(&window_a, size, j);
+                let child_b = self.b.child_window(&window_b, size, j);
+                let child = self.combine(level + 1, child_a, child_b);
+                if child != EMPTY {
+                    mask |= 0x0000_0001 << j;
+                    children.push(child);
+                }
+            }
+            let bottom = (self.depth - level - 1) as usize;
+            if mask == 0 {
+                EMPTY
+            } else {
+                let block = self.levels[bottom - 1].block_id(children);
+                self.levels[bottom].node_id((mask, block))
+            }
+        };
+        self.memo.insert(key, id);
+        id
+    }
+}
+
+impl<'a> Operand<'a> {
+    //Window of the result root, in a result of the given depth
+    fn root_window(&self, depth: u32) -> Window {
+        //The operand's tree has to be at least as big as the result, so every result node maps onto its nodes
+        let tree_depth = cmp::max(depth, self.dag.depth);
+        let root = if tree_depth > self.dag.depth { Cursor::Lifted(tree_depth - self.dag.depth) } else { Cursor::Node(0) };
+        let levels = tree_depth - depth;
+        let cells = 1i64 << levels; //Nodes per axis at the level of the result root
+
+        let size = 1i64 << depth;
+        let base = (
+            (-self.offset.0).div_euclid(size),
+            (-self.offset.1).div_euclid(size),
+            (-self.offset.2).div_euclid(size),
+        );
+        let mut window = [Cursor::Empty; 8];
+        for (d, slot) in window.iter_mut().enumerate() {
+            let cell = (base.0 + (d & 1) as i64, base.1 + ((d >> 1) & 1) as i64, base.2 + ((d >> 2) & 1) as i64);
+            if cell.0 < 0 || cell.1 < 0 || cell.2 < 0 || cell.0 >= cells || cell.1 >= cells || cell.2 >= cells { continue; }
+
+            let mut cursor = root;
+            for l in (0..levels).rev() {
+                let j = ((cell.0 >> l) & 1) | (((cell.1 >> l) & 1) << 1) | (((cell.2 >> l) & 1) << 2);
+                cursor = self.child(cursor, j as u32);
+            }
+            *slot = cursor;
+        }
+        self.trim(window, size as u64)
+    }
+
+    //Window of child j of a result node of the given size
+    fn child_window(&self, window: &Window, size: u64, j: u32) -> Window {
+        let half = size / 2;
+        let inner = self.inner_offset(size);
+        let first = [
+            (inner.0 + (j & 1) as u64 * half) / half,
+            (inner.1 + ((j >> 1) & 1) as u64 * half) / half,
+            (inner.2 + ((j >> 2) & 1) as u64 * half) / half,
+        ];
+
+        let mut child_window = [Cursor::Empty; 8];
+        for (d, slot) in child_window.iter_mut().enumerate() {
+            //Position in the window, in nodes of half the size
+            let e = [first[0] + (d & 1) as u64, first[1] + ((d >> 1) & 1) as u64, first[2] + ((d >> 2) & 1) as u64];
+            let cursor = window[(e[0] / 2 + e[1] / 2 * 2 + e[2] / 2 * 4) as usize];
+            *slot = self.child(cursor, (e[0] % 2 + e[1] % 2 * 2 + e[2] % 2 * 4) as u32);
+        }
+        self.trim(child_window, half)
+    }
+
+    //Voxels of a result leaf, from a window of operand leaves
+    fn leaf_mask(&self, window: &Window) -> u32 {
+        let inner = self.inner_offset(2);
+        let mut mask = 0;
+        for j in 0..8 {
+            let e = [inner.0 + (j & 1), inner.1 + ((j >> 1) & 1), inner.2 + ((j >> 2) & 1)];
+            let voxels = match window[(e[0] / 2 + e[1] / 2 * 2 + e[2] / 2 * 4) as usize] {
+                Cursor::Node(idx) => self.dag.data[idx as usize * 2],
+                _ => 0,
+            };
+            if voxels & (0x0000_0001 << (e[0] % 2 + e[1] % 2 * 2 + e[2] % 2 * 4)) != 0 {
+                mask |= 0x0000_0001 << j;
+            }
+        }
+        mask
+    }
+
+    //Where a result node of the given size starts inside of its window, the same for every node on a level
+    fn inner_offset(&self, size: u64) -> (u64, u64, u64) {
+        let size = size as i64;
+        (
+            (-self.offset.0).rem_euclid(size) as u64,
+            (-self.offset.1).rem_euclid(size) as u64,
+            (-self.offset.2).rem_euclid(size) as u64,
+        )
+    }
+
+    //Drops the nodes of a window that don't overlap the result node, so equal windows compare equal
+    fn trim(&self, window: Window, size: u64) -> Window {
+        let inner = self.inner_offset(size);
+        let mut window = window;
+        for (d, slot) in window.iter_mut().enumerate() {
+            if (d & 1 != 0 && inner.0 == 0) || (d & 2 != 0 && inner.1 == 0) || (d & 4 != 0 && inner.2 == 0) {
+                *slot = Cursor::Empty;
+            }
+        }
+        window
+    }
+
+    fn child(&self, cursor: Cursor, j: u32) -> Cursor {
+        match cursor {
+            Cursor::Empty => Cursor::Empty,
+            Cursor::Lifted(levels) => {
+                if j != 0 { Cursor::Empty }
+                else if levels == 1 { Cursor::Node(0) }
+                else { Cursor::Lifted(levels - 1) }
+            },
+            Cursor::Node(idx) => {
+                let childmask = self.dag.data[idx as usize * 2];
+                let child = self.dag.data[idx as usize * 2 + 1];
+                if childmask & (0x0000_0001 << j) == 0 { return Cursor::Empty; }
+                Cursor::Node(child + (childmask & ((0x0000_0001 << j) - 1)).count_ones())
+            },
+        }
+    }
+
+    //Material of the voxel at a position in the result
+    fn get(&self, x: u32, y: u32, z: u32) -> Option<u8> {
+        let pos = (x as i64 - self.offset.0, y as i64 - self.offset.1, z as i64 - self.offset.2);
+        if pos.0 < 0 || pos.1 < 0 || pos.2 < 0 || pos.0 > u32::MAX as i64 || pos.1 > u32::MAX as i64 || pos.2 > u32::MAX as i64 {
+            return None;
+        }
+        self.dag.get(pos.0 as u32, pos.1 as u32, pos.2 as u32)
+    }
+}
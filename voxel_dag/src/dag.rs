@@ -1,11 +1,16 @@
-use glam::*;
 use serde::{Serialize, Deserialize};
 
+use std::collections::HashMap;
+use std::cmp;
 use std::time::Instant;
+use std::path::Path;
 
-use super::octree::Octree;
-
-//NOTE: See NOTE in src/octree.rs; This implementation is awful and only stays for cross referencing.
+use crate::error::{Error, check_dimensions};
+use crate::octree::required_level;
+use crate::svdag;
+use crate::attributes;
+use crate::query::SOLID_MATERIAL;
+use crate::bricks::LeafFormat;
 
 //In the original paper, it suggests storing only a pointer to the first child, and then
 //have all the other children stored in memory consecutively, but I'm not sure how I'd do this
@@ -19,6 +24,10 @@ use super::octree::Octree;
 //Data structure:
 // childmask        [24 bits empty; 8 bits for mask]
 // child index 1    [u32] where 0 means this is a leaf node
+//Child j sits at (j % 2, j / 2 % 2, j / 4 % 2) inside its parent. Indices count nodes, not u32s,
+//so a node lives at data[idx * 2] and data[idx * 2 + 1]. The root is always node 0.
+//A leaf node spans 2x2x2 voxels, and its childmask says which of those voxels are solid.
+//With brick leaves, the bottom nodes are 4x4x4 bricks that use both words instead, see src/bricks.rs.
 
 //In the above data structure, we only need the first index, as we can simply check the childmask
 //to see which children contain geometry, and so we only need to know where the first
@@ -50,103 +59,299 @@ impl Node {
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct DAG {
-    data: Vec<u32>, //raw data, only used internally
+//Marks an empty child slot while reducing a level. Never ends up in the final buffer.
+pub(crate) const EMPTY: u32 = u32::MAX;
+
+//A single level of the DAG while it's being reduced.
+//Nodes are deduplicated on (childmask, child block), and the children of a node are
+//deduplicated as a whole block, as they have to be stored consecutively in the final buffer.
+#[derive(Default)]
+pub(crate) struct Level {
+    nodes: Vec<(u32, u32)>, //(childmask, index of the child block in the level below), stored as-is in the bottom level
+    node_lookup: HashMap<(u32, u32), u32>,
+    blocks: Vec<Vec<u32>>, //Groups of siblings, as indices into nodes
+    block_lookup: HashMap<Vec<u32>, u32>,
 }
 
-impl DAG {
-    //Creation
-    pub fn from_voxel_data(data: &[u8], data_size: (u32, u32, u32)) -> Self {
-        // unimplemented!();
-        //First generate an Octree
-        //then turn it into a DAG by removing all duplicate leafs.
-        //We can save on memory by generating the octree only partially
-        //before turning it into a DAG, then generate more parts.
-
-        //For now, we won't bother with generating only partial Octrees.
-        //I first want it to work before I optimize this :)
-        let mut biggest_axis_size = data_size.0;
-        if data_size.1 > biggest_axis_size { biggest_axis_size = data_size.1; }
-        if data_size.2 > biggest_axis_size { biggest_axis_size = data_size.2; }
-
-        let required_level = (biggest_axis_size as f32).log2().ceil() as u32;
-        debug!("Required octree level: {}", required_level);
-        let now = Instant::now();
-        let mut octree = Octree::from_voxel_data(data, data_size, required_level).expect("Failed to create octree!");
-        for _ in 0..required_level {
-            octree.generate_level();
+impl Level {
+    pub(crate) fn node_id(&mut self, node: (u32, u32)) -> u32 {
+        let nodes = &mut self.nodes;
+        *self.node_lookup.entry(node).or_insert_with(|| {
+            nodes.push(node);
+            (nodes.len() - 1) as u32
+        })
+    }
+
+    pub(crate) fn block_id(&mut self, block: Vec<u32>) -> u32 {
+        if let Some(idx) = self.block_lookup.get(&block) {
+            return *idx;
         }
-        let duration = Instant::now() - now;
-        debug!("Time to generate octree: {}ms", duration.as_millis());
-        debug!("Octants: {}", octree.octants.len());
-        debug!("Octree generated, ready to convert to DAG!");
-
-        //Generating the DAG
-        let mut current_level = 0;
-        let current_idx = 0;
-        let mut unique_nodes = Vec::new();
-        for octant in &octree.octants {
-            if octant.level != current_level { current_level = octant.level; }
-            if current_idx >= data.len() {
-                //First node for this level
-                //All indices ill be the wrong index to index into the data index
-                //which needs to be fixed afterwards
-                unique_nodes.push(octant);
-            } else {
-                //Not the first node for this level, so first check if
-                //there are any duplicates already added to the data array
-                let mut found_duplicate = false;
-                'search: for node in &unique_nodes {
-                    if node == &octant {
-                        //Nodes match!
-                        //Duplicate found, so we do not add it to the list
-                        found_duplicate = true;
-                        break 'search;
+        let idx = self.blocks.len() as u32;
+        self.blocks.push(block.clone());
+        self.block_lookup.insert(block, idx);
+        idx
+    }
+
+    //Bottom level, every node directly stores which of its 8 voxels are solid.
+    //Reduces a cube of res * 2 voxels per axis, starting at origin, into a grid of res nodes per axis.
+    pub(crate) fn reduce_leaves<F: Fn(u32, u32, u32) -> bool>(&mut self, res: usize, origin: (u32, u32, u32), voxel: F) -> Vec<u32> {
+        let mut grid = vec![EMPTY; res * res * res];
+        for z in 0..res {
+            for y in 0..res {
+                for x in 0..res {
+                    let mut mask = 0;
+                    for j in 0..8 {
+                        let vx = origin.0 + (x * 2 + j % 2) as u32;
+                        let vy = origin.1 + (y * 2 + j / 2 % 2) as u32;
+                        let vz = origin.2 + (z * 2 + j / 4 % 2) as u32;
+                        if voxel(vx, vy, vz) {
+                            mask |= 0x0000_0001 << j;
+                        }
+                    }
+                    if mask > 0 {
+                        grid[x + y * res + z * res * res] = self.node_id((mask, 0));
                     }
-                }
-                if found_duplicate {
-                    unique_nodes.push(octant);
                 }
             }
         }
+        grid
+    }
 
-        //Now we need to loop through our unique nodes and fix all the indices
-        //NOTE: This is probably way more expensive than needed, but it's 3:28 AM right now
-        //      so this is how I'll solve this problem for now.
-        for node in &unique_nodes {
-            //Basically, we need to get the original children nodes from the octree
-            //and then go through the unique nodes and see where they ended up now.
-            //We cannot simply go through our octree backwards originally to make sure children
-            //have already been moved to a new location either, because then we still need to loop
-            //through our unique nodes, although we might have to loop through less of them.
-            /*
-            for i in 0..8 {
-                if let Some(old_idx) = node.children[i] {
-                    let old_child = &octree.octants[old_idx as usize];
-                    //Now that we have the old child, we need to find the new childs idx
-                    //TODO: Find a way to only loop through nodes of the right level
-                    'search: for i in 0..unique_nodes.len() {
-                        if unique_nodes[i] == old_child {
-                            //We found the new node that we should refer to
-                            node.children[i] = Some(i as u32);
-                            break 'search;
+    //Reduces a grid of child_res nodes per axis from the level below into a grid half that size in this level
+    pub(crate) fn reduce(&mut self, below: &mut Level, grid: &[u32], child_res: usize) -> Vec<u32> {
+        let res = child_res / 2;
+        let mut next_grid = vec![EMPTY; res * res * res];
+        for z in 0..res {
+            for y in 0..res {
+                for x in 0..res {
+                    let mut mask = 0;
+                    let mut children = Vec::new();
+                    for j in 0..8 {
+                        let cx = x * 2 + j % 2;
+                        let cy = y * 2 + j / 2 % 2;
+                        let cz = z * 2 + j / 4 % 2;
+                        let child = grid[cx + cy * child_res + cz * child_res * child_res];
+                        if child != EMPTY {
+                            mask |= 0x0000_0001 << j;
+                            children.push(child);
                         }
                     }
+                    if mask > 0 {
+                        let block = below.block_id(children);
+                        next_grid[x + y * res + z * res * res] = self.node_id((mask, block));
+                    }
                 }
             }
-            */
         }
+        next_grid
+    }
+}
+
+//Reduces a grid of res nodes per axis in levels[level] until only a single node is left.
+//Levels are ordered bottom-up, levels[0] holds the leaf nodes. Returns the remaining node, or EMPTY.
+pub(crate) fn reduce_to_root(levels: &mut [Level], level: usize, grid: Vec<u32>, res: usize) -> u32 {
+    let mut level = level;
+    let mut grid = grid;
+    let mut res = res;
+    while res > 1 {
+        let (below, above) = levels.split_at_mut(level + 1);
+        grid = above[0].reduce(&mut below[level], &grid, res);
+        res /= 2;
+        level += 1;
+    }
+    grid[0]
+}
+
+//Merges the levels of a subtree that was built on its own into levels, deduplicating against the nodes
+//already in there. Both are ordered bottom-up, and the subtree's root level ends up in levels[local.len() - 1].
+//Returns the merged subtree root, or EMPTY.
+pub(crate) fn merge_levels(levels: &mut [Level], local: &[Level], root: u32) -> u32 {
+    let mut block_map: Vec<u32> = Vec::new(); //Merged index of every block in the level below
+    for i in 0..local.len() {
+        let node_map: Vec<u32> = local[i].nodes.iter().map(|(mask, block)| {
+            let child = if i == 0 { *block } else { block_map[*block as usize] };
+            levels[i].node_id((*mask, child))
+        }).collect();
+
+        if i == local.len() - 1 {
+            return if root == EMPTY { EMPTY } else { node_map[root as usize] };
+        }
+        block_map = local[i].blocks.iter().map(|block| {
+            levels[i].block_id(block.iter().map(|node| node_map[*node as usize]).collect())
+        }).collect();
+    }
+    EMPTY
+}
 
-        let mut data = Vec::new();
+//Lays out the buffer top-down, with the root at index 0, so 0 can never be a valid child index.
+//Blocks are placed breadth first from the root, same as DAG::compact, so the layout only depends on the
+//shape of the DAG and not on the order nodes were found in. That keeps every builder's output identical.
+//Levels are ordered bottom-up, and root is a node in the last level (or EMPTY). Bottom level nodes are copied as-is.
+pub(crate) fn layout(levels: &[Level], root: u32) -> Result<Vec<u32>, Error> {
+    layout_tagged(levels, root, 0)
+}
 
-        Self {
-            data: data,
+//Same as layout, but the bits in tag_mask of every node reference (in blocks and root) aren't part of the index.
+//They're stored in the child word of the node the reference points to, like the mirror bits of a SymmetricDAG.
+pub(crate) fn layout_tagged(levels: &[Level], root: u32, tag_mask: u32) -> Result<Vec<u32>, Error> {
+    if root == EMPTY {
+        return Ok(vec![0, 0]);
+    }
+
+    //Every node in buffer order as (level, reference), blocks get their offset the first time they're reached.
+    //0 marks a block without an offset, as it's never a valid child index.
+    let mut block_offsets: Vec<Vec<u32>> = levels.iter().map(|level| vec![0; level.blocks.len()]).collect();
+    let mut queue = vec![(levels.len() - 1, root)];
+    let mut offset: u32 = 1;
+    let mut i = 0;
+    while i < queue.len() {
+        let (level, reference) = queue[i];
+        i += 1;
+        if level == 0 { continue; }
+
+        let block = levels[level].nodes[(reference & !tag_mask) as usize].1 as usize;
+        if block_offsets[level - 1][block] != 0 { continue; }
+        block_offsets[level - 1][block] = offset;
+        let children = &levels[level - 1].blocks[block];
+        offset = offset.checked_add(children.len() as u32).ok_or(Error::NodeIndexOverflow)?;
+        if offset & tag_mask != 0 { return Err(Error::NodeIndexOverflow); }
+        for child in children {
+            queue.push((level - 1, *child));
         }
     }
 
+    let mut data = Vec::with_capacity(queue.len() * 2);
+    for (level, reference) in queue {
+        let (mask, block) = levels[level].nodes[(reference & !tag_mask) as usize];
+        let child = if level == 0 { block } else { block_offsets[level - 1][block as usize] };
+        data.push(mask);
+        data.push(child | (reference & tag_mask));
+    }
+    Ok(data)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DAG {
+    pub(crate) data: Vec<u32>, //raw data, only used internally
+    pub(crate) depth: u32, //Amount of levels below the root, the DAG spans pow(2, depth) voxels per axis
+    pub(crate) size: (u32, u32, u32), //Original extents, everything outside of it is empty
+    #[serde(skip)]
+    pub(crate) block_lookup: Option<HashMap<(u32, Vec<u32>), u32>>, //Child blocks already in data by level, built on the first edit
+    pub(crate) voxel_counts: Vec<u32>, //Solid voxels below every node, see src/attributes.rs
+    pub(crate) materials: Vec<u8>, //Material of every solid voxel, in attribute order
+    #[serde(default)]
+    pub(crate) leaves: LeafFormat, //What the bottom level of nodes looks like
+}
+
+impl DAG {
+    //Creation
+    pub fn from_voxel_data(data: &[u8], data_size: (u32, u32, u32)) -> Result<Self, Error> {
+        check_dimensions(data, data_size)?;
+
+        //The DAG is built bottom-up, one level at a time. Every level is hashed, so identical
+        //subtrees collapse into a single node before the level above them is generated.
+        //This reduces the entire volume at once, see src/streaming.rs for building it in parts.
+        let depth = cmp::max(required_level(data_size), 1);
+        debug!("Required DAG depth: {}", depth);
+
+        let now = Instant::now();
+
+        //Voxels outside of data_size are treated as empty
+        let material = |x: u32, y: u32, z: u32| -> u8 {
+            if x >= data_size.0 || y >= data_size.1 || z >= data_size.2 { return 0; }
+            data[x as usize + y as usize * data_size.0 as usize + z as usize * data_size.0 as usize * data_size.1 as usize]
+        };
+        let voxel = |x: u32, y: u32, z: u32| -> bool { material(x, y, z) > 0 };
+
+        let mut levels: Vec<Level> = (0..depth).map(|_| Level::default()).collect();
+        let res = 1 << (depth - 1);
+        let grid = levels[0].reduce_leaves(res, (0, 0, 0), voxel);
+        let root = reduce_to_root(&mut levels, 0, grid, res);
+        let data = layout(&levels, root)?;
+
+        //Attributes are stored separately, so they don't affect deduplication
+        let voxel_counts = attributes::count_voxels(&data, depth, LeafFormat::Voxels)?;
+        let materials = attributes::gather_materials(&data, depth, LeafFormat::Voxels, material);
+
+        let duration = Instant::now() - now;
+        debug!("Time to generate DAG: {}ms", duration.as_millis());
+        debug!("Node count: {}", data.len() / 2);
+
+        Ok(Self {
+            data,
+            depth,
+            size: data_size,
+            block_lookup: None,
+            voxel_counts,
+            materials,
+            leaves: LeafFormat::Voxels,
+        })
+    }
+
+    //Materials are optional, every voxel gets material 1 without them
+    pub(crate) fn from_raw(data: Vec<u32>, depth: u32, size: (u32, u32, u32), leaves: LeafFormat, materials: Option<Vec<u8>>) -> Result<Self, Error> {
+        let voxel_counts = attributes::count_voxels(&data, depth, leaves)?;
+        let voxel_count = voxel_counts[0] as usize;
+        let materials = match materials {
+            Some(materials) => {
+                if materials.len() != voxel_count {
+                    return Err(Error::DimensionMismatch {
+                        expected: voxel_count,
+                        found: materials.len(),
+                    });
+                }
+                materials
+            },
+            None => vec![SOLID_MATERIAL; voxel_count],
+        };
+
+        Ok(Self {
+            data,
+            depth,
+            size,
+            block_lookup: None,
+            voxel_counts,
+            materials,
+            leaves,
+        })
+    }
+
+    //IO, see src/svdag.rs for the file format
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        svdag::save(self, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        svdag::load(path)
+    }
+
     //Functions
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Size of the original volume, in voxels
+    pub fn size(&self) -> (u32, u32, u32) {
+        self.size
+    }
 
+    /// Size of the power of two cube the DAG spans, in voxels
+    pub fn cube_size(&self) -> u32 {
+        1 << self.depth
+    }
+
+    pub fn leaf_format(&self) -> LeafFormat {
+        self.leaves
+    }
+
+    //Level of the leaf nodes, anything above it is an interior node
+    pub(crate) fn leaf_level(&self) -> u32 {
+        self.leaves.leaf_level(self.depth)
+    }
+
+    pub fn get_node(&self, idx: u32) -> Node {
+        Node::new(self.data[idx as usize * 2], self.data[idx as usize * 2 + 1])
+    }
 
     //Memory
     pub fn get_ptr(&self) -> *const u32 {
@@ -156,4 +361,8 @@ impl DAG {
     pub fn get_len(&self) -> usize {
         self.data.len()
     }
+
+    pub fn get_data(&self) -> &[u32] {
+        &self.data
+    }
 }
@@ -0,0 +1,97 @@
+use glam::*;
+
+use crate::aabb::BoundingBox;
+use crate::dag::DAG;
+use crate::bricks::{leaf_bits, leaf_voxel, leaf_rank};
+
+//The inverse of from_voxel_data, for tooling, meshing and round trip tests.
+//Only nodes that overlap the region are visited, and materials are read in attribute order along the way,
+//so extracting a small region out of a big DAG stays cheap.
+
+impl DAG {
+    /// Decompresses every voxel that bounds overlaps into a dense grid of materials, indexed like from_voxel_data.
+    /// The grid starts at the voxel containing bounds.min, and voxels outside of size() are 0.
+    /// Returns the grid and its size.
+    pub fn decompress(&self, bounds: &BoundingBox) -> (Vec<u8>, (u32, u32, u32)) {
+        let min = (bounds.min.x().floor() as i64, bounds.min.y().floor() as i64, bounds.min.z().floor() as i64);
+        let max = (bounds.max.x().ceil() as i64, bounds.max.y().ceil() as i64, bounds.max.z().ceil() as i64);
+        let size = (
+            (max.0 - min.0).max(0) as u32,
+            (max.1 - min.1).max(0) as u32,
+            (max.2 - min.2).max(0) as u32,
+        );
+
+        let mut region = Region {
+            min,
+            max: (min.0 + size.0 as i64, min.1 + size.1 as i64, min.2 + size.2 as i64),
+            size,
+            data: vec![0; size.0 as usize * size.1 as usize * size.2 as usize],
+        };
+        if !region.data.is_empty() {
+            self.decompress_node(0, 0, (0, 0, 0), 0, &mut region);
+        }
+        (region.data, size)
+    }
+
+    /// Decompresses the whole volume, the result can be passed straight back into from_voxel_data.
+    pub fn to_voxel_data(&self) -> Vec<u8> {
+        let bounds = BoundingBox::new(Vec3A::zero(), Vec3A::new(self.size.0 as f32, self.size.1 as f32, self.size.2 as f32));
+        self.decompress(&bounds).0
+    }
+
+    //attribute_offset is the attribute index of the first voxel below this node
+    fn decompress_node(&self, idx: u32, level: u32, pos: (u32, u32, u32), attribute_offset: u32, region: &mut Region) {
+        if level == self.leaf_level() {
+            let bits = leaf_bits(&self.data, idx, self.leaves);
+            for i in 0..self.leaves.leaf_voxels() {
+                if bits & (1u64 << i) == 0 { continue; }
+                let voxel = leaf_voxel(i, self.leaves);
+                let voxel = (pos.0 + voxel.0, pos.1 + voxel.1, pos.2 + voxel.2);
+                if let Some(out) = region.index(voxel) {
+                    region.data[out] = self.materials[(attribute_offset + leaf_rank(bits, i)) as usize];
+                }
+            }
+            return;
+        }
+
+        let childmask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1];
+        let half = 1u32 << (self.depth - level - 1);
+
+        let mut child_idx = child;
+        let mut offset = attribute_offset;
+        for j in 0..8 {
+            if childmask & (0x0000_0001 << j) == 0 { continue; }
+            let child_pos = (pos.0 + (j % 2) * half, pos.1 + (j / 2 % 2) * half, pos.2 + (j / 4 % 2) * half);
+            if region.overlaps(child_pos, half) {
+                self.decompress_node(child_idx, level + 1, child_pos, offset, region);
+            }
+            offset += self.voxel_counts[child_idx as usize];
+            child_idx += 1;
+        }
+    }
+}
+
+//Dense output grid, covering the voxels [min, max)
+struct Region {
+    min: (i64, i64, i64),
+    max: (i64, i64, i64),
+    size: (u32, u32, u32),
+    data: Vec<u8>,
+}
+
+impl Region {
+    fn overlaps(&self, pos: (u32, u32, u32), size: u32) -> bool {
+        let lo = (pos.0 as i64, pos.1 as i64, pos.2 as i64);
+        let hi = (lo.0 + size as i64, lo.1 + size as i64, lo.2 + size as i64);
+        hi.0 > self.min.0 && hi.1 > self.min.1 && hi.2 > self.min.2 && lo.0 < self.max.0 && lo.1 < self.max.1 && lo.2 < self.max.2
+    }
+
+    fn index(&self, voxel: (u32, u32, u32)) -> Option<usize> {
+        if !self.overlaps(voxel, 1) { return None; }
+        let x = (voxel.0 as i64 - self.min.0) as usize;
+        let y = (voxel.1 as i64 - self.min.1) as usize;
+        let z = (voxel.2 as i64 - self.min.2) as usize;
+        Some(x + y * self.size.0 as usize + z * self.size.0 as usize * self.size.1 as usize)
+    }
+}
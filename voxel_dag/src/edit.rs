@@ -0,0 +1,333 @@
+use glam::*;
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::aabb::BoundingBox;
+use crate::dag::DAG;
+use crate::error::Error;
+use crate::bricks::{leaf_bits, leaf_node, leaf_voxel, leaf_rank};
+
+//Editing, based on https://graphics.tudelft.nl/Publications-new/2020/CBE20/ModifyingCompressedVoxels-main.pdf
+//Nodes can be shared by many parents, so they're never modified in place. Instead, every node on the
+//path to an edited voxel is copied, and the new child blocks are appended to the end of the buffer.
+//Blocks that already exist are reused, so the DAG stays deduplicated. Only the root is overwritten,
+//as it's always node 0. Old nodes stay in the buffer until the DAG is compacted.
+//Materials are in attribute order, so every edited subtree owns a single range of them. Only those ranges
+//get replaced, the materials of everything the edit didn't touch stay where they are.
+//A voxel counts as inside of a shape when its center is.
+
+pub enum Shape {
+    Box(BoundingBox),
+    Sphere {
+        center: Vec3A,
+        radius: f32,
+    },
+}
+
+#[derive(PartialEq)]
+enum Coverage {
+    Outside,
+    Partial,
+    Inside,
+}
+
+impl Shape {
+    //Checks the voxel centers inside of the cube [pos, pos + size)
+    fn coverage(&self, pos: (u32, u32, u32), size: u32) -> Coverage {
+        let first = Vec3A::new(pos.0 as f32, pos.1 as f32, pos.2 as f32) + Vec3A::splat(0.5);
+        let last = first + Vec3A::splat((size - 1) as f32);
+        match self {
+            Shape::Box(bounds) => {
+                if last.cmplt(bounds.min).any() || first.cmpge(bounds.max).any() {
+                    Coverage::Outside
+                } else if first.cmpge(bounds.min).all() && last.cmplt(bounds.max).all() {
+                    Coverage::Inside
+                } else {
+                    Coverage::Partial
+                }
+            },
+            Shape::Sphere { center, radius } => {
+                let nearest = center.max(first).min(last);
+                let farthest = Vec3A::new(
+                    if (center.x() - first.x()).abs() > (center.x() - last.x()).abs() { first.x() } else { last.x() },
+                    if (center.y() - first.y()).abs() > (center.y() - last.y()).abs() { first.y() } else { last.y() },
+                    if (center.z() - first.z()).abs() > (center.z() - last.z()).abs() { first.z() } else { last.z() },
+                );
+                if (nearest - *center).length() > *radius {
+                    Coverage::Outside
+                } else if (farthest - *center).length() <= *radius {
+                    Coverage::Inside
+                } else {
+                    Coverage::Partial
+                }
+            },
+        }
+    }
+}
+
+impl DAG {
+    //Edits, coordinates are in voxels. Voxels outside of size() are never touched.
+    //Every edit returns the node ranges that changed, so only those have to be uploaded again.
+    //The voxel counts change in the same ranges, but materials() has to be uploaded again entirely.
+    //Uploading is up to the caller, voxel_dag only reports the ranges. The engine doesn't edit DAGs at runtime
+    //yet, so it has no partial upload path, and uploads every buffer in full.
+    pub fn set_voxel(&mut self, x: u32, y: u32, z: u32, material: u8) -> Result<Vec<Range<u32>>, Error> {
+        self.fill(&Shape::Box(voxel_box(x, y, z)), material)
+    }
+
+    pub fn clear_voxel(&mut self, x: u32, y: u32, z: u32) -> Result<Vec<Range<u32>>, Error> {
+        self.carve(&Shape::Box(voxel_box(x, y, z)))
+    }
+
+    pub fn fill_box(&mut self, bounds: BoundingBox, material: u8) -> Result<Vec<Range<u32>>, Error> {
+        self.fill(&Shape::Box(bounds), material)
+    }
+
+    pub fn fill_sphere(&mut self, center: Vec3A, radius: f32, material: u8) -> Result<Vec<Range<u32>>, Error> {
+        self.fill(&Shape::Sphere { center, radius }, material)
+    }
+
+    /// Makes every voxel inside of the shape solid, with the given material.
+    pub fn fill(&mut self, shape: &Shape, material: u8) -> Result<Vec<Range<u32>>, Error> {
+        self.edit(shape, Some(material))
+    }
+
+    /// Makes every voxel inside of the shape empty.
+    pub fn carve(&mut self, shape: &Shape) -> Result<Vec<Range<u32>>, Error> {
+        self.edit(shape, None)
+    }
+
+    fn edit(&mut self, shape: &Shape, material: Option<u8>) -> Result<Vec<Range<u32>>, Error> {
+        self.build_block_lookup();
+
+        let old_len = self.node_count();
+        let old_root = (self.data[0], self.data[1]);
+        let mut splices = Vec::new();
+        let root = self.edit_node(Some(0), 0, (0, 0, 0), 0, &Edit { shape, material }, &mut splices)?;
+        self.data[0] = root.0;
+        self.data[1] = root.1;
+        self.voxel_counts[0] = root.2;
+        splice_materials(&mut self.materials, splices);
+
+        let mut dirty = Vec::new();
+        if (root.0, root.1) != old_root { dirty.push(0..1); }
+        if self.node_count() > old_len { dirty.push(old_len..self.node_count()); }
+        Ok(dirty)
+    }
+
+    //Returns the edited copy of a node as (childmask, first child, voxel count), with (0, 0, 0) being empty.
+    //old_offset is where the old node's materials start, or where they would be if the node is new.
+    //Every range of materials that changes gets added to splices, in attribute order.
+    fn edit_node(&mut self, old: Option<u32>, level: u32, pos: (u32, u32, u32), old_offset: u32, edit: &Edit, splices: &mut Vec<Splice>) -> Result<(u32, u32, u32), Error> {
+        if level == self.leaf_level() {
+            return Ok(self.edit_leaf(old, pos, old_offset, edit, splices));
+        }
+
+        let half = 1 << (self.depth - level - 1);
+        let node = match old {
+            Some(idx) => (self.data[idx as usize * 2], self.data[idx as usize * 2 + 1]),
+            None => (0, 0),
+        };
+
+        let mut mask = 0;
+        let mut count: u32 = 0;
+        let mut children = Vec::new();
+        let mut child_offset = old_offset;
+        for j in 0..8 {
+            let child_pos = (pos.0 + (j % 2) * half, pos.1 + (j / 2 % 2) * half, pos.2 + (j / 4 % 2) * half);
+            let exists = node.0 & (0x0000_0001 << j) != 0;
+            let coverage = match edit.shape.coverage(child_pos, half) {
+                Coverage::Outside => Coverage::Outside,
+                coverage => self.size_coverage(child_pos, half, coverage),
+            };
+
+            let existing = if exists { Some(node.1 + (node.0 & ((0x0000_0001 << j) - 1)).count_ones()) } else { None };
+            let existing_count = existing.map_or(0, |idx| self.voxel_counts[idx as usize]);
+            let new_child = match (coverage, edit.material) {
+                (Coverage::Outside, _) => existing.map(|idx| (self.data[idx as usize * 2], self.data[idx as usize * 2 + 1], existing_count)),
+                (Coverage::Inside, Some(material)) => {
+                    let full = self.full_node(level + 1)?;
+                    splices.push(Splice {
+                        start: child_offset,
+                        len: existing_count,
+                        materials: vec![material; full.2 as usize],
+                    });
+                    Some(full)
+                },
+                (Coverage::Inside, None) => {
+                    if existing.is_some() {
+                        splices.push(Splice {
+                            start: child_offset,
+                            len: existing_count,
+                            materials: Vec::new(),
+                        });
+                    }
+                    None
+                },
+                (Coverage::Partial, _) => {
+                    let edited = self.edit_node(existing, level + 1, child_pos, child_offset, edit, splices)?;
+                    if edited.2 == 0 { None } else { Some(edited) }
+                },
+            };
+            child_offset += existing_count;
+            if let Some(child) = new_child {
+                mask |= 0x0000_0001 << j;
+                count = count.checked_add(child.2).ok_or(Error::NodeIndexOverflow)?;
+                children.push(child);
+            }
+        }
+
+        if mask == 0 {
+            return Ok((0, 0, 0));
+        }
+        Ok((mask, self.insert_block(level + 1, &children)?, count))
+    }
+
+    //Same as edit_node, for a leaf. Its voxels are always either inside or outside of the shape.
+    fn edit_leaf(&self, old: Option<u32>, pos: (u32, u32, u32), old_offset: u32, edit: &Edit, splices: &mut Vec<Splice>) -> (u32, u32, u32) {
+        let bits = old.map(|idx| leaf_bits(&self.data, idx, self.leaves)).unwrap_or(0);
+
+        let mut new_bits = 0;
+        let mut materials = Vec::new();
+        for i in 0..self.leaves.leaf_voxels() {
+            let voxel = leaf_voxel(i, self.leaves);
+            let voxel = (pos.0 + voxel.0, pos.1 + voxel.1, pos.2 + voxel.2);
+            let coverage = match edit.shape.coverage(voxel, 1) {
+                Coverage::Outside => Coverage::Outside,
+                coverage => self.size_coverage(voxel, 1, coverage),
+            };
+            let voxel_material = if coverage == Coverage::Outside {
+                if bits & (1u64 << i) != 0 { Some(self.materials[(old_offset + leaf_rank(bits, i)) as usize]) } else { None }
+            } else {
+                edit.material
+            };
+            if let Some(voxel_material) = voxel_material {
+                new_bits |= 1u64 << i;
+                materials.push(voxel_material);
+            }
+        }
+
+        splices.push(Splice {
+            start: old_offset,
+            len: bits.count_ones(),
+            materials,
+        });
+        let (lo, hi) = leaf_node(new_bits, self.leaves);
+        (lo, hi, new_bits.count_ones())
+    }
+
+    //Narrows down the coverage of a shape to the voxels inside of size()
+    fn size_coverage(&self, pos: (u32, u32, u32), size: u32, coverage: Coverage) -> Coverage {
+        let end = (pos.0 + size, pos.1 + size, pos.2 + size);
+        if pos.0 >= self.size.0 || pos.1 >= self.size.1 || pos.2 >= self.size.2 {
+            Coverage::Outside
+        } else if end.0 > self.size.0 || end.1 > self.size.1 || end.2 > self.size.2 {
+            Coverage::Partial
+        } else {
+            coverage
+        }
+    }
+
+    //A node at the given level with every voxel below it solid, as (childmask, first child, voxel count)
+    fn full_node(&mut self, level: u32) -> Result<(u32, u32, u32), Error> {
+        if level == self.leaf_level() {
+            let voxels = self.leaves.leaf_voxels();
+            let (lo, hi) = leaf_node(u64::MAX >> (64 - voxels), self.leaves);
+            return Ok((lo, hi, voxels));
+        }
+        let child = self.full_node(level + 1)?;
+        let count = child.2.checked_mul(8).ok_or(Error::NodeIndexOverflow)?;
+        Ok((0xFF, self.insert_block(level + 1, &[child; 8])?, count))
+    }
+
+    //Returns the index of a block of siblings, appending it to the buffer if it doesn't exist yet.
+    //Children are (childmask, first child, voxel count) at the given level. Blocks are only shared within a level,
+    //the words of a brick leaf can be the same as those of an interior node.
+    pub(crate) fn insert_block(&mut self, level: u32, children: &[(u32, u32, u32)]) -> Result<u32, Error> {
+        let mut key = Vec::with_capacity(children.len() * 2);
+        for child in children {
+            key.push(child.0);
+            key.push(child.1);
+        }
+
+        let key = (level, key);
+        let lookup = self.block_lookup.as_mut().expect("Block lookup has to be built before inserting blocks");
+        if let Some(idx) = lookup.get(&key) {
+            return Ok(*idx);
+        }
+
+        let idx = self.data.len() / 2;
+        if idx + children.len() > u32::MAX as usize { return Err(Error::NodeIndexOverflow); }
+        self.data.extend_from_slice(&key.1);
+        for child in children {
+            self.voxel_counts.push(child.2);
+        }
+        lookup.insert(key, idx as u32);
+        Ok(idx as u32)
+    }
+
+    pub(crate) fn build_block_lookup(&mut self) {
+        if self.block_lookup.is_some() { return; }
+
+        let mut lookup = HashMap::new();
+        let mut stack = vec![(0, 0)];
+        while let Some((idx, level)) = stack.pop() {
+            if level == self.leaf_level() { continue; }
+            let childmask = self.data[idx as usize * 2];
+            let child = self.data[idx as usize * 2 + 1];
+            if childmask == 0 { continue; }
+
+            let count = childmask.count_ones();
+            let key = (level + 1, self.data[child as usize * 2 .. (child + count) as usize * 2].to_vec());
+            if lookup.contains_key(&key) { continue; }
+            lookup.insert(key, child);
+            for i in 0..count {
+                stack.push((child + i, level + 1));
+            }
+        }
+        self.block_lookup = Some(lookup);
+    }
+
+    fn node_count(&self) -> u32 {
+        (self.data.len() / 2) as u32
+    }
+}
+
+//A fill with Some(material), or a carve
+struct Edit<'a> {
+    shape: &'a Shape,
+    material: Option<u8>,
+}
+
+//Replaces len materials starting at start, indices are from before any splice is applied
+struct Splice {
+    start: u32,
+    len: u32,
+    materials: Vec<u8>,
+}
+
+//Applies splices in attribute order. A single one is spliced in place, which is all a voxel edit needs.
+//More than that would each move everything behind them, so those rebuild the materials once instead.
+fn splice_materials(materials: &mut Vec<u8>, splices: Vec<Splice>) {
+    if splices.len() == 1 {
+        let splice = splices.into_iter().next().unwrap();
+        materials.splice(splice.start as usize .. (splice.start + splice.len) as usize, splice.materials);
+        return;
+    }
+
+    let mut result = Vec::with_capacity(materials.len());
+    let mut pos = 0;
+    for splice in splices {
+        result.extend_from_slice(&materials[pos .. splice.start as usize]);
+        result.extend_from_slice(&splice.materials);
+        pos = (splice.start + splice.len) as usize;
+    }
+    result.extend_from_slice(&materials[pos..]);
+    *materials = result;
+}
+
+fn voxel_box(x: u32, y: u32, z: u32) -> BoundingBox {
+    let min = Vec3A::new(x as f32, y as f32, z as f32);
+    BoundingBox::new(min, min + Vec3A::splat(1.0))
+}
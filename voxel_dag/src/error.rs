@@ -0,0 +1,70 @@
+use std::fmt;
+
+/// Everything that can go wrong while building or loading one of the voxel data structures.
+#[derive(Debug)]
+pub enum Error {
+    /// The requested tree level can't be used to build the structure.
+    InvalidLevel(u32),
+    /// `data.len()` doesn't match the volume described by `data_size`.
+    DimensionMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// A size that has to be a power of two isn't.
+    NonPowerOfTwo(u32),
+    /// A brick isn't aligned to the brick size, lies outside of the volume or comes before a brick that was already added.
+    InvalidBrick((u32, u32, u32)),
+    /// A node in a buffer can't be traversed safely, see DAG::validate.
+    InvalidNode {
+        node: u32,
+        reason: String,
+    },
+    /// The structure has more nodes than a u32 child index can address.
+    NodeIndexOverflow,
+    /// A serialized structure is malformed or uses an unsupported version.
+    Serialization(String),
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidLevel(level) => write!(f, "Unable to create a tree with level {}", level),
+            Error::DimensionMismatch { expected, found } => write!(f, "Expected {} voxels, but got {}", expected, found),
+            Error::NonPowerOfTwo(size) => write!(f, "{} is not a power of two", size),
+            Error::InvalidBrick(min) => write!(f, "Brick at {:?} is misaligned, out of bounds or out of order", min),
+            Error::InvalidNode { node, reason } => write!(f, "Node {} is invalid: {}", node, reason),
+            Error::NodeIndexOverflow => write!(f, "Node count does not fit in a u32 index"),
+            Error::Serialization(msg) => write!(f, "Serialization failed: {}", msg),
+            Error::Io(err) => write!(f, "IO error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+//Shared by all builders, as they all index data with x + y * x_size + z * x_size * y_size
+pub(crate) fn check_dimensions(data: &[u8], data_size: (u32, u32, u32)) -> Result<(), Error> {
+    let expected = data_size.0 as usize * data_size.1 as usize * data_size.2 as usize;
+    if data.len() != expected {
+        return Err(Error::DimensionMismatch {
+            expected,
+            found: data.len(),
+        });
+    }
+    Ok(())
+}
@@ -1,6 +1,26 @@
 #[macro_use] extern crate log;
 
+mod decompress;
+mod error;
+mod lod;
+mod parallel;
+mod query;
+
 pub mod aabb;
+pub mod attributes;
+pub mod bricks;
+pub mod compact;
+pub mod csg;
 pub mod dag;
+pub mod edit;
 pub mod octree;
+pub mod raycast;
+pub mod stats;
+pub mod streaming;
+pub mod svdag;
+pub mod symmetry;
+pub mod transform;
+pub mod volume;
 pub mod voxel_data_structure;
+
+pub use error::Error;
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use crate::dag::{DAG, Level, EMPTY, layout};
+use crate::error::Error;
+use crate::bricks::LeafFormat;
+
+//Level of detail, by cutting the DAG off at a lower depth.
+//Nodes at the new bottom level become leaves, and every child that has geometry below it becomes a solid voxel,
+//so a LOD voxel spans pow(2, depth - lod_depth) voxels per axis. Its material is the most common one below it.
+//The result is a regular DAG, so it can be queried, raycast and uploaded like any other.
+//Scale positions down by lod_cell_size before querying it, or pass it as dag_scale when tracing it on the GPU.
+//
+//The voxels below a node have consecutive attribute indices (see src/attributes.rs),
+//so the material of a LOD voxel only needs a histogram over a slice of materials.
+//DAGs with brick leaves are cut off on voxel leaves, and get their bricks back if the LOD is deep enough.
+
+impl DAG {
+    /// Cuts the DAG off at the given depth, merging every cube of lod_cell_size voxels into one.
+    pub fn lod(&self, depth: u32) -> Result<DAG, Error> {
+        if depth < 1 || depth > self.depth {
+            return Err(Error::InvalidLevel(depth));
+        }
+        if self.leaves == LeafFormat::Bricks {
+            let lod = self.with_leaf_format(LeafFormat::Voxels)?.lod(depth)?;
+            return if depth < LeafFormat::Bricks.leaf_levels() { Ok(lod) } else { lod.with_leaf_format(LeafFormat::Bricks) };
+        }
+
+        let mut levels: Vec<Level> = (0..depth).map(|_| Level::default()).collect();
+        let mut memo = HashMap::new();
+        let root = if self.data[0] == 0 { EMPTY } else { self.lod_node(0, 0, depth, &mut levels, &mut memo) };
+        let data = layout(&levels, root)?;
+
+        let mut materials = Vec::new();
+        self.lod_materials(0, 0, depth, 0, &mut materials);
+
+        let cell_size = self.lod_cell_size(depth);
+        let size = (
+            self.size.0.div_ceil(cell_size),
+            self.size.1.div_ceil(cell_size),
+            self.size.2.div_ceil(cell_size),
+        );
+        DAG::from_raw(data, depth, size, LeafFormat::Voxels, Some(materials))
+    }
+
+    /// Voxels per axis that a single voxel of lod(depth) covers.
+    pub fn lod_cell_size(&self, depth: u32) -> u32 {
+        1 << (self.depth - depth)
+    }
+
+    //Node in levels for the node at idx, levels are bottom-up and end at the LOD's root level
+    fn lod_node(&self, idx: u32, level: u32, lod_depth: u32, levels: &mut [Level], memo: &mut HashMap<u32, u32>) -> u32 {
+        if let Some(id) = memo.get(&idx) {
+            return *id;
+        }
+
+        let childmask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1];
+        let bottom = (lod_depth - level - 1) as usize;
+        let id = if bottom == 0 {
+            levels[0].node_id((childmask, 0))
+        } else {
+            let children = (0..childmask.count_ones()).map(|i| self.lod_node(child + i, level + 1, lod_depth, levels, memo)).collect();
+            let block = levels[bottom - 1].block_id(children);
+            levels[bottom].node_id((childmask, block))
+        };
+        memo.insert(idx, id);
+        id
+    }
+
+    //Appends the material of every LOD voxel below the node at idx, in attribute order
+    fn lod_materials(&self, idx: u32, level: u32, lod_depth: u32, offset: u32, materials: &mut Vec<u8>) {
+        let childmask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1];
+
+        let mut offset = offset;
+        for i in 0..childmask.count_ones() {
+            let count = if child == 0 { 1 } else { self.voxel_counts[(child + i) as usize] };
+            if level + 1 == lod_depth {
+                materials.push(majority(&self.materials[offset as usize .. (offset + count) as usize]));
+            } else {
+                self.lod_materials(child + i, level + 1, lod_depth, offset, materials);
+            }
+            offset += count;
+        }
+    }
+}
+
+//Most common material, ties go to the lowest one
+fn majority(materials: &[u8]) -> u8 {
+    let mut histogram = [0u32; 256];
+    for material in materials {
+        histogram[*material as usize] += 1;
+    }
+    let mut best = 0;
+    for i in 1..256 {
+        if histogram[i] > histogram[best] { best = i; }
+    }
+    best as u8
+}
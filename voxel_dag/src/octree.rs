@@ -1,8 +1,10 @@
-use std::rc::Rc;
 use std::cmp;
 
 use glam::*;
 
+use crate::error::{Error, check_dimensions};
+use crate::aabb::BoundingBox;
+
 //Octree implementation for constructing a DAG
 //Not the most optimized implementation, but I don't care too much about memory usage, as this
 //will only be used as an intermediate stage for constructing DAGs, and we do not need to
@@ -23,7 +25,7 @@ use glam::*;
 pub struct Octant {
     pub children: [Option<u32>; 8], //u32 as an index into the octree
     pub level: u32,
-    pub is_leaf: bool,
+    pub is_leaf: bool, //Set for octants without any geometry inside of them
     pub position: Vec3A, //Top left, 16 bytes instead of 12 but might be worth the trade off
 }
 
@@ -61,14 +63,45 @@ pub struct Octree<'a> {
     pub current_level: (u32, Vec<usize>), //(current level, indices of nodes in vector)
     pub octants: Vec<Octant>,
     voxel_data: &'a [u8],
-    data_size: (u32, u32, u32)
+    data_size: (u32, u32, u32), //Original extents, the octree itself spans cube_size voxels per axis
+    cube_size: u32,
 }
 
 //Voxels (on 1 axis) per level of the octree: pow(2, level)
-//Required length for size: log2(biggest_voxel_count_axis)//.ceil() to get the proper level
+//Required length for size: log2(biggest_voxel_count_axis).ceil() to get the proper level
+pub fn required_level(data_size: (u32, u32, u32)) -> u32 {
+    let mut biggest_axis_size = data_size.0;
+    if data_size.1 > biggest_axis_size { biggest_axis_size = data_size.1; }
+    if data_size.2 > biggest_axis_size { biggest_axis_size = data_size.2; }
+
+    let mut level = 0;
+    while (1u64 << level) < biggest_axis_size as u64 { level += 1; }
+    level
+}
+
+//Checks if a box of voxels contains any geometry. Volumes get padded to a power of two cube,
+//so anything outside of data_size is simply treated as empty space.
+pub(crate) fn region_contains_geometry(data: &[u8], data_size: (u32, u32, u32), min: (usize, usize, usize), size: usize) -> bool {
+    let max_x = cmp::min(min.0 + size, data_size.0 as usize);
+    let max_y = cmp::min(min.1 + size, data_size.1 as usize);
+    let max_z = cmp::min(min.2 + size, data_size.2 as usize);
+
+    for z in min.2 .. max_z {
+        for y in min.1 .. max_y {
+            for x in min.0 .. max_x {
+                if data[x + y * data_size.0 as usize + z * data_size.0 as usize * data_size.1 as usize] > 0 {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
 impl<'a> Octree<'a> {
-    pub fn from_voxel_data(data: &'a [u8], data_size: (u32, u32, u32), level: u32) -> Result<Self, String> {
-        if level < 2 { return Err(format!("Unable to create octree with level {}", level)); }
+    pub fn from_voxel_data(data: &'a [u8], data_size: (u32, u32, u32), level: u32) -> Result<Self, Error> {
+        if level < 2 { return Err(Error::InvalidLevel(level)); }
+        check_dimensions(data, data_size)?;
 
         //x + y * x_size + z * x_size * y_size
         let octants = vec![Octant {
@@ -78,14 +111,82 @@ impl<'a> Octree<'a> {
             position: Vec3A::new(0.0, 0.0, 0.0),
         }];
 
-        Ok(Self {
+        let mut octree = Self {
             level: level,
             current_level: (0, vec![0]),
             octants: octants,
             voxel_data: data,
             data_size: data_size,
-        })
+            cube_size: 1 << required_level(data_size),
+        };
+        if octree.check_empty(0) {
+            octree.octants[0].is_leaf = true;
+            octree.current_level.1.clear();
+        }
+
+        Ok(octree)
+    }
+
+    /// Size of the original volume, in voxels
+    pub fn size(&self) -> (u32, u32, u32) {
+        self.data_size
     }
+
+    /// Size of the power of two cube the octree spans, in voxels.
+    /// Octant positions are normalized to this cube, so multiply by it to get voxel coordinates.
+    pub fn cube_size(&self) -> u32 {
+        self.cube_size
+    }
+
+    //Queries, coordinates are in voxels
+    //The octree doesn't store materials, so these only answer occupancy, build a DAG to get materials.
+    //They only look at the octants generated so far. An octant without children that isn't a leaf
+    //is treated as solid, so the answers are exact once the octree reaches single voxels.
+    pub fn is_solid(&self, x: u32, y: u32, z: u32) -> bool {
+        if x >= self.data_size.0 || y >= self.data_size.1 || z >= self.data_size.2 { return false; }
+
+        let mut idx = 0;
+        let mut pos = (0, 0, 0);
+        let mut size = self.cube_size;
+        loop {
+            let octant = &self.octants[idx];
+            if octant.is_leaf { return false; }
+            //Octants smaller than a voxel all contain the same voxel, so just keep following the first child
+            let half = size / 2;
+            let mut j = 0;
+            if half > 0 {
+                if x >= pos.0 + half { j |= 1; pos.0 += half; }
+                if y >= pos.1 + half { j |= 2; pos.1 += half; }
+                if z >= pos.2 + half { j |= 4; pos.2 += half; }
+            }
+            size = half;
+            match octant.children[j] {
+                Some(child) => idx = child as usize,
+                None => return true,
+            }
+        }
+    }
+
+    pub fn any_solid_in(&self, bounds: &BoundingBox) -> bool {
+        self.any_solid_in_octant(0, bounds)
+    }
+
+    fn any_solid_in_octant(&self, idx: usize, bounds: &BoundingBox) -> bool {
+        let octant = &self.octants[idx];
+        if octant.is_leaf { return false; }
+
+        let size = cmp::max(self.cube_size >> cmp::min(octant.level, 31), 1) as f32;
+        let min = (octant.position * self.cube_size as f32).floor();
+        let max = min + Vec3A::splat(size);
+        if max.cmple(bounds.min).any() || min.cmpge(bounds.max).any() { return false; }
+
+        //Non-empty octants always contain geometry somewhere, so stop once we're entirely inside of the box
+        let contained = min.cmpge(bounds.min).all() && max.cmple(bounds.max).all();
+        if contained || octant.children[0].is_none() { return true; }
+
+        octant.children.iter().any(|child| self.any_solid_in_octant(child.unwrap() as usize, bounds))
+    }
+
     //Prints raw data to console
     pub fn debug_print(&self) {
         for i in 0..cmp::min(self.octants.len(), 32) {
@@ -97,31 +198,29 @@ impl<'a> Octree<'a> {
     pub fn generate_level(&mut self) {
         let mut next_level = Vec::new();
         for idx in &self.current_level.1 {
-            //Check if node contains geometry
-            if !self.octants[*idx].is_leaf {
-                if !self.check_empty(*idx) {
-                    //Node is not a leaf and is also not empty
-                    //This means its ready to be explored for the next level
-                    //We need to generate children for the current node and add those to the next_level vec
-                    let parent_pos = self.octants[*idx].position;
-                    for j in 0..8 {
-                        let child_x = (j % 2) as f32 * 0.5;
-                        let child_y = (j / 2 % 2) as f32 * 0.5;
-                        let child_z = (j / 4 % 2) as f32 * 0.5;
-                        let child_pos = Vec3A::new(child_x, child_y, child_z);
-                        let new_child_pos = parent_pos + child_pos / 2.0f32.powi(self.octants[*idx].level as i32);
-                        let child_idx = self.octants.len();
-                        self.octants.push(Octant {
-                            children: [None; 8],
-                            level: self.octants[*idx].level + 1,
-                            is_leaf: false,
-                            position: new_child_pos,
-                        });
-                        self.octants[*idx].children[j] = Some(child_idx as u32); //Could potentially lead to data loss with massive octrees, but only when said octree contains more than 4_294_967_295 nodes
-                        next_level.push(child_idx);
-                    }
+            //Only nodes containing geometry end up in the current level,
+            //so they're all ready to be explored for the next level.
+            //We need to generate children for the current node and add the non-empty ones to the next_level vec.
+            //Empty children are marked as leafs right away, so every octant always knows if it's empty.
+            let parent_pos = self.octants[*idx].position;
+            for j in 0..8 {
+                let child_x = (j % 2) as f32 * 0.5;
+                let child_y = (j / 2 % 2) as f32 * 0.5;
+                let child_z = (j / 4 % 2) as f32 * 0.5;
+                let child_pos = Vec3A::new(child_x, child_y, child_z);
+                let new_child_pos = parent_pos + child_pos / 2.0f32.powi(self.octants[*idx].level as i32);
+                let child_idx = self.octants.len();
+                self.octants.push(Octant {
+                    children: [None; 8],
+                    level: self.octants[*idx].level + 1,
+                    is_leaf: false,
+                    position: new_child_pos,
+                });
+                self.octants[*idx].children[j] = Some(child_idx as u32); //Could potentially lead to data loss with massive octrees, but only when said octree contains more than 4_294_967_295 nodes
+                if self.check_empty(child_idx) {
+                    self.octants[child_idx].is_leaf = true;
                 } else {
-                    self.octants[*idx].is_leaf = true;
+                    next_level.push(child_idx);
                 }
             }
         }
@@ -130,34 +229,15 @@ impl<'a> Octree<'a> {
     }
 
     fn check_empty(&self, idx: usize) -> bool {
-        let mut biggest_axis_size = self.data_size.0;
-        if self.data_size.1 > biggest_axis_size { biggest_axis_size = self.data_size.1; }
-        if self.data_size.2 > biggest_axis_size { biggest_axis_size = self.data_size.2; }
-
         let node = &self.octants[idx];
         let pos = node.position;
-        let node_size = biggest_axis_size as f32 / 2.0f32.powi(node.level as i32);
-        let vox_space_pos = (pos * biggest_axis_size as f32).floor();
+        let node_size = self.cube_size as f32 / 2.0f32.powi(node.level as i32);
+        let vox_space_pos = (pos * self.cube_size as f32).floor();
 
         trace!("top_left: {}", vox_space_pos.x() as usize);
         trace!("size:     {}", node_size as usize);
 
-        let ux = vox_space_pos.x() as usize;
-        let uy = vox_space_pos.y() as usize;
-        let uz = vox_space_pos.z() as usize;
-        let us = node_size as usize;
-
-        for x in ux .. ux + us {
-            for y in uy .. uy + us {
-                for z in uz .. uz + us {
-                    if self.voxel_data[x + y * self.data_size.0 as usize + z * self.data_size.0 as usize * self.data_size.1 as usize] > 0 {
-                        trace!("Found a voxel containing geometry!");
-                        return false;
-                    }
-                }
-            }
-        }
-
-        true
+        let min = (vox_space_pos.x() as usize, vox_space_pos.y() as usize, vox_space_pos.z() as usize);
+        !region_contains_geometry(self.voxel_data, self.data_size, min, cmp::max(node_size as usize, 1))
     }
 }
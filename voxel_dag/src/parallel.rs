@@ -0,0 +1,107 @@
+use rayon::prelude::*;
+
+use std::cmp;
+use std::time::Instant;
+
+use crate::dag::{DAG, Level, reduce_to_root, merge_levels, layout};
+use crate::error::{Error, check_dimensions};
+use crate::octree::required_level;
+use crate::attributes::{self, morton_decode};
+use crate::bricks::LeafFormat;
+
+//Parallel DAG construction.
+//The volume is split into pow(8, SPLIT_LEVELS) aligned subtrees, which are reduced on the rayon thread pool,
+//each into its own levels. The subtrees are then merged into one set of levels, in a fixed order,
+//before reducing the last few levels above them. Merging only touches the unique nodes of every subtree,
+//so it's cheap compared to reducing the voxels themselves.
+//The layout doesn't depend on the order nodes were found in (see layout in src/dag.rs), so the
+//result is identical to DAG::from_voxel_data, no matter how the work was scheduled.
+
+//Levels above the subtrees, 2 gives 64 subtrees to spread over the threads
+const SPLIT_LEVELS: u32 = 2;
+
+impl DAG {
+    /// Same as from_voxel_data, but spread over all CPU cores.
+    pub fn from_voxel_data_parallel(data: &[u8], data_size: (u32, u32, u32)) -> Result<Self, Error> {
+        check_dimensions(data, data_size)?;
+
+        let depth = cmp::max(required_level(data_size), 1);
+        //Subtrees need at least a leaf level of their own
+        let split = cmp::min(SPLIT_LEVELS, depth - 1);
+        if split == 0 {
+            return Self::from_voxel_data(data, data_size);
+        }
+
+        let now = Instant::now();
+
+        let material = |x: u32, y: u32, z: u32| -> u8 {
+            if x >= data_size.0 || y >= data_size.1 || z >= data_size.2 { return 0; }
+            data[x as usize + y as usize * data_size.0 as usize + z as usize * data_size.0 as usize * data_size.1 as usize]
+        };
+
+        let res = 1u32 << split; //Subtrees per axis
+        let subtree_level = depth - split;
+        let subtree_size = 1u32 << subtree_level;
+        let subtrees: Vec<(Vec<Level>, u32)> = (0..res * res * res).into_par_iter().map(|i| {
+            let min = (i % res * subtree_size, i / res % res * subtree_size, i / (res * res) * subtree_size);
+            let mut levels: Vec<Level> = (0..subtree_level).map(|_| Level::default()).collect();
+            let leaf_res = (subtree_size / 2) as usize;
+            let grid = levels[0].reduce_leaves(leaf_res, min, |x, y, z| material(x, y, z) > 0);
+            let root = reduce_to_root(&mut levels, 0, grid, leaf_res);
+            (levels, root)
+        }).collect();
+
+        //Merging in index order keeps the node ids deterministic as well
+        let mut levels: Vec<Level> = (0..depth).map(|_| Level::default()).collect();
+        let mut grid = Vec::with_capacity(subtrees.len());
+        for (local, root) in subtrees {
+            grid.push(merge_levels(&mut levels, &local, root));
+        }
+        let root = reduce_to_root(&mut levels, (subtree_level - 1) as usize, grid, res as usize);
+        let data = layout(&levels, root)?;
+
+        //Attribute order visits the subtrees in Morton order, so every subtree's materials can be gathered on their own
+        let subtree_materials: Vec<Vec<u8>> = (0..(res * res * res) as u64).into_par_iter().map(|code| {
+            let pos = morton_decode(code, split);
+            let min = (pos.0 * subtree_size, pos.1 * subtree_size, pos.2 * subtree_size);
+            match find_node(&data, depth, split, min) {
+                Some(idx) => attributes::gather_subtree_materials(&data, depth, LeafFormat::Voxels, idx, split, min, material),
+                None => Vec::new(),
+            }
+        }).collect();
+        let materials = subtree_materials.concat();
+        let voxel_counts = attributes::count_voxels(&data, depth, LeafFormat::Voxels)?;
+
+        let duration = Instant::now() - now;
+        debug!("Time to generate DAG in parallel: {}ms", duration.as_millis());
+        debug!("Node count: {}", data.len() / 2);
+
+        Ok(Self {
+            data,
+            depth,
+            size: data_size,
+            block_lookup: None,
+            voxel_counts,
+            materials,
+            leaves: LeafFormat::Voxels,
+        })
+    }
+}
+
+//Index of the node at the given level that contains voxel pos, or None if it's empty
+fn find_node(data: &[u32], depth: u32, level: u32, pos: (u32, u32, u32)) -> Option<u32> {
+    let mut idx = 0;
+    for l in 0..level {
+        let half = 1 << (depth - l - 1);
+        let mut j = 0;
+        if pos.0 & half != 0 { j |= 1; }
+        if pos.1 & half != 0 { j |= 2; }
+        if pos.2 & half != 0 { j |= 4; }
+
+        let childmask = data[idx as usize * 2];
+        let child = data[idx as usize * 2 + 1];
+        if childmask & (0x0000_0001 << j) == 0 { return None; }
+        idx = child + (childmask & ((0x0000_0001 << j) - 1)).count_ones();
+    }
+    Some(idx)
+}
@@ -0,0 +1,99 @@
+use glam::*;
+
+use crate::aabb::BoundingBox;
+use crate::dag::DAG;
+use crate::bricks::{LeafFormat, leaf_bits, leaf_voxel, leaf_voxel_index};
+
+//Queries on the packed node layout from src/dag.rs, shared by DAG and VoxelDAG.
+//They only descend the node buffer, so they keep working after the dense voxel data is dropped.
+
+//Material of every solid voxel in a DAG that was loaded without materials
+pub(crate) const SOLID_MATERIAL: u8 = 1;
+
+/// Finds the leaf node and leaf voxel index a cell ends up in, or None if the cell is empty.
+pub(crate) fn find_leaf(data: &[u32], depth: u32, leaves: LeafFormat, pos: (u32, u32, u32)) -> Option<(u32, u32)> {
+    let cube_size = 1u64 << depth;
+    if pos.0 as u64 >= cube_size || pos.1 as u64 >= cube_size || pos.2 as u64 >= cube_size { return None; }
+
+    let mut idx = 0;
+    for level in 0..leaves.leaf_level(depth) {
+        let half = 1 << (depth - level - 1);
+        let mut j = 0;
+        if pos.0 & half != 0 { j |= 1; }
+        if pos.1 & half != 0 { j |= 2; }
+        if pos.2 & half != 0 { j |= 4; }
+
+        let childmask = data[idx as usize * 2];
+        let child = data[idx as usize * 2 + 1];
+        if childmask & (0x0000_0001 << j) == 0 { return None; }
+        idx = child + (childmask & ((0x0000_0001 << j) - 1)).count_ones();
+    }
+
+    let i = leaf_voxel_index(pos, leaves);
+    if leaf_bits(data, idx, leaves) & (1u64 << i) == 0 { return None; }
+    Some((idx, i))
+}
+
+/// Checks if any solid cell overlaps the box [min, max), in cell coordinates.
+pub(crate) fn any_solid_in(data: &[u32], depth: u32, leaves: LeafFormat, min: Vec3A, max: Vec3A) -> bool {
+    any_solid_in_node(data, depth, leaves, 0, 0, (0, 0, 0), &[[min.x(), min.y(), min.z()], [max.x(), max.y(), max.z()]])
+}
+
+//bounds is [min, max]
+fn any_solid_in_node(data: &[u32], depth: u32, leaves: LeafFormat, idx: u32, level: u32, pos: (u32, u32, u32), bounds: &[[f32; 3]; 2]) -> bool {
+    let [min, max] = bounds;
+    if level == leaves.leaf_level(depth) {
+        let bits = leaf_bits(data, idx, leaves);
+        return (0..leaves.leaf_voxels()).any(|i| {
+            let voxel = leaf_voxel(i, leaves);
+            let lo = [(pos.0 + voxel.0) as f32, (pos.1 + voxel.1) as f32, (pos.2 + voxel.2) as f32];
+            bits & (1u64 << i) != 0 && (0..3).all(|a| lo[a] + 1.0 > min[a] && lo[a] < max[a])
+        });
+    }
+
+    let childmask = data[idx as usize * 2];
+    let child = data[idx as usize * 2 + 1];
+    let half = 1u32 << (depth - level - 1);
+
+    for j in 0..8 {
+        if childmask & (0x0000_0001 << j) == 0 { continue; }
+        let child_pos = (pos.0 + (j % 2) * half, pos.1 + (j / 2 % 2) * half, pos.2 + (j / 4 % 2) * half);
+        let lo = [child_pos.0 as f32, child_pos.1 as f32, child_pos.2 as f32];
+        let hi = [lo[0] + half as f32, lo[1] + half as f32, lo[2] + half as f32];
+
+        let mut overlaps = true;
+        let mut contained = true;
+        for i in 0..3 {
+            if hi[i] <= min[i] || lo[i] >= max[i] { overlaps = false; }
+            if lo[i] < min[i] || hi[i] > max[i] { contained = false; }
+        }
+        if !overlaps { continue; }
+
+        //Every non-empty node contains at least one solid cell, so we can stop early
+        //when it lies entirely inside of the box
+        if contained { return true; }
+
+        let child_idx = child + (childmask & ((0x0000_0001 << j) - 1)).count_ones();
+        if any_solid_in_node(data, depth, leaves, child_idx, level + 1, child_pos, bounds) {
+            return true;
+        }
+    }
+    false
+}
+
+impl DAG {
+    //Queries, coordinates are in voxels
+    pub fn get(&self, x: u32, y: u32, z: u32) -> Option<u8> {
+        let size = self.size();
+        if x >= size.0 || y >= size.1 || z >= size.2 { return None; }
+        self.attribute_index(x, y, z).map(|idx| self.materials[idx as usize])
+    }
+
+    pub fn is_solid(&self, x: u32, y: u32, z: u32) -> bool {
+        self.get(x, y, z).is_some()
+    }
+
+    pub fn any_solid_in(&self, bounds: &BoundingBox) -> bool {
+        any_solid_in(self.get_data(), self.depth(), self.leaves, bounds.min, bounds.max)
+    }
+}
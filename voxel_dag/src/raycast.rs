@@ -0,0 +1,148 @@
+use glam::*;
+
+use crate::dag::DAG;
+use crate::bricks::{leaf_bits, leaf_voxel, leaf_rank};
+
+//Ray traversal over the packed node buffer.
+//Nodes don't store their position, so it's determined by the path we take down the DAG.
+//Children are visited front to back by flipping the child index with the sign bits of the
+//ray direction (see https://daeken.svbtle.com/a-stupidly-simple-fast-octree-traversal-for-ray-intersection),
+//which means the first voxel we hit is always the closest one.
+//Everything is in voxel space, the DAG spans [0, cube_size) on every axis.
+
+pub struct RayHit {
+    pub distance: f32,
+    pub voxel: (u32, u32, u32),
+    pub normal: Vec3, //Normal of the face the ray entered through, zero if the ray started inside the voxel
+    pub material: u8,
+}
+
+pub(crate) struct Ray {
+    pub(crate) origin: [f32; 3],
+    pub(crate) dir: [f32; 3],
+    pub(crate) max_t: f32,
+    pub(crate) octant_mask: usize, //Child index bits to flip, so children are visited front to back
+}
+
+impl DAG {
+    /// Finds the closest solid voxel along a ray, up to max_t along dir.
+    /// Returns None if dir is zero, infinite or NaN.
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<RayHit> {
+        let ray = Ray::new(origin, dir, max_t)?;
+        self.raycast_node(&ray, 0, 0, (0, 0, 0), 0)
+    }
+
+    //attribute_offset is the attribute index of the first voxel below this node
+    fn raycast_node(&self, ray: &Ray, idx: u32, level: u32, pos: (u32, u32, u32), attribute_offset: u32) -> Option<RayHit> {
+        if level == self.leaf_level() {
+            return self.raycast_leaf(ray, idx, pos, attribute_offset);
+        }
+
+        let node = self.get_node(idx);
+        let half = 1 << (self.depth() - level - 1);
+
+        for i in 0..8 {
+            let j = i ^ ray.octant_mask;
+            if node.childmask & (0x0000_0001 << j) == 0 { continue; }
+
+            let child_pos = (
+                pos.0 + (j as u32 % 2) * half,
+                pos.1 + (j as u32 / 2 % 2) * half,
+                pos.2 + (j as u32 / 4 % 2) * half,
+            );
+            if ray.intersect(child_pos, half).is_none() { continue; }
+
+            let (child_idx, child_offset) = self.child_attribute_offset(node.childmask, node.child, j as u32, attribute_offset);
+            if let Some(hit) = self.raycast_node(ray, child_idx, level + 1, child_pos, child_offset) {
+                return Some(hit);
+            }
+        }
+
+        None
+    }
+
+    //Leaf voxel indices are Morton codes, so flipping every bit of an axis orders them front to back as well
+    fn raycast_leaf(&self, ray: &Ray, idx: u32, pos: (u32, u32, u32), attribute_offset: u32) -> Option<RayHit> {
+        let bits = leaf_bits(&self.data, idx, self.leaves);
+        let flip = self.leaves.octant_flip(ray.octant_mask as u32);
+
+        for i in 0..self.leaves.leaf_voxels() {
+            let j = i ^ flip;
+            if bits & (1u64 << j) == 0 { continue; }
+
+            let voxel = leaf_voxel(j, self.leaves);
+            let voxel = (pos.0 + voxel.0, pos.1 + voxel.1, pos.2 + voxel.2);
+            if let Some((t_near, axis)) = ray.intersect(voxel, 1) {
+                let material = self.materials[(attribute_offset + leaf_rank(bits, j)) as usize];
+                return Some(ray.hit(t_near, axis, voxel, material));
+            }
+        }
+
+        None
+    }
+}
+
+impl Ray {
+    //None for directions that can't be normalized, they'd turn every slab test into NaN
+    pub(crate) fn new(origin: Vec3, dir: Vec3, max_t: f32) -> Option<Self> {
+        let dir = dir.normalize();
+        if !(dir.x().is_finite() && dir.y().is_finite() && dir.z().is_finite()) || dir.length_squared() == 0.0 {
+            return None;
+        }
+        let mut octant_mask = 0;
+        if dir.x() < 0.0 { octant_mask |= 1; }
+        if dir.y() < 0.0 { octant_mask |= 2; }
+        if dir.z() < 0.0 { octant_mask |= 4; }
+
+        Some(Self {
+            origin: [origin.x(), origin.y(), origin.z()],
+            dir: [dir.x(), dir.y(), dir.z()],
+            max_t,
+            octant_mask,
+        })
+    }
+
+    pub(crate) fn hit(&self, distance: f32, axis: Option<usize>, voxel: (u32, u32, u32), material: u8) -> RayHit {
+        let mut normal = [0.0; 3];
+        if let Some(axis) = axis {
+            normal[axis] = -self.dir[axis].signum();
+        }
+        RayHit {
+            distance,
+            voxel,
+            normal: Vec3::new(normal[0], normal[1], normal[2]),
+            material,
+        }
+    }
+
+    //Slab test against an axis aligned cube. Returns the entry distance and the axis we entered through,
+    //or None for the axis if the ray starts inside of the cube.
+    pub(crate) fn intersect(&self, min: (u32, u32, u32), size: u32) -> Option<(f32, Option<usize>)> {
+        let min = [min.0 as f32, min.1 as f32, min.2 as f32];
+        let mut t_near = f32::NEG_INFINITY;
+        let mut t_far = f32::INFINITY;
+        let mut axis = None;
+
+        for (i, &lo) in min.iter().enumerate() {
+            let hi = lo + size as f32;
+            if self.dir[i] == 0.0 {
+                if self.origin[i] < lo || self.origin[i] >= hi { return None; }
+                continue;
+            }
+            let t0 = (lo - self.origin[i]) / self.dir[i];
+            let t1 = (hi - self.origin[i]) / self.dir[i];
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            if t0 > t_near {
+                t_near = t0;
+                axis = Some(i);
+            }
+            if t1 < t_far { t_far = t1; }
+        }
+
+        if t_near > t_far || t_far < 0.0 || t_near > self.max_t { return None; }
+        if t_near < 0.0 {
+            return Some((0.0, None));
+        }
+        Some((t_near, axis))
+    }
+}
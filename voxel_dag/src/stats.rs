@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use crate::dag::DAG;
+use crate::error::Error;
+use crate::bricks::{LeafFormat, leaf_bits};
+
+//Diagnostics for node buffers. Stats describe how well a DAG compresses, and validate checks that a buffer
+//can be traversed safely, so broken buffers get caught before they're uploaded to the GPU.
+//Nodes don't know their own level, so both walk down from the root. The same node can be referenced from
+//many parents, which is exactly what the reference counts measure.
+
+/// Overview of a DAG, see DAG::stats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DagStats {
+    /// Unique nodes on every level, starting at the root
+    pub nodes_per_level: Vec<u32>,
+    /// References to the nodes on every level, which is how many nodes an octree would need there
+    pub references_per_level: Vec<u64>,
+    pub unique_nodes: u32,
+    pub referenced_nodes: u64,
+    /// Unique leaf nodes, these are bricks for DAGs with brick leaves
+    pub leaf_count: u32,
+    /// Deepest level that has a node, the root is level 0
+    pub max_depth: u32,
+    pub voxel_count: u32,
+    /// Size of the node buffer, including nodes that are no longer reachable
+    pub node_bytes: usize,
+    /// Size of the voxel count and material buffers
+    pub attribute_bytes: usize,
+}
+
+impl DagStats {
+    /// Referenced nodes divided by unique nodes, higher is better.
+    pub fn dedup_ratio(&self) -> f64 {
+        self.referenced_nodes as f64 / self.unique_nodes as f64
+    }
+}
+
+impl DAG {
+    /// Counts the nodes reachable from the root. Assumes the buffer is valid, see validate.
+    pub fn stats(&self) -> DagStats {
+        let mut nodes_per_level = Vec::new();
+        let mut references_per_level = Vec::new();
+
+        let mut level_nodes: HashMap<u32, u64> = HashMap::new();
+        level_nodes.insert(0, 1);
+        for level in 0..=self.leaf_level() {
+            nodes_per_level.push(level_nodes.len() as u32);
+            references_per_level.push(level_nodes.values().fold(0u64, |sum, refs| sum.saturating_add(*refs)));
+            if level == self.leaf_level() { break; }
+
+            let mut next = HashMap::new();
+            for (idx, refs) in &level_nodes {
+                let childmask = self.data[*idx as usize * 2];
+                let child = self.data[*idx as usize * 2 + 1];
+                for i in 0..childmask.count_ones() {
+                    let child_refs = next.entry(child + i).or_insert(0u64);
+                    *child_refs = child_refs.saturating_add(*refs);
+                }
+            }
+            if next.is_empty() { break; }
+            level_nodes = next;
+        }
+
+        let leaf_count = if nodes_per_level.len() as u32 == self.leaf_level() + 1 { *nodes_per_level.last().unwrap() } else { 0 };
+        DagStats {
+            unique_nodes: nodes_per_level.iter().sum(),
+            referenced_nodes: references_per_level.iter().fold(0u64, |sum, refs| sum.saturating_add(*refs)),
+            leaf_count,
+            max_depth: (nodes_per_level.len() - 1) as u32,
+            voxel_count: self.voxel_counts[0],
+            node_bytes: self.data.len() * 4,
+            attribute_bytes: self.voxel_counts.len() * 4 + self.materials.len(),
+            nodes_per_level,
+            references_per_level,
+        }
+    }
+
+    /// Checks that every child index is in range, every interior node has children, there are no cycles,
+    /// and that the voxel counts and materials match the nodes.
+    pub fn validate(&self) -> Result<(), Error> {
+        let node_count = self.data.len() / 2;
+        if self.voxel_counts.len() != node_count {
+            return Err(Error::DimensionMismatch {
+                expected: node_count,
+                found: self.voxel_counts.len(),
+            });
+        }
+
+        let voxel_count = Checker::new(&self.data, self.depth, self.leaves, Some(&self.voxel_counts)).check()?;
+        if self.materials.len() != voxel_count as usize {
+            return Err(Error::DimensionMismatch {
+                expected: voxel_count as usize,
+                found: self.materials.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a node buffer can be traversed, without needing voxel counts. Used before loading untrusted buffers.
+pub(crate) fn check_nodes(data: &[u32], depth: u32, leaves: LeafFormat) -> Result<(), Error> {
+    Checker::new(data, depth, leaves, None).check().map(|_| ())
+}
+
+struct Checker<'a> {
+    data: &'a [u32],
+    leaf_level: u32,
+    leaves: LeafFormat,
+    voxel_counts: Option<&'a [u32]>, //Compared against the actual counts, if there are any
+    counts: HashMap<(u32, u32), u32>, //Voxels below every (node, level) that was checked already
+    on_path: Vec<bool>, //Interior nodes above the current one
+}
+
+impl<'a> Checker<'a> {
+    fn new(data: &'a [u32], depth: u32, leaves: LeafFormat, voxel_counts: Option<&'a [u32]>) -> Self {
+        Self {
+            data,
+            leaf_level: leaves.leaf_level(depth),
+            leaves,
+            voxel_counts,
+            counts: HashMap::new(),
+            on_path: vec![false; data.len() / 2],
+        }
+    }
+
+    //Returns the amount of solid voxels in the DAG
+    fn check(&mut self) -> Result<u32, Error> {
+        if self.data.len() < 2 || !self.data.len().is_multiple_of(2) {
+            return Err(invalid(0, "The node buffer has to hold at least the root, as pairs of u32s"));
+        }
+        self.node(0, 0)
+    }
+
+    fn node(&mut self, idx: u32, level: u32) -> Result<u32, Error> {
+        if let Some(count) = self.counts.get(&(idx, level)) {
+            return Ok(*count);
+        }
+
+        let childmask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1];
+        let count = if level == self.leaf_level {
+            if self.leaves == LeafFormat::Voxels && (childmask > 0xFF || child != 0) {
+                return Err(invalid(idx, "Leaf uses more than its 8 voxel bits"));
+            }
+            leaf_bits(self.data, idx, self.leaves).count_ones()
+        } else if childmask > 0xFF {
+            return Err(invalid(idx, "Childmask uses more than 8 bits"));
+        } else if childmask == 0 {
+            //The root of an empty DAG is the only interior node without children
+            if idx != 0 || level != 0 { return Err(invalid(idx, "Interior node has an empty childmask")); }
+            0
+        } else {
+            let child_count = childmask.count_ones();
+            if child == 0 || child as usize + child_count as usize > self.on_path.len() {
+                return Err(invalid(idx, "Child index is out of range"));
+            }
+
+            self.on_path[idx as usize] = true;
+            let mut sum: u32 = 0;
+            for i in 0..child_count {
+                //Leaves are never descended into, so only interior nodes can close a cycle
+                if level + 1 < self.leaf_level && self.on_path[(child + i) as usize] {
+                    return Err(invalid(child + i, "Node is its own ancestor"));
+                }
+                let child_voxels = self.node(child + i, level + 1)?;
+                sum = sum.checked_add(child_voxels).ok_or(Error::NodeIndexOverflow)?;
+            }
+            self.on_path[idx as usize] = false;
+            sum
+        };
+
+        if let Some(voxel_counts) = self.voxel_counts {
+            if voxel_counts[idx as usize] != count {
+                return Err(invalid(idx, "Voxel count doesn't match the voxels below the node"));
+            }
+        }
+        self.counts.insert((idx, level), count);
+        Ok(count)
+    }
+}
+
+fn invalid(node: u32, reason: &str) -> Error {
+    Error::InvalidNode {
+        node,
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,169 @@
+use std::cmp;
+use std::io::Write;
+use std::time::Instant;
+
+use crate::dag::{DAG, Level, EMPTY, reduce_to_root, layout};
+use crate::error::Error;
+use crate::octree::required_level;
+use crate::stats;
+use crate::attributes::{self, morton_encode};
+use crate::bricks::LeafFormat;
+
+//Out-of-core DAG construction, for volumes that don't fit in memory as a single dense array.
+//The volume is fed in as cubic bricks instead, which can be generated or loaded one at a time.
+//Every brick gets reduced into the same levels from_voxel_data uses, so nodes are deduplicated
+//across bricks as well, and only unique nodes stay in memory. Once every brick is in, the grid of
+//brick roots is reduced into the final DAG. Bricks that are never added are empty.
+//
+//Attribute order (see src/attributes.rs) visits solid voxels in Morton order, with x in the lowest bit,
+//so the materials of a brick are contiguous. Bricks have to be added in Morton order as well, which is the
+//order brick_positions returns them in, so the materials of every brick can be written straight to the sink.
+//That way they never have to be in memory all at once either.
+
+/// A cube of brick_size voxels per axis, indexed like from_voxel_data.
+/// Voxels outside of the volume are ignored.
+pub struct ChunkBlock {
+    pub min: (u32, u32, u32), //First voxel of the brick, has to be a multiple of the brick size
+    pub data: Vec<u8>,
+}
+
+pub struct StreamingBuilder<W: Write> {
+    size: (u32, u32, u32),
+    depth: u32,
+    brick_size: u32,
+    brick_level: u32, //Levels inside of a brick, brick_size is pow(2, brick_level)
+    levels: Vec<Level>, //Bottom-up, shared by all bricks
+    roots: Vec<u32>, //Root node of every brick, in the level just above the brick levels
+    next_brick: u64, //Lowest Morton code the next brick can have
+    materials: W, //Sink for the materials of every brick, in attribute order
+    start: Instant,
+}
+
+impl<W: Write> StreamingBuilder<W> {
+    /// The materials of every solid voxel get written to materials as the bricks are added, one byte each.
+    pub fn new(data_size: (u32, u32, u32), brick_size: u32, materials: W) -> Result<Self, Error> {
+        if !brick_size.is_power_of_two() {
+            return Err(Error::NonPowerOfTwo(brick_size));
+        }
+        let depth = cmp::max(required_level(data_size), 1);
+        let brick_level = brick_size.trailing_zeros();
+        if brick_level < 1 || brick_level > depth {
+            return Err(Error::InvalidLevel(brick_level));
+        }
+
+        let res = 1usize << (depth - brick_level);
+        Ok(Self {
+            size: data_size,
+            depth,
+            brick_size,
+            brick_level,
+            levels: (0..depth).map(|_| Level::default()).collect(),
+            roots: vec![EMPTY; res * res * res],
+            next_brick: 0,
+            materials,
+            start: Instant::now(),
+        })
+    }
+
+    /// Position of every brick that overlaps the volume, in the order they have to be added in.
+    pub fn brick_positions(&self) -> Vec<(u32, u32, u32)> {
+        let bits = self.depth - self.brick_level;
+        let mut positions = Vec::new();
+        for z in (0..self.size.2).step_by(self.brick_size as usize) {
+            for y in (0..self.size.1).step_by(self.brick_size as usize) {
+                for x in (0..self.size.0).step_by(self.brick_size as usize) {
+                    positions.push((x, y, z));
+                }
+            }
+        }
+        positions.sort_by_key(|pos| morton_encode((pos.0 / self.brick_size, pos.1 / self.brick_size, pos.2 / self.brick_size), bits));
+        positions
+    }
+
+    pub fn brick_size(&self) -> u32 {
+        self.brick_size
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Adds a brick, which has to come after every brick added so far in brick_positions.
+    /// Bricks can be skipped, they're empty.
+    pub fn add_brick(&mut self, brick: ChunkBlock) -> Result<(), Error> {
+        let b = self.brick_size as usize;
+        if brick.data.len() != b * b * b {
+            return Err(Error::DimensionMismatch {
+                expected: b * b * b,
+                found: brick.data.len(),
+            });
+        }
+
+        let min = brick.min;
+        let res = 1u32 << (self.depth - self.brick_level);
+        let (bx, by, bz) = (min.0 / self.brick_size, min.1 / self.brick_size, min.2 / self.brick_size);
+        let aligned = min.0.is_multiple_of(self.brick_size) && min.1.is_multiple_of(self.brick_size) && min.2.is_multiple_of(self.brick_size);
+        if !aligned || bx >= res || by >= res || bz >= res {
+            return Err(Error::InvalidBrick(min));
+        }
+        let code = morton_encode((bx, by, bz), self.depth - self.brick_level);
+        if code < self.next_brick {
+            return Err(Error::InvalidBrick(min));
+        }
+        self.next_brick = code + 1;
+        let idx = (bx + by * res + bz * res * res) as usize;
+
+        let size = self.size;
+        let material = |x: u32, y: u32, z: u32| -> u8 {
+            if x >= size.0 || y >= size.1 || z >= size.2 { return 0; }
+            let (lx, ly, lz) = ((x - min.0) as usize, (y - min.1) as usize, (z - min.2) as usize);
+            brick.data[lx + ly * b + lz * b * b]
+        };
+
+        let grid = self.levels[0].reduce_leaves(b / 2, min, |x, y, z| material(x, y, z) > 0);
+        self.roots[idx] = reduce_to_root(&mut self.levels, 0, grid, b / 2);
+
+        let materials = attributes::gather_cube_materials(min, self.brick_level, material);
+        self.materials.write_all(&materials)?;
+        Ok(())
+    }
+
+    /// Reduces the brick roots into the final node buffer, with voxel leaves, and returns it together with the sink.
+    /// DAG::from_parts turns both back into a DAG, once the materials fit in memory.
+    pub fn finish(mut self) -> Result<(Vec<u32>, W), Error> {
+        let res = 1usize << (self.depth - self.brick_level);
+        let roots = std::mem::take(&mut self.roots);
+        let root = reduce_to_root(&mut self.levels, (self.brick_level - 1) as usize, roots, res);
+        let data = layout(&self.levels, root)?;
+        self.materials.flush()?;
+
+        let duration = Instant::now() - self.start;
+        debug!("Time to stream DAG: {}ms", duration.as_millis());
+        debug!("Node count: {}", data.len() / 2);
+
+        Ok((data, self.materials))
+    }
+}
+
+impl DAG {
+    /// Builds a DAG out of bricks, without ever holding the whole volume in memory.
+    /// The bricks have to be in the order of StreamingBuilder::brick_positions.
+    pub fn from_bricks<I: IntoIterator<Item = ChunkBlock>>(data_size: (u32, u32, u32), brick_size: u32, bricks: I) -> Result<Self, Error> {
+        let mut builder = StreamingBuilder::new(data_size, brick_size, Vec::new())?;
+        let depth = builder.depth();
+        for brick in bricks {
+            builder.add_brick(brick)?;
+        }
+        let (data, materials) = builder.finish()?;
+        DAG::from_raw(data, depth, data_size, LeafFormat::Voxels, Some(materials))
+    }
+
+    /// Puts a node buffer back together with the material of every solid voxel, in attribute order.
+    /// Everything is validated first, so both can come from anywhere.
+    pub fn from_parts(data: Vec<u32>, depth: u32, size: (u32, u32, u32), leaves: LeafFormat, materials: Vec<u8>) -> Result<Self, Error> {
+        stats::check_nodes(&data, depth, leaves)?;
+        let dag = DAG::from_raw(data, depth, size, leaves, Some(materials))?;
+        dag.validate()?;
+        Ok(dag)
+    }
+}
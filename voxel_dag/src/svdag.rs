@@ -0,0 +1,202 @@
+//! Binary file format for baked DAGs, so assets don't have to be rebuilt on every launch.
+//!
+//! Every value is stored little endian. A `.svdag` file looks like this:
+//!
+//! | Field         | Type                | Description                                          |
+//! |---------------|---------------------|------------------------------------------------------|
+//! | magic         | `[u8; 4]`           | Always `b"SVDG"`                                     |
+//! | version       | `u32`               | Format version, currently `2`                        |
+//! | size          | `[u32; 3]`          | Original extents of the volume, in voxels            |
+//! | depth         | `u32`               | Levels below the root, the DAG spans `2^depth` voxels |
+//! | leaf format   | `u32`               | `0` for 2x2x2 voxel leaves, `1` for 4x4x4 bricks     |
+//! | node count    | `u32`               | Amount of nodes in the node buffer                   |
+//! | nodes         | `[u32; 2 * count]`  | Packed `[childmask, first_child]` node buffer        |
+//! | section count | `u32`               | Amount of attribute sections that follow             |
+//! | sections      | see below           |                                                      |
+//! | checksum      | `u32`               | CRC32 of every byte before it                        |
+//!
+//! Every attribute section starts with a 4 byte tag and a `u32` byte length, followed by its payload.
+//! Sections with unknown tags are skipped when loading, so new ones can be added without a version bump.
+//! Version `1` files have no leaf format field, and always use voxel leaves.
+//!
+//! | Tag    | Payload                                                                      |
+//! |--------|------------------------------------------------------------------------------|
+//! | `MATL` | One `u8` material per solid voxel, in attribute order (see src/attributes.rs) |
+
+use std::fs::File;
+use std::io::{Read, Write, BufReader, BufWriter};
+use std::path::Path;
+
+use crate::dag::DAG;
+use crate::error::Error;
+use crate::bricks::LeafFormat;
+use crate::stats;
+use crate::octree::required_level;
+
+pub const MAGIC: [u8; 4] = *b"SVDG";
+pub const VERSION: u32 = 2;
+
+pub const MATERIAL_TAG: [u8; 4] = *b"MATL";
+
+//Deepest DAG a u32 coordinate can address
+const MAX_DEPTH: u32 = 31;
+
+/// A tagged block of extra data stored next to the node buffer.
+pub struct Section {
+    pub tag: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+/// Writes a DAG in the `.svdag` format.
+pub fn write<W: Write>(dag: &DAG, sections: &[Section], writer: &mut W) -> Result<(), Error> {
+    let nodes = dag.get_data();
+    let mut bytes = Vec::with_capacity(32 + nodes.len() * 4);
+
+    bytes.extend_from_slice(&MAGIC);
+    push_u32(&mut bytes, VERSION);
+    let size = dag.size();
+    push_u32(&mut bytes, size.0);
+    push_u32(&mut bytes, size.1);
+    push_u32(&mut bytes, size.2);
+    push_u32(&mut bytes, dag.depth());
+    push_u32(&mut bytes, match dag.leaf_format() {
+        LeafFormat::Voxels => 0,
+        LeafFormat::Bricks => 1,
+    });
+    push_u32(&mut bytes, (nodes.len() / 2) as u32);
+    for value in nodes {
+        push_u32(&mut bytes, *value);
+    }
+
+    push_u32(&mut bytes, sections.len() as u32);
+    for section in sections {
+        if section.data.len() > u32::MAX as usize {
+            return Err(Error::Serialization(format!("Section {:?} is too large", section.tag)));
+        }
+        bytes.extend_from_slice(&section.tag);
+        push_u32(&mut bytes, section.data.len() as u32);
+        bytes.extend_from_slice(&section.data);
+    }
+
+    let checksum = crc32fast::hash(&bytes);
+    push_u32(&mut bytes, checksum);
+
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Reads a DAG in the `.svdag` format, along with all of its sections.
+pub fn read<R: Read>(reader: &mut R) -> Result<(DAG, Vec<Section>), Error> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    if bytes.len() < 4 {
+        return Err(Error::Serialization("File is too short".to_string()));
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32fast::hash(body) != u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) {
+        return Err(Error::Serialization("Checksum mismatch".to_string()));
+    }
+
+    let mut cursor = Cursor { bytes: body, pos: 0 };
+    if cursor.take(4)? != &MAGIC[..] {
+        return Err(Error::Serialization("Not an svdag file".to_string()));
+    }
+    let version = cursor.u32()?;
+    if !(1..=VERSION).contains(&version) {
+        return Err(Error::Serialization(format!("Unsupported version {}", version)));
+    }
+
+    let size = (cursor.u32()?, cursor.u32()?, cursor.u32()?);
+    let depth = cursor.u32()?;
+    let leaves = match if version >= 2 { cursor.u32()? } else { 0 } {
+        0 => LeafFormat::Voxels,
+        1 => LeafFormat::Bricks,
+        format => return Err(Error::Serialization(format!("Unknown leaf format {}", format))),
+    };
+    if depth < leaves.leaf_levels() || depth > MAX_DEPTH {
+        return Err(Error::InvalidLevel(depth));
+    }
+    if required_level(size) > depth {
+        return Err(Error::Serialization(format!("Size {:?} doesn't fit in a DAG of depth {}", size, depth)));
+    }
+
+    let node_count = cursor.u32()? as usize;
+    if node_count < 1 {
+        return Err(Error::Serialization("Node buffer is empty".to_string()));
+    }
+    let raw_nodes = cursor.take(node_count.checked_mul(8).ok_or(Error::NodeIndexOverflow)?)?;
+    let mut nodes = Vec::with_capacity(node_count * 2);
+    for chunk in raw_nodes.chunks(4) {
+        nodes.push(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+    }
+
+    let section_count = cursor.u32()?;
+    let mut sections = Vec::new();
+    for _ in 0..section_count {
+        let raw_tag = cursor.take(4)?;
+        let tag = [raw_tag[0], raw_tag[1], raw_tag[2], raw_tag[3]];
+        let len = cursor.u32()? as usize;
+        sections.push(Section {
+            tag,
+            data: cursor.take(len)?.to_vec(),
+        });
+    }
+
+    if cursor.pos != body.len() {
+        return Err(Error::Serialization("Trailing data after the last section".to_string()));
+    }
+
+    //Everything after this indexes the node buffer, so it has to be safe to traverse first
+    stats::check_nodes(&nodes, depth, leaves)?;
+
+    let materials = sections.iter().find(|section| section.tag == MATERIAL_TAG).map(|section| section.data.clone());
+    let dag = DAG::from_raw(nodes, depth, size, leaves, materials)?;
+    //from_raw trusts the node buffer for the voxel counts, this checks them against the nodes again
+    dag.validate()?;
+    Ok((dag, sections))
+}
+
+/// Saves a DAG to a `.svdag` file.
+pub fn save<P: AsRef<Path>>(dag: &DAG, path: P) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let sections = [Section {
+        tag: MATERIAL_TAG,
+        data: dag.materials().to_vec(),
+    }];
+    write(dag, &sections, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Loads a DAG from a `.svdag` file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<DAG, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let (dag, _sections) = read(&mut reader)?;
+    Ok(dag)
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() - self.pos < len {
+            return Err(Error::Serialization("Unexpected end of file".to_string()));
+        }
+        let slice = &self.bytes[self.pos .. self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
@@ -0,0 +1,380 @@
+use glam::*;
+use serde::{Serialize, Deserialize};
+
+use std::cmp;
+use std::time::Instant;
+
+use crate::dag::{DAG, Level, EMPTY, layout_tagged};
+use crate::error::{Error, check_dimensions};
+use crate::octree::required_level;
+use crate::raycast::{Ray, RayHit};
+
+//Symmetry-aware DAG, based on "Symmetry-aware Sparse Voxel DAGs" (Villanueva et al.).
+//On top of merging identical subtrees, subtrees that are mirror images of each other get merged as well.
+//Every node is stored in a canonical orientation, and whoever points to it stores how to mirror it back.
+//
+//The layout is the same as src/dag.rs, except that the top 3 bits of the child word hold the mirror
+//of the node itself (bit 0 flips x, bit 1 flips y, bit 2 flips z), so only 29 bits are left for the index:
+// childmask        [24 bits empty; 8 bits for mask] in canonical orientation
+// child word       [3 bits mirror; 29 bits child index] where an index of 0 means this is a leaf node
+//Mirrors stack up while descending: a node's actual orientation is the xor of every mirror on the path to it.
+//Child j of a node with mirror m is its stored child j ^ m, as mirroring flips the child index bits.
+//A plain DAG never sets the mirror bits, so its buffer is a valid symmetric buffer as well, which is how the
+//compute shader traces both.
+//
+//Attributes use the same order as src/attributes.rs, in the actual orientation, so materials() is identical
+//to the one of a plain DAG built from the same data. Voxel counts don't change when mirroring, so they can be
+//stored per node as usual.
+//Symmetric DAGs can't be edited, build a plain DAG for that.
+
+pub const MIRROR_SHIFT: u32 = 29;
+pub const INDEX_MASK: u32 = (0x0000_0001 << MIRROR_SHIFT) - 1;
+
+/// Mirrors a childmask, moving bit j to bit j ^ mirror.
+pub fn mirror_mask(mask: u32, mirror: u32) -> u32 {
+    if mirror == 0 { return mask; }
+    let mut mirrored = 0;
+    for j in 0..8 {
+        if mask & (0x0000_0001 << j) != 0 {
+            mirrored |= 0x0000_0001 << (j ^ mirror);
+        }
+    }
+    mirrored
+}
+
+//Position of child j among the stored children of a node
+fn rank(childmask: u32, j: u32) -> u32 {
+    (childmask & ((0x0000_0001 << j) - 1)).count_ones()
+}
+
+//A symmetric node looks the same under several mirrors, so references to it always use the lowest one.
+//Otherwise parents that are mirror images of each other could end up with different children.
+//symmetries is parallel to the nodes of the node's level, bit m is set if mirroring by m doesn't change the node.
+fn normalize(symmetries: &[u8], node: u32, mirror: u32) -> u32 {
+    let symmetries = symmetries[node as usize];
+    (0..8).filter(|m| symmetries & (0x01 << m) != 0).map(|m| mirror ^ m).min().unwrap()
+}
+
+//Node references in the levels are packed like the child word, as node | mirror << MIRROR_SHIFT
+fn reference(node: u32, mirror: u32) -> u32 {
+    node | (mirror << MIRROR_SHIFT)
+}
+
+//Given which mirrors of a node turn it into its canonical orientation, returns the lowest of those,
+//and the mirrors the canonical orientation is symmetric under.
+fn orientation<F: Fn(u32) -> bool>(is_canonical: F) -> (u32, u8) {
+    let mirror = (0..8).find(|m| is_canonical(*m)).unwrap();
+    let mut symmetries = 0;
+    for m in 0..8 {
+        if is_canonical(m) { symmetries |= 0x01 << (m ^ mirror); }
+    }
+    (mirror, symmetries)
+}
+
+/// How much symmetry-aware merging saved, compared to a plain DAG of the same data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SymmetryReport {
+    pub plain_nodes: u32,
+    pub symmetric_nodes: u32,
+}
+
+impl SymmetryReport {
+    /// Plain node count divided by symmetric node count, higher is better.
+    pub fn ratio(&self) -> f32 {
+        self.plain_nodes as f32 / self.symmetric_nodes as f32
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SymmetricDAG {
+    data: Vec<u32>,
+    depth: u32,
+    size: (u32, u32, u32),
+    voxel_counts: Vec<u32>,
+    materials: Vec<u8>,
+}
+
+impl SymmetricDAG {
+    //Creation, works the same as DAG::from_voxel_data but every node is mirrored into its canonical orientation first
+    pub fn from_voxel_data(data: &[u8], data_size: (u32, u32, u32)) -> Result<Self, Error> {
+        check_dimensions(data, data_size)?;
+
+        let depth = cmp::max(required_level(data_size), 1);
+        let now = Instant::now();
+
+        let material = |x: u32, y: u32, z: u32| -> u8 {
+            if x >= data_size.0 || y >= data_size.1 || z >= data_size.2 { return 0; }
+            data[x as usize + y as usize * data_size.0 as usize + z as usize * data_size.0 as usize * data_size.1 as usize]
+        };
+
+        //Bottom level, the canonical orientation of a leaf is the mirror with the lowest childmask.
+        //The levels are the same as in src/dag.rs, but every block references its nodes together with their mirror.
+        //The grid holds (node, mirror) for every cell, and symmetries is parallel to the nodes of every level.
+        let mut res = 1 << (depth - 1);
+        let mut grid = vec![(EMPTY, 0); res * res * res];
+        let mut levels = vec![Level::default()];
+        let mut symmetries = vec![Vec::new()];
+        for z in 0..res {
+            for y in 0..res {
+                for x in 0..res {
+                    let mut mask = 0;
+                    for j in 0..8 {
+                        if material((x * 2 + j % 2) as u32, (y * 2 + j / 2 % 2) as u32, (z * 2 + j / 4 % 2) as u32) > 0 {
+                            mask |= 0x0000_0001 << j;
+                        }
+                    }
+                    if mask > 0 {
+                        let canonical = (0..8).map(|m| mirror_mask(mask, m)).min().unwrap();
+                        let (mirror, node_symmetries) = orientation(|m| mirror_mask(mask, m) == canonical);
+                        let node = levels[0].node_id((canonical, 0));
+                        if node as usize == symmetries[0].len() { symmetries[0].push(node_symmetries); }
+                        grid[x + y * res + z * res * res] = (node, mirror);
+                    }
+                }
+            }
+        }
+
+        //Reduce the levels above it. Mirroring a node moves child j to j ^ mirror, and mirrors that child as well.
+        //The canonical orientation is the one with the lowest (childmask, children).
+        while res > 1 {
+            let child_res = res;
+            res /= 2;
+            let mut next_grid = vec![(EMPTY, 0); res * res * res];
+            let mut level = Level::default();
+            let mut level_symmetries = Vec::new();
+            for z in 0..res {
+                for y in 0..res {
+                    for x in 0..res {
+                        let mut children = [(EMPTY, 0); 8];
+                        for (j, child) in children.iter_mut().enumerate() {
+                            let cx = x * 2 + j % 2;
+                            let cy = y * 2 + j / 2 % 2;
+                            let cz = z * 2 + j / 4 % 2;
+                            *child = grid[cx + cy * child_res + cz * child_res * child_res];
+                        }
+                        if children.iter().all(|child| child.0 == EMPTY) { continue; }
+
+                        let below = symmetries.last().unwrap();
+                        let variants: Vec<(u32, Vec<u32>)> = (0..8).map(|mirror: u32| {
+                            let mut mask = 0;
+                            let mut block = Vec::new();
+                            for j in 0..8 {
+                                let child = children[(j ^ mirror) as usize];
+                                if child.0 != EMPTY {
+                                    mask |= 0x0000_0001 << j;
+                                    block.push(reference(child.0, normalize(below, child.0, child.1 ^ mirror)));
+                                }
+                            }
+                            (mask, block)
+                        }).collect();
+                        let canonical = variants.iter().min().unwrap().clone();
+                        let (mirror, node_symmetries) = orientation(|m| variants[m as usize] == canonical);
+
+                        let block = levels.last_mut().unwrap().block_id(canonical.1);
+                        let node = level.node_id((canonical.0, block));
+                        if node as usize == level_symmetries.len() { level_symmetries.push(node_symmetries); }
+                        next_grid[x + y * res + z * res * res] = (node, mirror);
+                    }
+                }
+            }
+            levels.push(level);
+            symmetries.push(level_symmetries);
+            grid = next_grid;
+        }
+
+        //Same layout as src/dag.rs, the mirror bits of every reference end up in the child word of the node
+        let root = if grid[0].0 == EMPTY { EMPTY } else { reference(grid[0].0, grid[0].1) };
+        let data = layout_tagged(&levels, root, !INDEX_MASK)?;
+
+        let mut dag = Self {
+            data,
+            depth,
+            size: data_size,
+            voxel_counts: Vec::new(),
+            materials: Vec::new(),
+        };
+        dag.voxel_counts = dag.count_voxels()?;
+        let mut materials = Vec::with_capacity(dag.voxel_counts[0] as usize);
+        dag.gather_materials(0, 0, 0, (0, 0, 0), &material, &mut materials);
+        dag.materials = materials;
+
+        let duration = Instant::now() - now;
+        debug!("Time to generate symmetric DAG: {}ms", duration.as_millis());
+        debug!("Symmetric node count: {}", dag.data.len() / 2);
+
+        Ok(dag)
+    }
+
+    /// Compares the node count against a plain DAG, which should be built from the same data.
+    pub fn report(&self, plain: &DAG) -> SymmetryReport {
+        SymmetryReport {
+            plain_nodes: (plain.get_len() / 2) as u32,
+            symmetric_nodes: (self.data.len() / 2) as u32,
+        }
+    }
+
+    //Childmask in the actual orientation, first child index and the actual mirror of a node,
+    //given the mirror of everything above it
+    fn node(&self, idx: u32, parent_mirror: u32) -> (u32, u32, u32) {
+        let childmask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1];
+        let mirror = parent_mirror ^ (child >> MIRROR_SHIFT);
+        (mirror_mask(childmask, mirror), child & INDEX_MASK, mirror)
+    }
+
+    //Index and attribute offset of child j of an interior node, whose own attributes start at offset
+    fn child_attribute_offset(&self, idx: u32, mirror: u32, j: u32, offset: u32) -> (u32, u32) {
+        let stored_mask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1] & INDEX_MASK;
+        let mut offset = offset;
+        for k in 0..j {
+            if stored_mask & (0x0000_0001 << (k ^ mirror)) != 0 {
+                offset += self.voxel_counts[(child + rank(stored_mask, k ^ mirror)) as usize];
+            }
+        }
+        (child + rank(stored_mask, j ^ mirror), offset)
+    }
+
+    fn count_voxels(&self) -> Result<Vec<u32>, Error> {
+        let mut counts = vec![0; self.data.len() / 2];
+        let mut visited = vec![false; self.data.len() / 2];
+        self.count_node(0, &mut counts, &mut visited)?;
+        Ok(counts)
+    }
+
+    fn count_node(&self, idx: u32, counts: &mut Vec<u32>, visited: &mut Vec<bool>) -> Result<u32, Error> {
+        if visited[idx as usize] { return Ok(counts[idx as usize]); }
+
+        let childmask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1] & INDEX_MASK;
+        let count = if child == 0 {
+            childmask.count_ones()
+        } else {
+            let mut sum: u32 = 0;
+            for i in 0..childmask.count_ones() {
+                let child_count = self.count_node(child + i, counts, visited)?;
+                sum = sum.checked_add(child_count).ok_or(Error::NodeIndexOverflow)?;
+            }
+            sum
+        };
+
+        counts[idx as usize] = count;
+        visited[idx as usize] = true;
+        Ok(count)
+    }
+
+    fn gather_materials<F: Fn(u32, u32, u32) -> u8>(&self, idx: u32, parent_mirror: u32, level: u32, pos: (u32, u32, u32), material: &F, materials: &mut Vec<u8>) {
+        let (childmask, child, mirror) = self.node(idx, parent_mirror);
+        let half = 1 << (self.depth - level - 1);
+
+        for j in 0..8 {
+            if childmask & (0x0000_0001 << j) == 0 { continue; }
+            let child_pos = (pos.0 + (j % 2) * half, pos.1 + (j / 2 % 2) * half, pos.2 + (j / 4 % 2) * half);
+            if child == 0 {
+                materials.push(material(child_pos.0, child_pos.1, child_pos.2));
+            } else {
+                let stored_mask = self.data[idx as usize * 2];
+                self.gather_materials(child + rank(stored_mask, j ^ mirror), mirror, level + 1, child_pos, material, materials);
+            }
+        }
+    }
+
+    //Queries, coordinates are in voxels
+    pub fn get(&self, x: u32, y: u32, z: u32) -> Option<u8> {
+        if x >= self.size.0 || y >= self.size.1 || z >= self.size.2 { return None; }
+
+        let mut idx = 0;
+        let mut parent_mirror = 0;
+        let mut offset = 0;
+        for level in 0..self.depth {
+            let half = 1 << (self.depth - level - 1);
+            let mut j = 0;
+            if x & half != 0 { j |= 1; }
+            if y & half != 0 { j |= 2; }
+            if z & half != 0 { j |= 4; }
+
+            let (childmask, child, mirror) = self.node(idx, parent_mirror);
+            if childmask & (0x0000_0001 << j) == 0 { return None; }
+            if child == 0 {
+                return Some(self.materials[(offset + rank(childmask, j)) as usize]);
+            }
+            let (child_idx, child_offset) = self.child_attribute_offset(idx, mirror, j, offset);
+            idx = child_idx;
+            offset = child_offset;
+            parent_mirror = mirror;
+        }
+        None
+    }
+
+    pub fn is_solid(&self, x: u32, y: u32, z: u32) -> bool {
+        self.get(x, y, z).is_some()
+    }
+
+    /// Finds the closest solid voxel along a ray, same as DAG::raycast.
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<RayHit> {
+        let ray = Ray::new(origin, dir, max_t)?;
+        self.raycast_node(&ray, 0, 0, 0, (0, 0, 0), 0)
+    }
+
+    fn raycast_node(&self, ray: &Ray, idx: u32, parent_mirror: u32, level: u32, pos: (u32, u32, u32), attribute_offset: u32) -> Option<RayHit> {
+        let (childmask, child, mirror) = self.node(idx, parent_mirror);
+        let half = 1 << (self.depth - level - 1);
+
+        for i in 0..8 {
+            let j = (i ^ ray.octant_mask) as u32;
+            if childmask & (0x0000_0001 << j) == 0 { continue; }
+
+            let child_pos = (pos.0 + (j % 2) * half, pos.1 + (j / 2 % 2) * half, pos.2 + (j / 4 % 2) * half);
+            let (t_near, axis) = match ray.intersect(child_pos, half) {
+                Some(hit) => hit,
+                None => continue,
+            };
+
+            if child == 0 {
+                let material = self.materials[(attribute_offset + rank(childmask, j)) as usize];
+                return Some(ray.hit(t_near, axis, child_pos, material));
+            }
+
+            let (child_idx, child_offset) = self.child_attribute_offset(idx, mirror, j, attribute_offset);
+            if let Some(hit) = self.raycast_node(ray, child_idx, mirror, level + 1, child_pos, child_offset) {
+                return Some(hit);
+            }
+        }
+
+        None
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn size(&self) -> (u32, u32, u32) {
+        self.size
+    }
+
+    pub fn cube_size(&self) -> u32 {
+        1 << self.depth
+    }
+
+    /// Material of every solid voxel, in the same order as DAG::materials.
+    pub fn materials(&self) -> &[u8] {
+        &self.materials
+    }
+
+    /// Amount of solid voxels below every node, parallel to the node buffer.
+    pub fn voxel_counts(&self) -> &[u32] {
+        &self.voxel_counts
+    }
+
+    pub fn get_ptr(&self) -> *const u32 {
+        self.data.as_ptr()
+    }
+
+    pub fn get_len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn get_data(&self) -> &[u32] {
+        &self.data
+    }
+}
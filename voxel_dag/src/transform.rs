@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use crate::dag::{DAG, Level, EMPTY, layout};
+use crate::error::Error;
+use crate::csg::CsgOp;
+use crate::attributes;
+use crate::bricks::LeafFormat;
+
+//Voxel space transforms, applied to the nodes instead of a dense grid.
+//Rotating or mirroring the power of two cube the DAG spans just reorders the children of every node the same way,
+//so every unique node only gets transformed once, and the result is as deduplicated as the input.
+//The volume usually doesn't fill the whole cube though, so after flipping an axis it's moved back to the origin.
+//Translations go through the same windowed traversal as CSG (see src/csg.rs), which re-roots the DAG
+//into a bigger tree when it has to grow.
+//Attribute order depends on the order of the children, so materials are looked up again afterwards.
+
+//Deepest DAG a u32 coordinate can address, same as src/svdag.rs
+const MAX_DEPTH: u32 = 31;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+//An axis aligned orientation of the cube. Axis a of the result reads axis perm[a] of the source, reversed if flip[a].
+#[derive(Clone, Copy)]
+struct Orientation {
+    perm: [usize; 3],
+    flip: [bool; 3],
+}
+
+impl Orientation {
+    //A quarter turn, counter-clockwise when looking down the axis
+    fn quarter_turn(axis: Axis) -> Self {
+        match axis {
+            Axis::X => Self { perm: [0, 2, 1], flip: [false, true, false] },
+            Axis::Y => Self { perm: [2, 1, 0], flip: [false, false, true] },
+            Axis::Z => Self { perm: [1, 0, 2], flip: [true, false, false] },
+        }
+    }
+
+    fn mirror(axis: Axis) -> Self {
+        let mut flip = [false; 3];
+        flip[axis as usize] = true;
+        Self { perm: [0, 1, 2], flip }
+    }
+
+    //This orientation, applied after other
+    fn then(self, other: Orientation) -> Self {
+        let mut perm = [0; 3];
+        let mut flip = [false; 3];
+        for a in 0..3 {
+            perm[a] = other.perm[self.perm[a]];
+            flip[a] = self.flip[a] ^ other.flip[self.perm[a]];
+        }
+        Self { perm, flip }
+    }
+
+    //Where child j of a node ends up
+    fn child(&self, j: u32) -> u32 {
+        let mut moved = 0;
+        for a in 0..3 {
+            let bit = ((j >> self.perm[a]) & 1) ^ self.flip[a] as u32;
+            moved |= bit << a;
+        }
+        moved
+    }
+
+    //Source position of a voxel in the result, inside of a cube of the given size
+    fn source(&self, pos: [u32; 3], size: u32) -> [u32; 3] {
+        let mut source = [0; 3];
+        for a in 0..3 {
+            source[self.perm[a]] = if self.flip[a] { size - 1 - pos[a] } else { pos[a] };
+        }
+        source
+    }
+}
+
+impl DAG {
+    /// Rotates the volume by quarter turns around an axis. The result starts at the origin again.
+    pub fn rotate(&self, axis: Axis, quarter_turns: u32) -> Result<DAG, Error> {
+        let mut orientation = Orientation { perm: [0, 1, 2], flip: [false; 3] };
+        for _ in 0..quarter_turns % 4 {
+            orientation = Orientation::quarter_turn(axis).then(orientation);
+        }
+        self.orient(orientation)
+    }
+
+    /// Mirrors the volume along an axis, keeping it in place.
+    pub fn mirror(&self, axis: Axis) -> Result<DAG, Error> {
+        self.orient(Orientation::mirror(axis))
+    }
+
+    /// Moves the volume by offset, growing the DAG when it has to. Voxels that end up at a negative position are dropped.
+    pub fn translate(&self, offset: (i32, i32, i32)) -> Result<DAG, Error> {
+        //Depth 2 is deep enough for either leaf format
+        let empty = DAG::from_raw(vec![0, 0], 2, (0, 0, 0), self.leaves, None)?;
+        empty.csg(self, CsgOp::Union, offset)
+    }
+
+    /// Makes every voxel pow(2, levels) voxels wide on every axis.
+    pub fn upscale(&self, levels: u32) -> Result<DAG, Error> {
+        if self.leaves != LeafFormat::Voxels {
+            return self.with_leaf_format(LeafFormat::Voxels)?.upscale(levels)?.with_leaf_format(self.leaves);
+        }
+
+        //Everything that can overflow is checked before any node is built
+        let depth = self.depth.checked_add(levels).filter(|depth| *depth <= MAX_DEPTH).ok_or(Error::InvalidLevel(levels))?;
+        let factor = 1u64 << levels;
+        let size = (self.size.0 as u64 * factor, self.size.1 as u64 * factor, self.size.2 as u64 * factor);
+        if size.0 > u32::MAX as u64 || size.1 > u32::MAX as u64 || size.2 > u32::MAX as u64 {
+            return Err(Error::InvalidLevel(levels));
+        }
+        let repeat = 1usize.checked_shl(levels * 3).ok_or(Error::NodeIndexOverflow)?;
+        let material_count = self.materials.len().checked_mul(repeat).ok_or(Error::NodeIndexOverflow)?;
+        if material_count > u32::MAX as usize {
+            return Err(Error::NodeIndexOverflow);
+        }
+
+        //Every solid voxel becomes the same full subtree, so there's only one of those per level
+        let mut new_levels: Vec<Level> = (0..depth).map(|_| Level::default()).collect();
+        let mut full = Vec::new();
+        for i in 0..levels as usize {
+            let node = if i == 0 {
+                new_levels[0].node_id((0xFF, 0))
+            } else {
+                let block = new_levels[i - 1].block_id(vec![full[i - 1]; 8]);
+                new_levels[i].node_id((0xFF, block))
+            };
+            full.push(node);
+        }
+
+        let mut memo = HashMap::new();
+        let root = if self.voxel_counts[0] == 0 { EMPTY } else { self.upscale_node(0, 0, depth, &full, &mut new_levels, &mut memo) };
+        let data = layout(&new_levels, root)?;
+
+        let mut materials = Vec::with_capacity(material_count);
+        for material in &self.materials {
+            materials.extend(std::iter::repeat_n(*material, repeat));
+        }
+
+        DAG::from_raw(data, depth, (size.0 as u32, size.1 as u32, size.2 as u32), LeafFormat::Voxels, Some(materials))
+    }
+
+    /// Merges every cube of pow(2, levels) voxels into one, using the most common material. Same as lod.
+    pub fn downscale(&self, levels: u32) -> Result<DAG, Error> {
+        if levels >= self.depth {
+            return Err(Error::InvalidLevel(levels));
+        }
+        self.lod(self.depth - levels)
+    }
+
+    fn orient(&self, orientation: Orientation) -> Result<DAG, Error> {
+        if self.leaves != LeafFormat::Voxels {
+            return self.with_leaf_format(LeafFormat::Voxels)?.orient(orientation)?.with_leaf_format(self.leaves);
+        }
+
+        let mut levels: Vec<Level> = (0..self.depth).map(|_| Level::default()).collect();
+        let mut memo = HashMap::new();
+        let root = if self.voxel_counts[0] == 0 { EMPTY } else { self.orient_node(orientation, 0, 0, &mut levels, &mut memo) };
+        let data = layout(&levels, root)?;
+
+        let old_size = [self.size.0, self.size.1, self.size.2];
+        let size = [old_size[orientation.perm[0]], old_size[orientation.perm[1]], old_size[orientation.perm[2]]];
+
+        //Flipped axes leave the volume at the far end of the cube
+        let cube_size = self.cube_size();
+        let mut shift = [0; 3];
+        for a in 0..3 {
+            if orientation.flip[a] { shift[a] = cube_size - size[a]; }
+        }
+        let extent = (shift[0] + size[0], shift[1] + size[1], shift[2] + size[2]);
+        let oriented = DAG::from_raw(data, self.depth, extent, LeafFormat::Voxels, None)?;
+        let mut result = if shift == [0, 0, 0] {
+            oriented
+        } else {
+            oriented.translate((-(shift[0] as i32), -(shift[1] as i32), -(shift[2] as i32)))?
+        };
+
+        let material = |x: u32, y: u32, z: u32| -> u8 {
+            let source = orientation.source([x + shift[0], y + shift[1], z + shift[2]], cube_size);
+            self.get(source[0], source[1], source[2]).unwrap_or(0)
+        };
+        result.materials = attributes::gather_materials(&result.data, result.depth, LeafFormat::Voxels, material);
+        Ok(result)
+    }
+
+    //Node in levels for the node at idx, levels are bottom-up
+    fn orient_node(&self, orientation: Orientation, idx: u32, level: u32, levels: &mut [Level], memo: &mut HashMap<u32, u32>) -> u32 {
+        if let Some(id) = memo.get(&idx) {
+            return *id;
+        }
+
+        let childmask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1];
+        let mut moved = [EMPTY; 8];
+        let mut i = 0;
+        for j in 0..8 {
+            if childmask & (0x0000_0001 << j) == 0 { continue; }
+            moved[orientation.child(j) as usize] = if level == self.leaf_level() {
+                0 //Any non-empty marker, voxels don't have nodes
+            } else {
+                self.orient_node(orientation, child + i, level + 1, levels, memo)
+            };
+            i += 1;
+        }
+
+        let mut mask = 0;
+        let mut children = Vec::new();
+        for (j, node) in moved.iter().enumerate() {
+            if *node == EMPTY { continue; }
+            mask |= 0x0000_0001 << j;
+            children.push(*node);
+        }
+
+        let bottom = (self.depth - level - 1) as usize;
+        let id = if bottom == 0 {
+            levels[0].node_id((mask, 0))
+        } else {
+            let block = levels[bottom - 1].block_id(children);
+            levels[bottom].node_id((mask, block))
+        };
+        memo.insert(idx, id);
+        id
+    }
+
+    //Node in levels for the node at idx, leaves turn into interior nodes with full children
+    fn upscale_node(&self, idx: u32, level: u32, depth: u32, full: &[u32], levels: &mut [Level], memo: &mut HashMap<u32, u32>) -> u32 {
+        if let Some(id) = memo.get(&idx) {
+            return *id;
+        }
+
+        let childmask = self.data[idx as usize * 2];
+        let child = self.data[idx as usize * 2 + 1];
+        let bottom = (depth - level - 1) as usize;
+        let id = if level == self.leaf_level() {
+            match full.last() {
+                Some(full) => {
+                    let block = levels[bottom - 1].block_id(vec![*full; childmask.count_ones() as usize]);
+                    levels[bottom].node_id((childmask, block))
+                },
+                None => levels[0].node_id((childmask, 0)),
+            }
+        } else {
+            let children = (0..childmask.count_ones()).map(|i| self.upscale_node(child + i, level + 1, depth, full, levels, memo)).collect();
+            let block = levels[bottom - 1].block_id(children);
+            levels[bottom].node_id((childmask, block))
+        };
+        memo.insert(idx, id);
+        id
+    }
+}
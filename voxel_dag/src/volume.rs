@@ -0,0 +1,116 @@
+use crate::aabb::BoundingBox;
+use crate::dag::DAG;
+use crate::octree::Octree;
+use crate::symmetry::SymmetricDAG;
+use crate::voxel_data_structure::VoxelDAG;
+use crate::error::{Error, check_dimensions};
+
+//A dense grid of materials together with its size, so loaders don't have to pass both around separately.
+//Every builder still works on (&[u8], data_size), the from_volume constructors just unpack it.
+
+/// Dense voxel volume, indexed with x + y * size.0 + z * size.0 * size.1. Material 0 is empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Volume {
+    size: (u32, u32, u32),
+    data: Vec<u8>,
+}
+
+impl Volume {
+    /// Creates an empty volume.
+    pub fn new(size: (u32, u32, u32)) -> Self {
+        Self {
+            size,
+            data: vec![0; size.0 as usize * size.1 as usize * size.2 as usize],
+        }
+    }
+
+    /// Wraps existing voxel data, which has to match size.
+    pub fn from_data(data: Vec<u8>, size: (u32, u32, u32)) -> Result<Self, Error> {
+        check_dimensions(&data, size)?;
+        Ok(Self {
+            size,
+            data,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32, u32) {
+        self.size
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns the material at a position, or None if it's empty or outside of the volume.
+    pub fn get(&self, x: u32, y: u32, z: u32) -> Option<u8> {
+        if x >= self.size.0 || y >= self.size.1 || z >= self.size.2 { return None; }
+        match self.data[self.index(x, y, z)] {
+            0 => None,
+            material => Some(material),
+        }
+    }
+
+    /// Sets the material at a position, 0 clears it. Panics if the position is outside of the volume.
+    pub fn set(&mut self, x: u32, y: u32, z: u32, material: u8) {
+        assert!(x < self.size.0 && y < self.size.1 && z < self.size.2, "Voxel ({}, {}, {}) is outside of the volume", x, y, z);
+        let idx = self.index(x, y, z);
+        self.data[idx] = material;
+    }
+
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        x as usize + y as usize * self.size.0 as usize + z as usize * self.size.0 as usize * self.size.1 as usize
+    }
+}
+
+impl DAG {
+    pub fn from_volume(volume: &Volume) -> Result<Self, Error> {
+        Self::from_voxel_data(volume.data(), volume.size())
+    }
+
+    pub fn from_volume_parallel(volume: &Volume) -> Result<Self, Error> {
+        Self::from_voxel_data_parallel(volume.data(), volume.size())
+    }
+
+    /// Decompresses a region into a volume, see decompress.
+    pub fn decompress_volume(&self, bounds: &BoundingBox) -> Volume {
+        let (data, size) = self.decompress(bounds);
+        Volume {
+            size,
+            data,
+        }
+    }
+
+    /// Decompresses the whole DAG into a volume of the same size.
+    pub fn to_volume(&self) -> Volume {
+        Volume {
+            size: self.size,
+            data: self.to_voxel_data(),
+        }
+    }
+}
+
+impl SymmetricDAG {
+    pub fn from_volume(volume: &Volume) -> Result<Self, Error> {
+        Self::from_voxel_data(volume.data(), volume.size())
+    }
+}
+
+impl VoxelDAG {
+    pub fn from_volume(volume: &Volume, level: u32) -> Result<Self, Error> {
+        Self::from_voxel_data(volume.data(), volume.size(), level)
+    }
+}
+
+impl<'a> Octree<'a> {
+    pub fn from_volume(volume: &'a Volume, level: u32) -> Result<Self, Error> {
+        Self::from_voxel_data(volume.data(), volume.size(), level)
+    }
+}
@@ -1,9 +1,14 @@
 use glam::*;
 use serde::{Serialize, Deserialize};
 
-use std::time::Instant;
 use std::cmp;
 
+use crate::error::{Error, check_dimensions};
+use crate::octree::{required_level, region_contains_geometry};
+use crate::aabb::BoundingBox;
+use crate::query;
+use crate::bricks::LeafFormat;
+
 //In the original paper, it suggests storing only a pointer to the first child, and then
 //have all the other children stored in memory consecutively, but I'm not sure how I'd do this
 //in Rust and I have to send it to the GPU anyway, which I plan on doing through an SSBO.
@@ -14,32 +19,19 @@ use std::cmp;
 // childmask        [24 bits empty; 8 bits for mask]
 // child index 1    [u32]
 //Explanation:
-// childmask        Specifies if said child is a leaf
-// child index 1    Index of first non-empty child
+// childmask        Specifies which children contain geometry
+// child index 1    Index of first non-empty child, 0 for nodes on the last level
+//This is the exact same layout as src/dag.rs, just without merging duplicate nodes.
+//The cells on the last level are cube_size / pow(2, level) voxels in size.
 
 fn node_contains_geometry(position: Vec3, level: u32, data: &[u8], data_size: (u32,u32,u32)) -> bool {
-    let mut biggest_axis_size = data_size.0;
-    if data_size.1 > biggest_axis_size { biggest_axis_size = data_size.1; }
-    if data_size.2 > biggest_axis_size { biggest_axis_size = data_size.2; }
-
-    let node_size = biggest_axis_size as f32 / 2.0f32.powi(level as i32);
-    let vox_space_pos = (position * biggest_axis_size as f32).floor();
-
-    let ux = vox_space_pos.x() as usize;
-    let uy = vox_space_pos.y() as usize;
-    let uz = vox_space_pos.z() as usize;
-    let us = node_size as usize;
-
-    for px in ux .. ux + us {
-        for py in uy .. uy + us {
-            for pz in uz .. uz + us {
-                if data[px as usize + py as usize * data_size.0 as usize + pz as usize * data_size.0 as usize * data_size.1 as usize] > 0 {
-                    return true;
-                }
-            }
-        }
-    }
-    false
+    let cube_size = 1 << required_level(data_size);
+
+    let node_size = cube_size as f32 / 2.0f32.powi(level as i32);
+    let vox_space_pos = (position * cube_size as f32).floor();
+
+    let min = (vox_space_pos.x() as usize, vox_space_pos.y() as usize, vox_space_pos.z() as usize);
+    region_contains_geometry(data, data_size, min, cmp::max(node_size as usize, 1))
 }
 
 //Struct used internally
@@ -47,22 +39,23 @@ pub struct Octant {
     pub parent: u32,
     pub first_child: u32,
     pub level: u32,
-    pub is_leaf: bool,
+    pub is_leaf: bool, //Set for octants without any geometry inside of them
     pub position: Vec3,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct VoxelDAG {
-    data: Vec<u32>, //raw data, only used internally for OpenGL
-    ssbo: u32, // https://www.khronos.org/opengl/wiki/Shader_Storage_Buffer_Object
+    data: Vec<u32>, //raw data, uploaded to an SSBO by the engine
+    size: (u32, u32, u32), //Original extents, the tree itself spans a power of two cube
+    level: u32,
 }
 
 //Voxels (on 1 axis) per level of the octree: pow(2, level)
 //Required length for size: log2(biggest_voxel_count_axis)//.ceil() to get the proper level
-//TODO: Use a proper error type, not a String.
 impl VoxelDAG {
-    pub fn from_voxel_data(data: &[u8], data_size: (u32, u32, u32), level: u32) -> Result<Self, String> {
-        if level < 1 { return Err("Unable to create octree with level lower than 1!".to_string()); }
+    pub fn from_voxel_data(data: &[u8], data_size: (u32, u32, u32), level: u32) -> Result<Self, Error> {
+        if level < 1 { return Err(Error::InvalidLevel(level)); }
+        check_dimensions(data, data_size)?;
 
         let mut nodes = vec![Octant {
             parent: 0,
@@ -75,81 +68,127 @@ impl VoxelDAG {
         generate_node(&mut nodes, data, data_size, 0, 0, level);
 
         debug!("Node count: {}", nodes.len());
+        if nodes.len() > u32::MAX as usize { return Err(Error::NodeIndexOverflow); }
 
         //TODO: Filter out duplicate nodes
 
         debug!("Generating raw buffer data!");
 
+        //Octants are packed breadth first, so the non-empty children of a node always end up next to each other.
+        //order maps packed node indices to octant indices.
+        let mut order = vec![0];
         let mut data = Vec::new();
-
-        for i in 0..nodes.len() {
-            //Mask denotes which children are leaf nodes
+        let mut i = 0;
+        while i < order.len() {
+            let octant = &nodes[order[i] as usize];
             let mut mask = 0x0000_0000;
-            for j in nodes[i as usize].first_child..nodes[i as usize].first_child+1 {
-                if j != 0 { //0 is invalid index
-                    if nodes[j as usize].is_leaf {
-                        mask |= 0x0000_0001 << 1;
+            let mut first_child = 0;
+            if !octant.is_leaf && octant.level < level {
+                let children_are_cells = octant.level + 1 == level;
+                if !children_are_cells { first_child = order.len() as u32; }
+                for j in 0..8 {
+                    let child = octant.first_child + j;
+                    if !nodes[child as usize].is_leaf {
+                        mask |= 0x0000_0001 << j;
+                        if !children_are_cells { order.push(child); }
                     }
                 }
             }
             data.push(mask as u32);
-            data.push(i as u32);
-        }
-
-        //Generate ssbo buffer
-        let mut ssbo: u32 = 0;
-        unsafe {
-            let raw_data = data.as_slice();
-
-            gl::GenBuffers(1, &mut ssbo);
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, ssbo);
-            gl::BufferData(gl::SHADER_STORAGE_BUFFER, std::mem::size_of_val(raw_data) as isize, raw_data.as_ptr() as *const std::ffi::c_void, gl::DYNAMIC_DRAW);
-            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 3, ssbo);
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0); //unbind ssbo
+            data.push(first_child);
+            i += 1;
         }
 
         Ok(Self {
-            data: data,
-            ssbo: ssbo,
+            data,
+            size: data_size,
+            level,
         })
     }
+
+    /// Size of the original volume, in voxels
+    pub fn size(&self) -> (u32, u32, u32) {
+        self.size
+    }
+
+    /// Size of the power of two cube the tree spans, in voxels
+    pub fn cube_size(&self) -> u32 {
+        1 << required_level(self.size)
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    //Queries, coordinates are in voxels
+    //There are no materials in here, so these only answer occupancy, use a DAG to get materials.
+    //Cells on the last level can span multiple voxels, so the answers are only as precise as the level
+    pub fn is_solid(&self, x: u32, y: u32, z: u32) -> bool {
+        if x >= self.size.0 || y >= self.size.1 || z >= self.size.2 { return false; }
+        let scale = self.cell_scale();
+        let cell = ((x as f32 * scale) as u32, (y as f32 * scale) as u32, (z as f32 * scale) as u32);
+        query::find_leaf(&self.data, self.level, LeafFormat::Voxels, cell).is_some()
+    }
+
+    pub fn any_solid_in(&self, bounds: &BoundingBox) -> bool {
+        let scale = self.cell_scale();
+        query::any_solid_in(&self.data, self.level, LeafFormat::Voxels, bounds.min * scale, bounds.max * scale)
+    }
+
+    //Cells per voxel, on a single axis
+    fn cell_scale(&self) -> f32 {
+        (1u64 << self.level) as f32 / self.cube_size() as f32
+    }
+
+    //Memory
+    pub fn get_ptr(&self) -> *const u32 {
+        self.data.as_ptr()
+    }
+
+    pub fn get_len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn get_data(&self) -> &[u32] {
+        &self.data
+    }
 }
 
 fn generate_node(nodes: &mut Vec<Octant>, data: &[u8], data_size: (u32,u32,u32), parent: u32, cur_level: u32, level: u32) {
     let node = &nodes[parent as usize];
     //Check if node contains geometry, then generate children
-    if cur_level >= level {
-        println!("Reached max level!");
+    if !node_contains_geometry(node.position, node.level, data, data_size) {
+        nodes[parent as usize].is_leaf = true;
         return;
     }
-    if node_contains_geometry(node.position, node.level, data, data_size) {
-        let mut children = Vec::new();
-        let parent_pos = nodes[parent as usize].position;
-        for j in 0..8 {
-            let child_x = (j % 2) as f32 * 0.5;
-            let child_y = (j / 2 % 2) as f32 * 0.5;
-            let child_z = (j / 4 % 2) as f32 * 0.5;
-            let child_pos = Vec3::new(child_x, child_y, child_z);
-            let new_child_pos = parent_pos + child_pos / 2.0f32.powi(cur_level as i32); //Could possibly lead to loss of information, but the level probably won't go past 2 bilion lol
-            let child_idx = nodes.len() as u32;
-            if j == 0 { nodes[parent as usize].first_child = child_idx; }
-
-            //We have the child, let's now generate it's children
-            nodes.push(Octant {
-                parent: parent,
-                first_child: 0,
-                level: cur_level + 1,
-                is_leaf: false,
-                position: new_child_pos,
-            });
-            children.push(child_idx);
-        }
-        for child in children {
-            //Call this function again
-            generate_node(nodes, data, data_size, child, cur_level + 1, level);
-        }
-    } else {
-        nodes[parent as usize].is_leaf = true;
+    if cur_level >= level {
+        trace!("Reached max level!");
         return;
     }
+
+    let mut children = Vec::new();
+    let parent_pos = nodes[parent as usize].position;
+    for j in 0..8 {
+        let child_x = (j % 2) as f32 * 0.5;
+        let child_y = (j / 2 % 2) as f32 * 0.5;
+        let child_z = (j / 4 % 2) as f32 * 0.5;
+        let child_pos = Vec3::new(child_x, child_y, child_z);
+        let new_child_pos = parent_pos + child_pos / 2.0f32.powi(cur_level as i32); //Could possibly lead to loss of information, but the level probably won't go past 2 bilion lol
+        let child_idx = nodes.len() as u32;
+        if j == 0 { nodes[parent as usize].first_child = child_idx; }
+
+        //We have the child, let's now generate it's children
+        nodes.push(Octant {
+            parent,
+            first_child: 0,
+            level: cur_level + 1,
+            is_leaf: false,
+            position: new_child_pos,
+        });
+        children.push(child_idx);
+    }
+    for child in children {
+        //Call this function again
+        generate_node(nodes, data, data_size, child, cur_level + 1, level);
+    }
 }
@@ -0,0 +1,37 @@
+mod common;
+
+use voxel_dag::dag::DAG;
+use voxel_dag::bricks::LeafFormat;
+
+use common::*;
+
+#[test]
+fn build_matches_dense_data() {
+    let mut rng = Rng(1234567);
+    for &size in SIZES {
+        for &density in &[0, 5, 50, 100] {
+            let data = random_volume(&mut rng, size, density);
+            let dag = DAG::from_voxel_data(&data, size).unwrap();
+            assert_matches(&dag, &data, size, &format!("build {:?} {}%", size, density));
+        }
+    }
+}
+
+#[test]
+fn brick_leaves_match_voxel_leaves() {
+    let mut rng = Rng(42);
+    for &size in SIZES {
+        let data = random_volume(&mut rng, size, 30);
+        let dag = DAG::from_voxel_data(&data, size).unwrap();
+        //DAGs that are too shallow for bricks are allowed to refuse
+        if let Ok(bricks) = dag.with_leaf_format(LeafFormat::Bricks) {
+            assert_matches(&bricks, &data, size, &format!("bricks {:?}", size));
+            assert_eq!(bricks.with_leaf_format(LeafFormat::Voxels).unwrap().get_data(), dag.get_data());
+        }
+    }
+}
+
+#[test]
+fn rejects_mismatched_data() {
+    assert!(DAG::from_voxel_data(&[0; 7], (2, 2, 2)).is_err());
+}
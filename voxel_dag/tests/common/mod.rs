@@ -0,0 +1,47 @@
+//Helpers shared by the integration tests. Every test compares against a plain dense array,
+//indexed like from_voxel_data, which is the simplest thing that can't be wrong.
+#![allow(dead_code)]
+
+use voxel_dag::dag::DAG;
+
+/// Sizes that cover single voxels, odd sizes that don't fill the octree, and full power of two cubes.
+pub const SIZES: &[(u32, u32, u32)] = &[(1, 1, 1), (2, 2, 2), (3, 5, 2), (8, 8, 8), (13, 9, 20), (16, 16, 16), (17, 3, 4), (32, 32, 32)];
+
+/// Xorshift, so tests are reproducible without pulling in rand.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 16) as u32
+    }
+}
+
+/// Random voxel data, where density is the percentage of solid voxels.
+pub fn random_volume(rng: &mut Rng, size: (u32, u32, u32), density: u32) -> Vec<u8> {
+    (0..size.0 * size.1 * size.2).map(|_| {
+        if rng.next() % 100 < density { (rng.next() % 5 + 1) as u8 } else { 0 }
+    }).collect()
+}
+
+pub fn idx(size: (u32, u32, u32), x: u32, y: u32, z: u32) -> usize {
+    x as usize + y as usize * size.0 as usize + z as usize * size.0 as usize * size.1 as usize
+}
+
+/// Checks every voxel of the DAG against the dense data, and that the DAG is well formed.
+pub fn assert_matches(dag: &DAG, data: &[u8], size: (u32, u32, u32), what: &str) {
+    assert_eq!(dag.size(), size, "{}: size", what);
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                assert_eq!(dag.get(x, y, z).unwrap_or(0), data[idx(size, x, y, z)], "{}: voxel {:?}", what, (x, y, z));
+            }
+        }
+    }
+    assert_eq!(dag.to_voxel_data(), data, "{}: decompressed data", what);
+    if let Err(err) = dag.validate() {
+        panic!("{}: invalid DAG: {}", what, err);
+    }
+}
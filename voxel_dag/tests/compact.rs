@@ -0,0 +1,52 @@
+mod common;
+
+use glam::*;
+
+use voxel_dag::dag::DAG;
+use voxel_dag::aabb::BoundingBox;
+use voxel_dag::bricks::LeafFormat;
+use voxel_dag::edit::Shape;
+
+use common::*;
+
+#[test]
+fn compact_after_carving_everything() {
+    let mut rng = Rng(21);
+    for &size in &[(8, 8, 8), (13, 9, 20), (4, 4, 4)] {
+        for &leaves in &[LeafFormat::Voxels, LeafFormat::Bricks] {
+            let data = random_volume(&mut rng, size, 40);
+            let mut dag = DAG::from_voxel_data(&data, size).unwrap().with_leaf_format(leaves).unwrap();
+            let everything = BoundingBox::new(Vec3A::zero(), Vec3A::new(size.0 as f32, size.1 as f32, size.2 as f32));
+            dag.carve(&Shape::Box(everything)).unwrap();
+
+            let compaction = dag.compact();
+            assert_eq!(compaction.after.dead_nodes, 0);
+            assert_eq!(dag.get_data(), &[0, 0][..]);
+            let empty = vec![0; data.len()];
+            assert_matches(&dag, &empty, size, &format!("carved {:?} {:?}", size, leaves));
+        }
+    }
+}
+
+#[test]
+fn compact_keeps_voxels() {
+    let mut rng = Rng(22);
+    let size = (16, 16, 16);
+    let mut data = random_volume(&mut rng, size, 30);
+    let mut dag = DAG::from_voxel_data(&data, size).unwrap();
+    for step in 0..20 {
+        let (x, y, z) = (rng.next() % size.0, rng.next() % size.1, rng.next() % size.2);
+        if step % 2 == 0 {
+            dag.set_voxel(x, y, z, 3).unwrap();
+            data[idx(size, x, y, z)] = 3;
+        } else {
+            dag.clear_voxel(x, y, z).unwrap();
+            data[idx(size, x, y, z)] = 0;
+        }
+    }
+
+    let compaction = dag.compact();
+    assert!(compaction.after.live_nodes <= compaction.before.live_nodes + compaction.before.dead_nodes);
+    assert_eq!(compaction.after.dead_nodes, 0);
+    assert_matches(&dag, &data, size, "compacted");
+}
@@ -0,0 +1,147 @@
+use voxel_dag::dag::DAG;
+use voxel_dag::csg::CsgOp;
+
+fn idx(size: (u32, u32, u32), x: u32, y: u32, z: u32) -> usize {
+    x as usize + y as usize * size.0 as usize + z as usize * size.0 as usize * size.1 as usize
+}
+
+fn volume<F: Fn(u32, u32, u32) -> u8>(size: (u32, u32, u32), material: F) -> Vec<u8> {
+    let mut data = vec![0; (size.0 * size.1 * size.2) as usize];
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                data[idx(size, x, y, z)] = material(x, y, z);
+            }
+        }
+    }
+    data
+}
+
+//Material at a position, 0 outside of the data
+fn dense_at(data: &[u8], size: (u32, u32, u32), pos: (i64, i64, i64)) -> u8 {
+    if pos.0 < 0 || pos.1 < 0 || pos.2 < 0 { return 0; }
+    let (x, y, z) = (pos.0 as u32, pos.1 as u32, pos.2 as u32);
+    if x >= size.0 || y >= size.1 || z >= size.2 { return 0; }
+    data[idx(size, x, y, z)]
+}
+
+//The result of an operation on dense data, in a volume of the given size
+fn dense_csg(a: (&[u8], (u32, u32, u32)), b: (&[u8], (u32, u32, u32)), op: CsgOp, offset: (i32, i32, i32), size: (u32, u32, u32)) -> Vec<u8> {
+    volume(size, |x, y, z| {
+        let av = dense_at(a.0, a.1, (x as i64, y as i64, z as i64));
+        let bv = dense_at(b.0, b.1, (x as i64 - offset.0 as i64, y as i64 - offset.1 as i64, z as i64 - offset.2 as i64));
+        match op {
+            CsgOp::Union => if bv > 0 { bv } else { av },
+            CsgOp::Intersection => if bv > 0 { av } else { 0 },
+            CsgOp::Difference => if bv > 0 { 0 } else { av },
+        }
+    })
+}
+
+fn assert_dense(dag: &DAG, expected: &[u8], size: (u32, u32, u32), what: &str) {
+    assert_eq!(dag.size(), size, "{}", what);
+    let data = volume(size, |x, y, z| dag.get(x, y, z).unwrap_or(0));
+    assert_eq!(data, expected, "{}", what);
+    //The result has to be as deduplicated as a fresh build
+    assert_eq!(dag.get_data(), DAG::from_voxel_data(expected, size).unwrap().get_data(), "{}", what);
+}
+
+#[test]
+fn union_grows_to_fit_the_offset() {
+    let a = vec![1; 4 * 4 * 4];
+    let b = vec![2; 3 * 2 * 2];
+    let a_dag = DAG::from_voxel_data(&a, (4, 4, 4)).unwrap();
+    let b_dag = DAG::from_voxel_data(&b, (3, 2, 2)).unwrap();
+
+    //Past the end of the first DAG's cube, so the result needs two more levels
+    let result = a_dag.csg(&b_dag, CsgOp::Union, (6, 0, 13)).unwrap();
+    assert_eq!(result.size(), (9, 4, 15));
+    assert_eq!(result.depth(), 4);
+    let expected = dense_csg((&a, (4, 4, 4)), (&b, (3, 2, 2)), CsgOp::Union, (6, 0, 13), (9, 4, 15));
+    assert_dense(&result, &expected, (9, 4, 15), "grown union");
+
+    //Negative offsets never grow the result, the voxels that end up below 0 are dropped
+    let result = a_dag.csg(&b_dag, CsgOp::Union, (-2, -1, 3)).unwrap();
+    assert_eq!(result.size(), (4, 4, 5));
+    let expected = dense_csg((&a, (4, 4, 4)), (&b, (3, 2, 2)), CsgOp::Union, (-2, -1, 3), (4, 4, 5));
+    assert_dense(&result, &expected, (4, 4, 5), "union at a negative offset");
+    assert_eq!(result.get(0, 0, 3), Some(2));
+    assert_eq!(result.get(0, 0, 2), Some(1));
+
+    //Entirely below 0
+    let result = a_dag.csg(&b_dag, CsgOp::Union, (-3, 0, 0)).unwrap();
+    assert_eq!(result.size(), (4, 4, 4));
+    assert_eq!(result.get_data(), a_dag.get_data());
+}
+
+#[test]
+fn intersection_and_difference_keep_the_first_size() {
+    //A bigger second operand, so only a window of it lines up with the first
+    let a_size = (7, 5, 6);
+    let b_size = (20, 3, 11);
+    let a = volume(a_size, |x, y, z| ((x + y * 2 + z) % 3) as u8 * 2);
+    let b = volume(b_size, |x, _, z| if (x + z) % 4 != 1 { 9 } else { 0 });
+    let a_dag = DAG::from_voxel_data(&a, a_size).unwrap();
+    let b_dag = DAG::from_voxel_data(&b, b_size).unwrap();
+
+    for &offset in &[(0, 0, 0), (-5, 1, -2), (3, -1, 4), (-13, 2, 0)] {
+        for &op in &[CsgOp::Intersection, CsgOp::Difference] {
+            let result = a_dag.csg(&b_dag, op, offset).unwrap();
+            let expected = dense_csg((&a, a_size), (&b, b_size), op, offset, a_size);
+            assert_dense(&result, &expected, a_size, &format!("{:?} at {:?}", op, offset));
+        }
+    }
+}
+
+//Offsets that don't line up with nodes of either DAG, on every level
+#[test]
+fn unaligned_offsets_match_dense_data() {
+    let a_size = (13, 9, 20);
+    let b_size = (6, 11, 5);
+    let a = volume(a_size, |x, y, z| if (x * x + y * y + z) % 7 < 3 { (x % 3 + 1) as u8 } else { 0 });
+    let b = volume(b_size, |x, y, z| if (x ^ y ^ z) & 1 == 0 { (z % 4 + 5) as u8 } else { 0 });
+    let a_dag = DAG::from_voxel_data(&a, a_size).unwrap();
+    let b_dag = DAG::from_voxel_data(&b, b_size).unwrap();
+
+    for &offset in &[(3, -2, 5), (-7, 1, 0), (9, 0, -3), (1, 1, 1), (17, 2, 2)] {
+        for &op in &[CsgOp::Union, CsgOp::Intersection, CsgOp::Difference] {
+            let result = a_dag.csg(&b_dag, op, offset).unwrap();
+            let size = result.size();
+            let expected = dense_csg((&a, a_size), (&b, b_size), op, offset, size);
+            assert_dense(&result, &expected, size, &format!("{:?} at {:?}", op, offset));
+        }
+    }
+}
+
+//Where both are solid, a union keeps the material of the second DAG, the others keep the first
+#[test]
+fn materials_of_overlapping_voxels() {
+    let a = DAG::from_voxel_data(&[3; 8], (2, 2, 2)).unwrap();
+    let b = DAG::from_voxel_data(&[0, 5, 0, 5, 0, 5, 0, 5], (2, 2, 2)).unwrap();
+
+    let union = a.union(&b).unwrap();
+    assert_eq!(union.get(0, 0, 0), Some(3));
+    assert_eq!(union.get(1, 0, 0), Some(5));
+
+    let intersection = a.intersection(&b).unwrap();
+    assert_eq!(intersection.get(0, 0, 0), None);
+    assert_eq!(intersection.get(1, 1, 1), Some(3));
+
+    let difference = a.difference(&b).unwrap();
+    assert_eq!(difference.get(0, 1, 1), Some(3));
+    assert_eq!(difference.get(1, 0, 1), None);
+}
+
+#[test]
+fn empty_operands() {
+    let size = (5, 5, 5);
+    let data = volume(size, |x, y, z| ((x + y + z) % 2) as u8);
+    let dag = DAG::from_voxel_data(&data, size).unwrap();
+    let empty = DAG::from_voxel_data(&[0; 27], (3, 3, 3)).unwrap();
+
+    assert_dense(&dag.union(&empty).unwrap(), &data, size, "union with empty");
+    assert_dense(&dag.difference(&empty).unwrap(), &data, size, "difference with empty");
+    assert_dense(&dag.intersection(&empty).unwrap(), &[0; 125], size, "intersection with empty");
+    assert_dense(&empty.union(&dag).unwrap(), &data, size, "empty union");
+    assert_dense(&dag.difference(&dag).unwrap(), &[0; 125], size, "difference with itself");
+}
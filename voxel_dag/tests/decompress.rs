@@ -0,0 +1,101 @@
+use glam::*;
+
+use voxel_dag::dag::DAG;
+use voxel_dag::aabb::BoundingBox;
+use voxel_dag::bricks::LeafFormat;
+
+fn idx(size: (u32, u32, u32), x: u32, y: u32, z: u32) -> usize {
+    x as usize + y as usize * size.0 as usize + z as usize * size.0 as usize * size.1 as usize
+}
+
+//Every material from 1 to 255 shows up, with enough holes that nodes don't collapse into full cubes
+fn pattern(size: (u32, u32, u32)) -> Vec<u8> {
+    let mut data = vec![0; (size.0 * size.1 * size.2) as usize];
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let i = idx(size, x, y, z);
+                data[i] = if (x * 5 + y * 3 + z) % 4 == 0 { 0 } else { (i % 255 + 1) as u8 };
+            }
+        }
+    }
+    data
+}
+
+#[test]
+fn decompress_of_build_is_the_input() {
+    //Single voxels, sizes that only fill part of the cube, and full cubes
+    for &size in &[(1, 1, 1), (2, 2, 2), (3, 5, 2), (1, 1, 9), (16, 16, 16), (17, 3, 4), (13, 9, 20)] {
+        let data = pattern(size);
+        let dag = DAG::from_voxel_data(&data, size).unwrap();
+        assert_eq!(dag.to_voxel_data(), data, "{:?}", size);
+        assert_eq!(DAG::from_voxel_data(&dag.to_voxel_data(), size).unwrap().get_data(), dag.get_data(), "{:?}", size);
+
+        //DAGs that are too shallow for bricks are allowed to refuse
+        if let Ok(bricks) = dag.with_leaf_format(LeafFormat::Bricks) {
+            assert_eq!(bricks.to_voxel_data(), data, "{:?} with bricks", size);
+        }
+    }
+
+    assert_eq!(DAG::from_voxel_data(&[0; 40], (2, 4, 5)).unwrap().to_voxel_data(), vec![0; 40]);
+}
+
+//Regions can start before the volume, end past it, and have bounds that aren't on voxel boundaries
+#[test]
+fn decompress_regions() {
+    let size = (13, 9, 20);
+    let data = pattern(size);
+    let dag = DAG::from_voxel_data(&data, size).unwrap();
+    let bricks = dag.with_leaf_format(LeafFormat::Bricks).unwrap();
+
+    let regions = [
+        (Vec3A::new(0.0, 0.0, 0.0), Vec3A::new(13.0, 9.0, 20.0)),
+        (Vec3A::new(-6.5, -4.25, -5.75), Vec3A::new(2.0, 1.5, 3.0)),
+        (Vec3A::new(11.5, 7.0, 18.2), Vec3A::new(30.0, 12.0, 22.0)),
+        (Vec3A::new(4.0, 4.0, 4.0), Vec3A::new(4.5, 4.5, 4.5)), //Inside a single voxel
+        (Vec3A::new(-10.0, -10.0, -10.0), Vec3A::new(-2.0, 5.0, 5.0)), //Entirely outside
+        (Vec3A::new(3.3, -1.0, 7.9), Vec3A::new(9.1, 10.0, 16.0)),
+    ];
+    for (min, max) in regions.iter() {
+        let (region, region_size) = dag.decompress(&BoundingBox::new(*min, *max));
+        assert_eq!(bricks.decompress(&BoundingBox::new(*min, *max)), (region.clone(), region_size), "{:?} with bricks", min);
+
+        let first = (min.x().floor() as i64, min.y().floor() as i64, min.z().floor() as i64);
+        let expected_size = ((max.x().ceil() as i64 - first.0) as u32, (max.y().ceil() as i64 - first.1) as u32, (max.z().ceil() as i64 - first.2) as u32);
+        assert_eq!(region_size, expected_size, "{:?}", min);
+        for z in 0..region_size.2 {
+            for y in 0..region_size.1 {
+                for x in 0..region_size.0 {
+                    let (vx, vy, vz) = (first.0 + x as i64, first.1 + y as i64, first.2 + z as i64);
+                    let inside = vx >= 0 && vy >= 0 && vz >= 0 && vx < size.0 as i64 && vy < size.1 as i64 && vz < size.2 as i64;
+                    let expected = if inside { data[idx(size, vx as u32, vy as u32, vz as u32)] } else { 0 };
+                    assert_eq!(region[idx(region_size, x, y, z)], expected, "{:?} at {:?}", min, (x, y, z));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn empty_regions() {
+    let dag = DAG::from_voxel_data(&pattern((8, 8, 8)), (8, 8, 8)).unwrap();
+    let (region, size) = dag.decompress(&BoundingBox::new(Vec3A::new(2.0, 2.0, 2.0), Vec3A::new(2.0, 5.0, 5.0)));
+    assert_eq!(size, (0, 3, 3));
+    assert!(region.is_empty());
+    let (region, size) = dag.decompress(&BoundingBox::new(Vec3A::new(5.0, 5.0, 5.0), Vec3A::new(1.0, 1.0, 1.0)));
+    assert_eq!(size, (0, 0, 0));
+    assert!(region.is_empty());
+}
+
+//Edits move materials around in attribute order, decompression has to follow them
+#[test]
+fn decompress_after_edits() {
+    let size = (9, 6, 7);
+    let mut data = pattern(size);
+    let mut dag = DAG::from_voxel_data(&data, size).unwrap();
+    for &(x, y, z, material) in &[(0, 0, 0, 200), (8, 5, 6, 0), (4, 2, 3, 17), (4, 2, 3, 0), (1, 5, 0, 3), (7, 0, 6, 99)] {
+        if material == 0 { dag.clear_voxel(x, y, z).unwrap(); } else { dag.set_voxel(x, y, z, material).unwrap(); }
+        data[idx(size, x, y, z)] = material;
+        assert_eq!(dag.to_voxel_data(), data, "after {:?}", (x, y, z, material));
+    }
+}
@@ -0,0 +1,166 @@
+use glam::*;
+
+use voxel_dag::dag::DAG;
+use voxel_dag::aabb::BoundingBox;
+use voxel_dag::bricks::LeafFormat;
+use voxel_dag::edit::Shape;
+
+fn idx(size: (u32, u32, u32), x: u32, y: u32, z: u32) -> usize {
+    x as usize + y as usize * size.0 as usize + z as usize * size.0 as usize * size.1 as usize
+}
+
+//Applies an edit to the dense data, a voxel is inside of a shape when its center is
+fn dense_edit(data: &mut [u8], size: (u32, u32, u32), shape: &Shape, material: u8) {
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let center = Vec3A::new(x as f32, y as f32, z as f32) + Vec3A::splat(0.5);
+                let inside = match shape {
+                    Shape::Box(bounds) => center.cmpge(bounds.min).all() && center.cmplt(bounds.max).all(),
+                    Shape::Sphere { center: sphere, radius } => (center - *sphere).length() <= *radius,
+                };
+                if inside { data[idx(size, x, y, z)] = material; }
+            }
+        }
+    }
+}
+
+//Materials are in attribute order, which only depends on where the solid voxels are,
+//so an edited DAG has to end up with exactly the materials of a fresh build
+fn assert_edited(dag: &DAG, data: &[u8], size: (u32, u32, u32), what: &str) {
+    let fresh = DAG::from_voxel_data(data, size).unwrap().with_leaf_format(dag.leaf_format()).unwrap();
+    assert_eq!(dag.materials(), fresh.materials(), "{}: materials", what);
+    assert_eq!(dag.to_voxel_data(), data, "{}: voxels", what);
+    if let Err(err) = dag.validate() {
+        panic!("{}: {}", what, err);
+    }
+}
+
+fn layered(size: (u32, u32, u32)) -> Vec<u8> {
+    let mut data = vec![0; (size.0 * size.1 * size.2) as usize];
+    for z in 0..size.2 {
+        for y in 0..size.1 / 2 {
+            for x in 0..size.0 {
+                data[idx(size, x, y, z)] = (y % 3 + 1) as u8;
+            }
+        }
+    }
+    data
+}
+
+//Single voxel edits, in the middle of shared subtrees, on the far edges of sizes that don't fill the cube
+//and in empty space, where the path has to be created first
+#[test]
+fn voxel_edits_match_dense_data() {
+    for &size in &[(8, 8, 8), (13, 9, 20), (5, 3, 7)] {
+        let mut data = layered(size);
+        let mut dag = DAG::from_voxel_data(&data, size).unwrap();
+        let last = (size.0 - 1, size.1 - 1, size.2 - 1);
+        let edits = [
+            (0, 0, 0, 9), (last.0, last.1, last.2, 4), (last.0, 0, last.2, 0), (2, 1, 3, 200),
+            (2, 1, 3, 0), (1, last.1, 0, 7), (1, last.1, 0, 8), (last.0, last.1 / 2, 1, 5), (0, 0, 0, 0),
+        ];
+        for &(x, y, z, material) in &edits {
+            if material == 0 { dag.clear_voxel(x, y, z).unwrap(); } else { dag.set_voxel(x, y, z, material).unwrap(); }
+            data[idx(size, x, y, z)] = material;
+            assert_edited(&dag, &data, size, &format!("{:?} after {:?}", size, (x, y, z, material)));
+        }
+    }
+}
+
+#[test]
+fn shape_edits_match_dense_data() {
+    let size = (13, 9, 20);
+    let mut data = layered(size);
+    let mut dag = DAG::from_voxel_data(&data, size).unwrap();
+    let shapes = [
+        (Shape::Box(BoundingBox::new(Vec3A::new(-2.5, 1.0, 3.25), Vec3A::new(4.0, 7.5, 30.0))), 3),
+        (Shape::Sphere { center: Vec3A::new(6.0, 4.0, 10.0), radius: 4.5 }, 0),
+        (Shape::Box(BoundingBox::new(Vec3A::new(8.0, 0.0, 0.0), Vec3A::new(16.0, 8.0, 16.0))), 6), //An aligned 8x8x8 node
+        (Shape::Sphere { center: Vec3A::new(13.0, 9.0, 20.0), radius: 6.0 }, 11), //Around the far corner
+        (Shape::Box(BoundingBox::new(Vec3A::new(0.0, 0.0, 0.0), Vec3A::new(8.0, 8.0, 8.0))), 0),
+        (Shape::Box(BoundingBox::new(Vec3A::new(20.0, 0.0, 0.0), Vec3A::new(30.0, 9.0, 20.0))), 2), //Entirely outside
+    ];
+    for (i, (shape, material)) in shapes.iter().enumerate() {
+        if *material == 0 { dag.carve(shape).unwrap(); } else { dag.fill(shape, *material).unwrap(); }
+        dense_edit(&mut data, size, shape, *material);
+        assert_edited(&dag, &data, size, &format!("shape {}", i));
+    }
+
+    //Edits reuse existing blocks, so nothing but garbage separates the result from a fresh build
+    dag.compact();
+    assert_eq!(dag.get_data(), DAG::from_voxel_data(&data, size).unwrap().get_data());
+}
+
+//The two words of a brick leaf can be the same as those of an interior node, [childmask, first child],
+//so edits must not hand out one for the other
+#[test]
+fn brick_leaves_are_not_shared_with_interior_nodes() {
+    let size = (16, 16, 16);
+    let mut data = vec![0; 16 * 16 * 16];
+    data[idx(size, 0, 0, 0)] = 5;
+    let mut dag = DAG::from_voxel_data(&data, size).unwrap().with_leaf_format(LeafFormat::Bricks).unwrap();
+    for &(x, y, z, material) in &[(8, 0, 0, 7), (9, 0, 2, 8), (12, 0, 0, 4), (0, 0, 0, 0), (15, 15, 15, 1)] {
+        if material == 0 { dag.clear_voxel(x, y, z).unwrap(); } else { dag.set_voxel(x, y, z, material).unwrap(); }
+        data[idx(size, x, y, z)] = material;
+        assert_edited(&dag, &data, size, &format!("bricks after {:?}", (x, y, z, material)));
+    }
+}
+
+#[test]
+fn brick_edits_match_dense_data() {
+    for &size in &[(16, 16, 16), (13, 9, 20)] {
+        let mut data = layered(size);
+        let mut dag = DAG::from_voxel_data(&data, size).unwrap().with_leaf_format(LeafFormat::Bricks).unwrap();
+        for i in 0..40 {
+            let (x, y, z) = (i * 7 % size.0, i * 5 % size.1, i * 11 % size.2);
+            let material = (i % 4) as u8 * 3;
+            if material == 0 { dag.clear_voxel(x, y, z).unwrap(); } else { dag.set_voxel(x, y, z, material).unwrap(); }
+            data[idx(size, x, y, z)] = material;
+            assert_edited(&dag, &data, size, &format!("{:?} bricks after {:?}", size, (x, y, z, material)));
+        }
+
+        let shapes = [
+            (Shape::Box(BoundingBox::new(Vec3A::new(4.0, 0.0, 4.0), Vec3A::new(8.0, 4.0, 8.0))), 2), //Exactly one brick
+            (Shape::Sphere { center: Vec3A::new(6.0, 4.0, 10.0), radius: 4.5 }, 0),
+            (Shape::Box(BoundingBox::new(Vec3A::new(-1.0, 2.5, 1.0), Vec3A::new(30.0, 6.0, 9.5))), 10),
+        ];
+        for (i, (shape, material)) in shapes.iter().enumerate() {
+            if *material == 0 { dag.carve(shape).unwrap(); } else { dag.fill(shape, *material).unwrap(); }
+            dense_edit(&mut data, size, shape, *material);
+            assert_edited(&dag, &data, size, &format!("{:?} bricks shape {}", size, i));
+        }
+    }
+}
+
+#[test]
+fn edits_outside_of_the_volume_do_nothing() {
+    let size = (5, 3, 7);
+    let data = layered(size);
+    let mut dag = DAG::from_voxel_data(&data, size).unwrap();
+    let before = dag.get_data().to_vec();
+    //Inside of the padded cube, but past the original extents
+    dag.set_voxel(6, 0, 0, 3).unwrap();
+    dag.set_voxel(0, 3, 0, 3).unwrap();
+    dag.fill_box(BoundingBox::new(Vec3A::new(5.0, 0.0, 0.0), Vec3A::new(8.0, 8.0, 8.0)), 3).unwrap();
+    assert_eq!(dag.get_data(), &before[..]);
+    assert_edited(&dag, &data, size, "outside");
+}
+
+#[test]
+fn dirty_ranges_cover_changed_nodes() {
+    let size = (16, 16, 16);
+    let mut dag = DAG::from_voxel_data(&layered(size), size).unwrap();
+    for i in 0..20 {
+        let before = dag.get_data().to_vec();
+        let dirty = dag.set_voxel(i * 7 % 16, i * 3 % 16, i * 11 % 16, 9).unwrap();
+
+        let after = dag.get_data();
+        for node in 0..after.len() / 2 {
+            let changed = node >= before.len() / 2 || after[node * 2..node * 2 + 2] != before[node * 2..node * 2 + 2];
+            if changed {
+                assert!(dirty.iter().any(|range| range.contains(&(node as u32))), "node {} changed outside of {:?}", node, dirty);
+            }
+        }
+    }
+}
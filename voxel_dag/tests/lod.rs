@@ -0,0 +1,125 @@
+use voxel_dag::dag::DAG;
+
+fn idx(size: (u32, u32, u32), x: u32, y: u32, z: u32) -> usize {
+    x as usize + y as usize * size.0 as usize + z as usize * size.0 as usize * size.1 as usize
+}
+
+//Most common material in every cell of the dense data, empty voxels don't count and ties go to the lowest material
+fn dense_lod(data: &[u8], size: (u32, u32, u32), cell_size: u32) -> (Vec<u8>, (u32, u32, u32)) {
+    let lod_size = (
+        size.0.div_ceil(cell_size),
+        size.1.div_ceil(cell_size),
+        size.2.div_ceil(cell_size),
+    );
+    let mut lod = vec![0; (lod_size.0 * lod_size.1 * lod_size.2) as usize];
+    for z in 0..lod_size.2 {
+        for y in 0..lod_size.1 {
+            for x in 0..lod_size.0 {
+                let mut histogram = [0u32; 256];
+                for cz in z * cell_size..((z + 1) * cell_size).min(size.2) {
+                    for cy in y * cell_size..((y + 1) * cell_size).min(size.1) {
+                        for cx in x * cell_size..((x + 1) * cell_size).min(size.0) {
+                            histogram[data[idx(size, cx, cy, cz)] as usize] += 1;
+                        }
+                    }
+                }
+                let mut best = 0;
+                for material in 1..256 {
+                    if histogram[material] > 0 && (best == 0 || histogram[material] > histogram[best]) { best = material; }
+                }
+                lod[idx(lod_size, x, y, z)] = best as u8;
+            }
+        }
+    }
+    (lod, lod_size)
+}
+
+fn assert_lod(lod: &DAG, expected: &[u8], size: (u32, u32, u32), what: &str) {
+    assert_eq!(lod.size(), size, "{}", what);
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                assert_eq!(lod.get(x, y, z).unwrap_or(0), expected[idx(size, x, y, z)], "{} at {:?}", what, (x, y, z));
+            }
+        }
+    }
+}
+
+//Cells of 2x2x2 voxels in a 4x4x4 volume, each filled with a known mix of materials
+#[test]
+fn majority_ties_go_to_the_lowest_material() {
+    let size = (4, 4, 4);
+    let mut data = vec![0; 64];
+    let cells: [(u32, u32, u32, &[u8]); 4] = [
+        (0, 0, 0, &[3, 3, 3, 3, 2, 2, 2, 2]), //Tie between 2 and 3
+        (1, 0, 0, &[7, 0, 0, 0, 0, 0, 0, 0]), //Empty voxels never win
+        (0, 1, 0, &[5, 5, 6, 6, 6, 0, 0, 0]),
+        (1, 1, 1, &[9, 4, 9, 4, 1, 1, 0, 0]), //Three way tie
+    ];
+    for (cx, cy, cz, materials) in cells.iter() {
+        for (j, material) in materials.iter().enumerate() {
+            let j = j as u32;
+            data[idx(size, cx * 2 + j % 2, cy * 2 + j / 2 % 2, cz * 2 + j / 4 % 2)] = *material;
+        }
+    }
+
+    let lod = DAG::from_voxel_data(&data, size).unwrap().lod(1).unwrap();
+    assert_eq!(lod.size(), (2, 2, 2));
+    assert_eq!(lod.get(0, 0, 0), Some(2));
+    assert_eq!(lod.get(1, 0, 0), Some(7));
+    assert_eq!(lod.get(0, 1, 0), Some(6));
+    assert_eq!(lod.get(1, 1, 1), Some(1));
+    assert_eq!(lod.get(1, 1, 0), None);
+}
+
+//Sizes that aren't a multiple of the cell size round up, and the cells on the far edge only cover what's inside the volume
+#[test]
+fn lod_of_non_cube_volumes() {
+    for &size in &[(5, 3, 2), (13, 9, 20), (1, 17, 6)] {
+        //Layers of materials with a hole through the middle, so cells mix materials and empty space
+        let mut data = vec![0; (size.0 * size.1 * size.2) as usize];
+        for z in 0..size.2 {
+            for y in 0..size.1 {
+                for x in 0..size.0 {
+                    if (x + y) % 5 == 2 { continue; }
+                    data[idx(size, x, y, z)] = ((x * 3 + y + z * 2) % 4 + 1) as u8;
+                }
+            }
+        }
+
+        let dag = DAG::from_voxel_data(&data, size).unwrap();
+        for depth in 1..=dag.depth() {
+            let lod = dag.lod(depth).unwrap();
+            assert_eq!(lod.depth(), depth);
+            let (expected, lod_size) = dense_lod(&data, size, dag.lod_cell_size(depth));
+            assert_lod(&lod, &expected, lod_size, &format!("lod {} of {:?}", depth, size));
+        }
+    }
+}
+
+#[test]
+fn full_depth_lod_is_the_original() {
+    let size = (6, 7, 3);
+    let data: Vec<u8> = (0..6 * 7 * 3).map(|i| (i % 3) as u8).collect();
+    let dag = DAG::from_voxel_data(&data, size).unwrap();
+    let lod = dag.lod(dag.depth()).unwrap();
+    assert_eq!(dag.lod_cell_size(dag.depth()), 1);
+    assert_eq!(lod.get_data(), dag.get_data());
+    assert_lod(&lod, &data, size, "full depth");
+}
+
+#[test]
+fn lod_of_an_empty_volume() {
+    let dag = DAG::from_voxel_data(&[0; 8 * 8 * 8], (8, 8, 8)).unwrap();
+    let lod = dag.lod(1).unwrap();
+    assert_eq!(lod.size(), (2, 2, 2));
+    assert_lod(&lod, &[0; 8], (2, 2, 2), "empty");
+}
+
+#[test]
+fn lod_depth_out_of_range() {
+    let dag = DAG::from_voxel_data(&[1; 8 * 8 * 8], (8, 8, 8)).unwrap();
+    assert!(dag.lod(0).is_err());
+    assert!(dag.lod(dag.depth() + 1).is_err());
+    assert_eq!(dag.lod_cell_size(1), 4);
+}
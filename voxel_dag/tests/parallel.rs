@@ -0,0 +1,33 @@
+mod common;
+
+use voxel_dag::dag::DAG;
+
+use common::*;
+
+#[test]
+fn parallel_matches_build() {
+    let mut rng = Rng(314);
+    for &size in SIZES {
+        for &density in &[0, 5, 50, 100] {
+            let data = random_volume(&mut rng, size, density);
+            let dag = DAG::from_voxel_data(&data, size).unwrap();
+            let parallel = DAG::from_voxel_data_parallel(&data, size).unwrap();
+
+            //Both builders share the same layout, so the buffers have to be identical and not just equivalent
+            assert_eq!(parallel.get_data(), dag.get_data(), "nodes {:?} {}%", size, density);
+            assert_eq!(parallel.voxel_counts(), dag.voxel_counts(), "voxel counts {:?} {}%", size, density);
+            assert_eq!(parallel.materials(), dag.materials(), "materials {:?} {}%", size, density);
+        }
+    }
+}
+
+#[test]
+fn parallel_large_volume() {
+    //Big enough to be split into several tasks
+    let mut rng = Rng(315);
+    let size = (60, 40, 70);
+    let data = random_volume(&mut rng, size, 10);
+    let parallel = DAG::from_voxel_data_parallel(&data, size).unwrap();
+    assert_eq!(parallel.get_data(), DAG::from_voxel_data(&data, size).unwrap().get_data());
+    assert_matches(&parallel, &data, size, "parallel");
+}
@@ -0,0 +1,156 @@
+use glam::*;
+
+use voxel_dag::dag::DAG;
+use voxel_dag::aabb::BoundingBox;
+use voxel_dag::bricks::LeafFormat;
+use voxel_dag::octree::{Octree, required_level};
+use voxel_dag::voxel_data_structure::VoxelDAG;
+
+fn idx(size: (u32, u32, u32), x: u32, y: u32, z: u32) -> usize {
+    x as usize + y as usize * size.0 as usize + z as usize * size.0 as usize * size.1 as usize
+}
+
+//A few solid clusters with empty space between them, so boxes can land both in and next to geometry
+fn clusters(size: (u32, u32, u32)) -> Vec<u8> {
+    let mut data = vec![0; (size.0 * size.1 * size.2) as usize];
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                if (x / 3 + y / 2 + z / 3) % 3 == 0 && (x + z) % 2 == 0 {
+                    data[idx(size, x, y, z)] = ((x + y) % 6 + 1) as u8;
+                }
+            }
+        }
+    }
+    data
+}
+
+//Any solid voxel [v, v + 1) overlapping [min, max)
+fn dense_any_solid_in(data: &[u8], size: (u32, u32, u32), bounds: &BoundingBox) -> bool {
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                if data[idx(size, x, y, z)] == 0 { continue; }
+                let lo = Vec3A::new(x as f32, y as f32, z as f32);
+                if (lo + Vec3A::one()).cmpgt(bounds.min).all() && lo.cmplt(bounds.max).all() { return true; }
+            }
+        }
+    }
+    false
+}
+
+//Boxes inside the volume, around single voxels, crossing and past the extents, and empty ones
+fn boxes(size: (u32, u32, u32)) -> Vec<BoundingBox> {
+    let end = Vec3A::new(size.0 as f32, size.1 as f32, size.2 as f32);
+    let mut boxes = vec![
+        BoundingBox::new(Vec3A::zero(), end),
+        BoundingBox::new(Vec3A::splat(-100.0), Vec3A::splat(100.0)),
+        BoundingBox::new(Vec3A::splat(-5.0), Vec3A::splat(0.0)),
+        BoundingBox::new(end, end + Vec3A::splat(3.0)),
+        BoundingBox::new(end - Vec3A::splat(1.5), end + Vec3A::splat(20.0)),
+        BoundingBox::new(Vec3A::new(-3.0, 1.0, -0.5), Vec3A::new(0.25, 2.0, 40.0)),
+        BoundingBox::new(Vec3A::splat(2.0), Vec3A::splat(2.0)),
+        BoundingBox::new(Vec3A::splat(3.0), Vec3A::splat(1.0)),
+    ];
+    for z in (0..size.2).step_by(2) {
+        for y in (0..size.1).step_by(3) {
+            for x in 0..size.0 {
+                let min = Vec3A::new(x as f32 + 0.5, y as f32 - 0.25, z as f32 + 0.75);
+                boxes.push(BoundingBox::new(min, min + Vec3A::new(0.25, 1.0, (x % 4) as f32)));
+            }
+        }
+    }
+    boxes
+}
+
+#[test]
+fn dag_queries_match_dense_data() {
+    for &size in &[(8, 8, 8), (13, 9, 20), (5, 3, 2)] {
+        let data = clusters(size);
+        let dag = DAG::from_voxel_data(&data, size).unwrap();
+        let mut dags = vec![("voxels", dag)];
+        if let Ok(bricks) = dags[0].1.with_leaf_format(LeafFormat::Bricks) {
+            dags.push(("bricks", bricks));
+        }
+
+        for (leaves, dag) in &dags {
+            //Points past the extents, but inside of the padded cube, are empty as well
+            for z in 0..size.2 + 3 {
+                for y in 0..size.1 + 3 {
+                    for x in 0..size.0 + 3 {
+                        let inside = x < size.0 && y < size.1 && z < size.2;
+                        let expected = if inside { data[idx(size, x, y, z)] } else { 0 };
+                        assert_eq!(dag.get(x, y, z).unwrap_or(0), expected, "{:?} {} at {:?}", size, leaves, (x, y, z));
+                        assert_eq!(dag.is_solid(x, y, z), expected > 0, "{:?} {} at {:?}", size, leaves, (x, y, z));
+                    }
+                }
+            }
+            assert!(!dag.is_solid(u32::MAX, 0, 0));
+
+            for bounds in boxes(size) {
+                assert_eq!(dag.any_solid_in(&bounds), dense_any_solid_in(&data, size, &bounds), "{:?} {} in {:?}..{:?}", size, leaves, bounds.min, bounds.max);
+            }
+        }
+    }
+}
+
+#[test]
+fn octree_queries_match_dense_data() {
+    for &size in &[(8, 8, 8), (13, 9, 20), (5, 3, 2)] {
+        let data = clusters(size);
+        let mut octree = Octree::from_voxel_data(&data, size, required_level(size).max(2)).unwrap();
+        while octree.current_level.0 < required_level(size) {
+            octree.generate_level();
+        }
+
+        for z in 0..size.2 + 3 {
+            for y in 0..size.1 + 3 {
+                for x in 0..size.0 + 3 {
+                    let inside = x < size.0 && y < size.1 && z < size.2;
+                    assert_eq!(octree.is_solid(x, y, z), inside && data[idx(size, x, y, z)] > 0, "{:?} at {:?}", size, (x, y, z));
+                }
+            }
+        }
+        for bounds in boxes(size) {
+            assert_eq!(octree.any_solid_in(&bounds), dense_any_solid_in(&data, size, &bounds), "{:?} in {:?}..{:?}", size, bounds.min, bounds.max);
+        }
+    }
+}
+
+#[test]
+fn voxel_dag_queries_match_dense_data() {
+    for &size in &[(8, 8, 8), (13, 9, 20), (5, 3, 2)] {
+        let data = clusters(size);
+        let voxel_dag = VoxelDAG::from_voxel_data(&data, size, required_level(size)).unwrap();
+
+        for z in 0..size.2 + 3 {
+            for y in 0..size.1 + 3 {
+                for x in 0..size.0 + 3 {
+                    let inside = x < size.0 && y < size.1 && z < size.2;
+                    assert_eq!(voxel_dag.is_solid(x, y, z), inside && data[idx(size, x, y, z)] > 0, "{:?} at {:?}", size, (x, y, z));
+                }
+            }
+        }
+        for bounds in boxes(size) {
+            assert_eq!(voxel_dag.any_solid_in(&bounds), dense_any_solid_in(&data, size, &bounds), "{:?} in {:?}..{:?}", size, bounds.min, bounds.max);
+        }
+    }
+}
+
+//On a coarser level every cell spans several voxels, and is solid if any of them is
+#[test]
+fn coarse_voxel_dag_is_conservative() {
+    let size = (16, 16, 16);
+    let mut data = vec![0; 16 * 16 * 16];
+    data[idx(size, 5, 9, 14)] = 3;
+    let voxel_dag = VoxelDAG::from_voxel_data(&data, size, 2).unwrap();
+    for z in 0..16 {
+        for y in 0..16 {
+            for x in 0..16 {
+                assert_eq!(voxel_dag.is_solid(x, y, z), x / 4 == 1 && y / 4 == 2 && z / 4 == 3, "{:?}", (x, y, z));
+            }
+        }
+    }
+    assert!(voxel_dag.any_solid_in(&BoundingBox::new(Vec3A::new(7.5, 8.0, 12.0), Vec3A::new(8.0, 9.0, 13.0))));
+    assert!(!voxel_dag.any_solid_in(&BoundingBox::new(Vec3A::new(8.0, 8.0, 12.0), Vec3A::new(9.0, 9.0, 13.0))));
+}
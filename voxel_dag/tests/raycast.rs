@@ -0,0 +1,147 @@
+mod common;
+
+use glam::*;
+
+use voxel_dag::dag::DAG;
+use voxel_dag::bricks::LeafFormat;
+use voxel_dag::raycast::RayHit;
+use voxel_dag::symmetry::SymmetricDAG;
+
+use common::*;
+
+//Entry distance of a ray into a single voxel, 0 if it starts inside of it
+fn voxel_entry(origin: Vec3, dir: Vec3, voxel: (u32, u32, u32)) -> Option<f32> {
+    let origin = [origin.x(), origin.y(), origin.z()];
+    let dir = [dir.x(), dir.y(), dir.z()];
+    let min = [voxel.0 as f32, voxel.1 as f32, voxel.2 as f32];
+    let mut t_near = f32::NEG_INFINITY;
+    let mut t_far = f32::INFINITY;
+    for i in 0..3 {
+        if dir[i] == 0.0 {
+            if origin[i] < min[i] || origin[i] >= min[i] + 1.0 { return None; }
+            continue;
+        }
+        let t0 = (min[i] - origin[i]) / dir[i];
+        let t1 = (min[i] + 1.0 - origin[i]) / dir[i];
+        t_near = t_near.max(t0.min(t1));
+        t_far = t_far.min(t0.max(t1));
+    }
+    if t_near > t_far || t_far < 0.0 { return None; }
+    Some(t_near.max(0.0))
+}
+
+//Closest solid voxel by testing every single one of them
+fn brute_force(data: &[u8], size: (u32, u32, u32), origin: Vec3, dir: Vec3, max_t: f32) -> Option<(f32, (u32, u32, u32))> {
+    let dir = dir.normalize();
+    let mut closest: Option<(f32, (u32, u32, u32))> = None;
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                if data[idx(size, x, y, z)] == 0 { continue; }
+                if let Some(t) = voxel_entry(origin, dir, (x, y, z)) {
+                    if t <= max_t && closest.is_none_or(|(best, _)| t < best) {
+                        closest = Some((t, (x, y, z)));
+                    }
+                }
+            }
+        }
+    }
+    closest
+}
+
+fn assert_hit(hit: Option<RayHit>, data: &[u8], size: (u32, u32, u32), origin: Vec3, dir: Vec3, max_t: f32, what: &str) {
+    let expected = brute_force(data, size, origin, dir, max_t);
+    match (hit, expected) {
+        (None, None) => {},
+        (Some(hit), Some((t, voxel))) => {
+            assert!((hit.distance - t).abs() < 1e-3, "{}: distance {} instead of {} from {:?} along {:?}", what, hit.distance, t, origin, dir);
+            //Rays that graze an edge can enter two voxels at the same distance, either one is fine then
+            if hit.voxel != voxel {
+                let t_hit = voxel_entry(origin, dir.normalize(), hit.voxel).expect("hit voxel isn't on the ray");
+                assert!((t_hit - t).abs() < 1e-4, "{}: voxel {:?} instead of {:?}", what, hit.voxel, voxel);
+            }
+            assert_eq!(hit.material, data[idx(size, hit.voxel.0, hit.voxel.1, hit.voxel.2)], "{}: material of {:?}", what, hit.voxel);
+            if hit.distance > 0.0 {
+                //The normal points against the ray, along the axis it entered through
+                assert_eq!(hit.normal.length(), 1.0, "{}", what);
+                assert!(hit.normal.dot(dir) < 0.0, "{}: normal {:?}", what, hit.normal);
+            }
+        },
+        (hit, expected) => panic!("{}: hit {:?} instead of {:?} from {:?} along {:?}", what, hit.map(|hit| hit.voxel), expected.map(|e| e.1), origin, dir),
+    }
+}
+
+fn random_ray(rng: &mut Rng, size: (u32, u32, u32)) -> (Vec3, Vec3) {
+    let mut coord = |extent: u32| (rng.next() % ((extent + 8) * 100)) as f32 / 100.0 - 4.0 + 0.013;
+    let origin = Vec3::new(coord(size.0), coord(size.1), coord(size.2));
+    let mut component = || (rng.next() % 2001) as f32 / 1000.0 - 1.0;
+    let mut dir = Vec3::new(component(), component(), component());
+    //Axis aligned rays take a different path through the slab tests
+    match rng.next() % 8 {
+        0 => dir = Vec3::new(dir.x(), 0.0, 0.0),
+        1 => dir = Vec3::new(0.0, dir.y(), dir.z()),
+        _ => {},
+    }
+    if dir.length_squared() == 0.0 { dir = Vec3::new(0.0, 0.0, 1.0); }
+    (origin, dir)
+}
+
+#[test]
+fn raycast_matches_brute_force() {
+    let mut rng = Rng(21);
+    for &size in &[(8, 8, 8), (13, 9, 20), (16, 16, 16), (3, 5, 2)] {
+        let data = random_volume(&mut rng, size, 10);
+        let dag = DAG::from_voxel_data(&data, size).unwrap();
+        let bricks = dag.with_leaf_format(LeafFormat::Bricks).ok();
+        let symmetric = SymmetricDAG::from_voxel_data(&data, size).unwrap();
+        for _ in 0..300 {
+            let (origin, dir) = random_ray(&mut rng, size);
+            let max_t = if rng.next().is_multiple_of(4) { (rng.next() % 1000) as f32 / 100.0 } else { 100.0 };
+            let what = format!("{:?}", size);
+            assert_hit(dag.raycast(origin, dir, max_t), &data, size, origin, dir, max_t, &what);
+            if let Some(bricks) = &bricks {
+                assert_hit(bricks.raycast(origin, dir, max_t), &data, size, origin, dir, max_t, &format!("{} with bricks", what));
+            }
+            assert_hit(symmetric.raycast(origin, dir, max_t), &data, size, origin, dir, max_t, &format!("{} symmetric", what));
+        }
+    }
+}
+
+#[test]
+fn rays_starting_inside_a_voxel() {
+    let dag = DAG::from_voxel_data(&[0, 0, 0, 0, 0, 0, 0, 6], (2, 2, 2)).unwrap();
+    let hit = dag.raycast(Vec3::new(1.5, 1.5, 1.5), Vec3::new(-1.0, 0.0, 0.0), 10.0).unwrap();
+    assert_eq!(hit.distance, 0.0);
+    assert_eq!(hit.voxel, (1, 1, 1));
+    assert_eq!(hit.normal, Vec3::zero());
+    assert_eq!(hit.material, 6);
+
+    let hit = dag.raycast(Vec3::new(1.5, 1.5, -3.0), Vec3::new(0.0, 0.0, 2.0), 10.0).unwrap();
+    assert_eq!(hit.distance, 4.0);
+    assert_eq!(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+    assert!(dag.raycast(Vec3::new(1.5, 1.5, -3.0), Vec3::new(0.0, 0.0, 1.0), 3.5).is_none());
+}
+
+//Directions that can't be normalized never hit anything, even when the ray starts next to the volume
+#[test]
+fn degenerate_directions_miss() {
+    let data = vec![5; 4 * 4 * 4];
+    let dag = DAG::from_voxel_data(&data, (4, 4, 4)).unwrap();
+    let bricks = DAG::from_voxel_data(&vec![5; 8 * 8 * 8], (8, 8, 8)).unwrap().with_leaf_format(LeafFormat::Bricks).unwrap();
+    let symmetric = SymmetricDAG::from_voxel_data(&data, (4, 4, 4)).unwrap();
+
+    let directions = [
+        Vec3::zero(),
+        Vec3::new(f32::NAN, 1.0, 0.0),
+        Vec3::new(f32::INFINITY, 0.0, 0.0),
+        Vec3::new(1.0, f32::NEG_INFINITY, 1.0),
+        Vec3::new(1e-30, 0.0, 0.0),
+    ];
+    for &origin in &[Vec3::new(-5.0, -5.0, -5.0), Vec3::new(1.5, 1.5, 1.5)] {
+        for &dir in &directions {
+            assert!(dag.raycast(origin, dir, 100.0).is_none(), "{:?} from {:?}", dir, origin);
+            assert!(bricks.raycast(origin, dir, 100.0).is_none(), "{:?} from {:?} with bricks", dir, origin);
+            assert!(symmetric.raycast(origin, dir, 100.0).is_none(), "{:?} from {:?} symmetric", dir, origin);
+        }
+    }
+}
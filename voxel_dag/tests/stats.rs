@@ -0,0 +1,92 @@
+use voxel_dag::dag::DAG;
+use voxel_dag::bricks::LeafFormat;
+use voxel_dag::Error;
+
+//Hand written node buffers, as [childmask, first child] pairs. from_parts checks the nodes before
+//trusting them for anything, then validates the voxel counts it derived from them.
+fn assert_invalid(data: Vec<u32>, depth: u32, leaves: LeafFormat, materials: usize, node: u32, what: &str) {
+    let size = 1 << depth;
+    match DAG::from_parts(data, depth, (size, size, size), leaves, vec![1; materials]) {
+        Err(Error::InvalidNode { node: found, reason }) => assert_eq!(found, node, "{}: {}", what, reason),
+        Err(err) => panic!("{}: wrong error {}", what, err),
+        Ok(_) => panic!("{}: buffer was accepted", what),
+    }
+}
+
+#[test]
+fn hand_written_buffer_is_valid() {
+    //Two leaves below one interior node, depth 3 is an 8x8x8 volume with voxel leaves at level 2
+    let data = vec![0b1, 1, 0b1001, 2, 0xFF, 0, 0x01, 0];
+    let dag = DAG::from_parts(data, 3, (8, 8, 8), LeafFormat::Voxels, vec![1; 9]).unwrap();
+    dag.validate().unwrap();
+    assert_eq!(dag.voxel_counts(), &[9, 9, 8, 1]);
+    assert_eq!(dag.stats().leaf_count, 2);
+}
+
+#[test]
+fn child_index_out_of_range() {
+    //The root points at two children, but there is only one node after it
+    assert_invalid(vec![0b11, 1, 0x01, 0], 2, LeafFormat::Voxels, 1, 0, "past the end");
+    //Pointing back at the root is never valid
+    assert_invalid(vec![0b1, 0, 0x01, 0], 2, LeafFormat::Voxels, 1, 0, "at the root");
+    assert_invalid(vec![0b1, 1, 0b1, 7, 0x01, 0], 3, LeafFormat::Voxels, 1, 1, "below the root");
+}
+
+#[test]
+fn empty_childmask_on_an_interior_node() {
+    assert_invalid(vec![0b1, 1, 0, 0], 3, LeafFormat::Voxels, 0, 1, "voxel leaves");
+    assert_invalid(vec![0b1, 1, 0b1, 2, 0, 0, 0xF, 0], 5, LeafFormat::Bricks, 4, 2, "bricks");
+
+    //An empty root is fine, that's an empty volume
+    DAG::from_parts(vec![0, 0], 3, (8, 8, 8), LeafFormat::Voxels, Vec::new()).unwrap().validate().unwrap();
+}
+
+#[test]
+fn childmask_with_more_than_8_bits() {
+    assert_invalid(vec![0x100, 1, 0x01, 0], 2, LeafFormat::Voxels, 1, 0, "interior");
+    //Voxel leaves only have the low 8 bits of the childmask, brick leaves use both words
+    assert_invalid(vec![0b1, 1, 0x1FF, 0], 2, LeafFormat::Voxels, 9, 1, "voxel leaf");
+    assert_invalid(vec![0b1, 1, 0x01, 1], 2, LeafFormat::Voxels, 2, 1, "voxel leaf with a child");
+}
+
+#[test]
+fn cycles() {
+    //A node that is its own child
+    assert_invalid(vec![0b1, 1, 0b1, 1, 0x01, 0], 4, LeafFormat::Voxels, 1, 1, "self");
+    //Two nodes pointing at each other
+    assert_invalid(vec![0b1, 1, 0b1, 2, 0b1, 1, 0x01, 0], 5, LeafFormat::Voxels, 1, 1, "pair");
+    //Below a shared block, the second reference must not be mistaken for a cycle
+    let data = vec![0b11, 1, 0b1, 3, 0b1, 3, 0b1, 4, 0x01, 0];
+    DAG::from_parts(data, 4, (16, 16, 16), LeafFormat::Voxels, vec![1; 2]).unwrap().validate().unwrap();
+}
+
+//Node 1 is an interior node on level 1 and a brick leaf on level 2, with 4 voxels below it as the former
+//and 3 as the latter. Its single voxel count can't be right for both.
+#[test]
+fn voxel_count_mismatch() {
+    let data = vec![0b11, 1, 0b1, 3, 0b1, 1, 0xF, 0];
+    match DAG::from_parts(data, 4, (16, 16, 16), LeafFormat::Bricks, vec![1; 8]) {
+        Err(Error::InvalidNode { node, reason }) => {
+            assert_eq!(node, 1);
+            assert!(reason.contains("Voxel count"), "{}", reason);
+        },
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn material_count_mismatch() {
+    let data = vec![0b1, 1, 0b1001, 2, 0xFF, 0, 0x01, 0];
+    for &materials in &[0, 8, 10] {
+        match DAG::from_parts(data.clone(), 3, (8, 8, 8), LeafFormat::Voxels, vec![1; materials]) {
+            Err(Error::DimensionMismatch { expected, found }) => assert_eq!((expected, found), (9, materials)),
+            other => panic!("{} materials: {:?}", materials, other.map(|_| ())),
+        }
+    }
+}
+
+#[test]
+fn truncated_buffers() {
+    assert_invalid(Vec::new(), 2, LeafFormat::Voxels, 0, 0, "empty");
+    assert_invalid(vec![0b1, 1, 0x01], 2, LeafFormat::Voxels, 1, 0, "odd length");
+}
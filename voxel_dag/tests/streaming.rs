@@ -0,0 +1,85 @@
+mod common;
+
+use voxel_dag::dag::DAG;
+use voxel_dag::bricks::LeafFormat;
+use voxel_dag::streaming::{ChunkBlock, StreamingBuilder};
+
+use common::*;
+
+fn chunk_block(data: &[u8], size: (u32, u32, u32), min: (u32, u32, u32), brick_size: u32) -> ChunkBlock {
+    let mut block = vec![0; (brick_size * brick_size * brick_size) as usize];
+    for z in 0..brick_size {
+        for y in 0..brick_size {
+            for x in 0..brick_size {
+                let (vx, vy, vz) = (min.0 + x, min.1 + y, min.2 + z);
+                if vx < size.0 && vy < size.1 && vz < size.2 {
+                    block[idx((brick_size, brick_size, brick_size), x, y, z)] = data[idx(size, vx, vy, vz)];
+                }
+            }
+        }
+    }
+    ChunkBlock {
+        min,
+        data: block,
+    }
+}
+
+#[test]
+fn streaming_matches_build() {
+    let mut rng = Rng(99);
+    for &size in &[(8, 8, 8), (13, 9, 20), (32, 32, 32), (5, 5, 5)] {
+        for &brick_size in &[2, 4, 8] {
+            let data = random_volume(&mut rng, size, 30);
+            let dag = DAG::from_voxel_data(&data, size).unwrap();
+
+            let mut builder = match StreamingBuilder::new(size, brick_size, Vec::new()) {
+                Ok(builder) => builder,
+                Err(_) => continue, //Bricks larger than the volume
+            };
+            let depth = builder.depth();
+            for min in builder.brick_positions() {
+                builder.add_brick(chunk_block(&data, size, min, brick_size)).unwrap();
+            }
+            let (nodes, materials) = builder.finish().unwrap();
+            let streamed = DAG::from_parts(nodes, depth, size, LeafFormat::Voxels, materials).unwrap();
+
+            assert_matches(&streamed, &data, size, &format!("streaming {:?} in {} bricks", size, brick_size));
+            assert_eq!(streamed.get_data(), dag.get_data());
+            assert_eq!(streamed.materials(), dag.materials());
+        }
+    }
+}
+
+#[test]
+fn empty_bricks_can_be_skipped() {
+    let mut rng = Rng(100);
+    let size = (16, 16, 16);
+    let mut data = random_volume(&mut rng, size, 30);
+    for z in 0..8 {
+        for y in 0..16 {
+            for x in 0..16 {
+                data[idx(size, x, y, z)] = 0;
+            }
+        }
+    }
+
+    let builder = StreamingBuilder::new(size, 8, Vec::new()).unwrap();
+    let bricks: Vec<ChunkBlock> = builder.brick_positions().into_iter()
+        .filter(|min| min.2 >= 8)
+        .map(|min| chunk_block(&data, size, min, 8))
+        .collect();
+    let streamed = DAG::from_bricks(size, 8, bricks).unwrap();
+    assert_eq!(streamed.get_data(), DAG::from_voxel_data(&data, size).unwrap().get_data());
+}
+
+#[test]
+fn bricks_out_of_order_are_rejected() {
+    let size = (16, 16, 16);
+    let data = vec![1; 16 * 16 * 16];
+    let mut builder = StreamingBuilder::new(size, 8, Vec::new()).unwrap();
+    let positions = builder.brick_positions();
+    builder.add_brick(chunk_block(&data, size, positions[1], 8)).unwrap();
+    assert!(builder.add_brick(chunk_block(&data, size, positions[0], 8)).is_err());
+    assert!(builder.add_brick(chunk_block(&data, size, positions[1], 8)).is_err());
+    assert!(builder.add_brick(chunk_block(&data, size, (3, 0, 0), 8)).is_err());
+}
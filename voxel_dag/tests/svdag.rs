@@ -0,0 +1,134 @@
+mod common;
+
+use voxel_dag::dag::DAG;
+use voxel_dag::svdag::{self, Section};
+use voxel_dag::bricks::LeafFormat;
+
+use common::*;
+
+fn encode(dag: &DAG, sections: &[Section]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    svdag::write(dag, sections, &mut bytes).unwrap();
+    bytes
+}
+
+fn material_section(dag: &DAG) -> Section {
+    Section {
+        tag: svdag::MATERIAL_TAG,
+        data: dag.materials().to_vec(),
+    }
+}
+
+//Recomputes the checksum after a file was changed by hand
+fn fix_checksum(bytes: &mut [u8]) {
+    let body_len = bytes.len() - 4;
+    let checksum = crc32fast::hash(&bytes[..body_len]);
+    bytes[body_len..].copy_from_slice(&checksum.to_le_bytes());
+}
+
+#[test]
+fn corrupt_files_are_errors() {
+    let mut rng = Rng(13);
+    let size = (9, 8, 5);
+    let data = random_volume(&mut rng, size, 30);
+    let dag = DAG::from_voxel_data(&data, size).unwrap();
+    let bytes = encode(&dag, &[material_section(&dag)]);
+
+    //Fix up the checksum after every change, so the contents themselves get checked
+    for i in 0..bytes.len() - 4 {
+        for &flip in &[0x01, 0xc0] {
+            let mut corrupt = bytes.clone();
+            corrupt[i] ^= flip;
+            fix_checksum(&mut corrupt);
+            if let Ok((dag, _)) = svdag::read(&mut &corrupt[..]) {
+                dag.validate().unwrap();
+                assert_eq!(dag.materials().len(), dag.stats().voxel_count as usize);
+            }
+        }
+    }
+}
+
+#[test]
+fn round_trip_keeps_every_field() {
+    //Sizes that don't fill the cube, so the stored size and depth can't be derived from each other
+    let size = (17, 3, 9);
+    let data: Vec<u8> = (0..17 * 3 * 9).map(|i| if i % 3 == 0 { 0 } else { (i % 250 + 1) as u8 }).collect();
+    let dag = DAG::from_voxel_data(&data, size).unwrap();
+    for &leaves in &[LeafFormat::Voxels, LeafFormat::Bricks] {
+        let dag = dag.with_leaf_format(leaves).unwrap();
+        //Unknown sections come back untouched, in order
+        let sections = [
+            Section { tag: *b"NOTE", data: b"baked by a test".to_vec() },
+            material_section(&dag),
+            Section { tag: *b"EMTY", data: Vec::new() },
+        ];
+        let (read, read_sections) = svdag::read(&mut &encode(&dag, &sections)[..]).unwrap();
+        assert_eq!(read.get_data(), dag.get_data());
+        assert_eq!(read.materials(), dag.materials());
+        assert_eq!(read.leaf_format(), leaves);
+        assert_eq!(read.depth(), dag.depth());
+        assert_eq!(read.size(), size);
+        assert_eq!(read_sections.iter().map(|section| (section.tag, section.data.clone())).collect::<Vec<_>>(),
+            sections.iter().map(|section| (section.tag, section.data.clone())).collect::<Vec<_>>());
+        assert_eq!(read.to_voxel_data(), data);
+    }
+}
+
+#[test]
+fn missing_materials_default_to_solid() {
+    let dag = DAG::from_voxel_data(&[0, 4, 4, 0, 0, 0, 0, 9], (2, 2, 2)).unwrap();
+    let (read, sections) = svdag::read(&mut &encode(&dag, &[])[..]).unwrap();
+    assert!(sections.is_empty());
+    assert_eq!(read.get_data(), dag.get_data());
+    assert_eq!(read.materials().len(), 3);
+    assert!(read.get(1, 0, 0).is_some());
+    assert_eq!(read.get(0, 0, 0), None);
+}
+
+//Version 1 has no leaf format field, and always uses voxel leaves
+#[test]
+fn reads_version_1() {
+    let size = (5, 6, 7);
+    let data: Vec<u8> = (0..5 * 6 * 7).map(|i| (i % 4) as u8).collect();
+    let dag = DAG::from_voxel_data(&data, size).unwrap();
+    let mut bytes = encode(&dag, &[material_section(&dag)]);
+    bytes[4..8].copy_from_slice(&1u32.to_le_bytes());
+    bytes.drain(24..28);
+    fix_checksum(&mut bytes);
+
+    let (read, _) = svdag::read(&mut &bytes[..]).unwrap();
+    assert_eq!(read.leaf_format(), LeafFormat::Voxels);
+    assert_eq!(read.to_voxel_data(), data);
+}
+
+#[test]
+fn save_and_load() {
+    let size = (12, 4, 4);
+    let data: Vec<u8> = (0..12 * 4 * 4).map(|i| (i % 7) as u8).collect();
+    let dag = DAG::from_voxel_data(&data, size).unwrap();
+    let path = std::env::temp_dir().join(format!("voxel_dag_test_{}.svdag", std::process::id()));
+    dag.save(&path).unwrap();
+    let loaded = DAG::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    let loaded = loaded.unwrap();
+    assert_eq!(loaded.get_data(), dag.get_data());
+    assert_eq!(loaded.to_voxel_data(), data);
+}
+
+#[test]
+fn truncated_files_are_errors() {
+    let dag = DAG::from_voxel_data(&[3; 8 * 8 * 8], (8, 8, 8)).unwrap();
+    let bytes = encode(&dag, &[material_section(&dag)]);
+    for len in 0..bytes.len() {
+        assert!(svdag::read(&mut &bytes[..len]).is_err(), "{} bytes", len);
+    }
+}
+
+#[test]
+fn rejects_sizes_that_dont_fit_the_depth() {
+    let dag = DAG::from_voxel_data(&[1; 8], (2, 2, 2)).unwrap();
+    let mut bytes = encode(&dag, &[material_section(&dag)]);
+    bytes[8..12].copy_from_slice(&3u32.to_le_bytes());
+    fix_checksum(&mut bytes);
+    assert!(svdag::read(&mut &bytes[..]).is_err());
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use glam::*;
+
+use voxel_dag::dag::DAG;
+use voxel_dag::symmetry::SymmetricDAG;
+
+use common::*;
+
+fn assert_symmetric_matches(dag: &SymmetricDAG, data: &[u8], size: (u32, u32, u32), what: &str) {
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                assert_eq!(dag.get(x, y, z).unwrap_or(0), data[idx(size, x, y, z)], "{}: voxel {:?}", what, (x, y, z));
+            }
+        }
+    }
+}
+
+#[test]
+fn symmetric_matches_dense_data() {
+    let mut rng = Rng(42);
+    for &size in SIZES {
+        let data = random_volume(&mut rng, size, 30);
+        let symmetric = SymmetricDAG::from_voxel_data(&data, size).unwrap();
+        assert_symmetric_matches(&symmetric, &data, size, &format!("symmetric {:?}", size));
+        assert_eq!(symmetric.materials(), DAG::from_voxel_data(&data, size).unwrap().materials());
+    }
+}
+
+#[test]
+fn mirrored_halves_are_merged() {
+    let mut rng = Rng(43);
+    let half = (8, 16, 16);
+    let size = (16, 16, 16);
+    let data = random_volume(&mut rng, half, 30);
+    let mut mirrored = vec![0; (size.0 * size.1 * size.2) as usize];
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..half.0 {
+                let material = data[idx(half, x, y, z)];
+                mirrored[idx(size, x, y, z)] = material;
+                mirrored[idx(size, size.0 - 1 - x, y, z)] = material;
+            }
+        }
+    }
+
+    let symmetric = SymmetricDAG::from_voxel_data(&mirrored, size).unwrap();
+    assert_symmetric_matches(&symmetric, &mirrored, size, "mirrored");
+    let report = symmetric.report(&DAG::from_voxel_data(&mirrored, size).unwrap());
+    assert!(report.symmetric_nodes < report.plain_nodes, "{:?}", report);
+}
+
+//The shader traces both kinds of DAG with the same code, so the CPU traversal of a symmetric DAG has to agree with the plain one
+#[test]
+fn raycasts_match_the_plain_dag() {
+    let mut rng = Rng(44);
+    for &size in &[(16, 16, 16), (13, 9, 20)] {
+        //Mirrored along x, so plenty of references carry a mirror
+        let mut data = random_volume(&mut rng, size, 15);
+        for z in 0..size.2 {
+            for y in 0..size.1 {
+                for x in 0..size.0 / 2 {
+                    data[idx(size, size.0 - 1 - x, y, z)] = data[idx(size, x, y, z)];
+                }
+            }
+        }
+        let plain = DAG::from_voxel_data(&data, size).unwrap();
+        let symmetric = SymmetricDAG::from_voxel_data(&data, size).unwrap();
+
+        for _ in 0..500 {
+            let mut coord = |extent: u32| (rng.next() % ((extent + 10) * 64)) as f32 / 64.0 - 5.0 + 0.007;
+            let origin = Vec3::new(coord(size.0), coord(size.1), coord(size.2));
+            let mut component = || (rng.next() % 2001) as f32 / 1000.0 - 1.0;
+            let dir = Vec3::new(component(), component(), component());
+
+            let expected = plain.raycast(origin, dir, 100.0);
+            let hit = symmetric.raycast(origin, dir, 100.0);
+            match (hit, expected) {
+                (None, None) => {},
+                (Some(hit), Some(expected)) => {
+                    assert_eq!(hit.voxel, expected.voxel, "{:?} from {:?} along {:?}", size, origin, dir);
+                    assert_eq!(hit.distance, expected.distance);
+                    assert_eq!(hit.normal, expected.normal);
+                    assert_eq!(hit.material, expected.material);
+                },
+                (hit, expected) => panic!("{:?}: {:?} instead of {:?} from {:?} along {:?}", size, hit.map(|hit| hit.voxel), expected.map(|hit| hit.voxel), origin, dir),
+            }
+        }
+    }
+}
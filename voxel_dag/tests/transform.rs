@@ -0,0 +1,223 @@
+use voxel_dag::dag::DAG;
+use voxel_dag::bricks::LeafFormat;
+use voxel_dag::transform::Axis;
+use voxel_dag::Error;
+
+fn idx(size: (u32, u32, u32), x: u32, y: u32, z: u32) -> usize {
+    x as usize + y as usize * size.0 as usize + z as usize * size.0 as usize * size.1 as usize
+}
+
+fn volume<F: Fn(u32, u32, u32) -> u8>(size: (u32, u32, u32), material: F) -> Vec<u8> {
+    let mut data = vec![0; (size.0 * size.1 * size.2) as usize];
+    for z in 0..size.2 {
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                data[idx(size, x, y, z)] = material(x, y, z);
+            }
+        }
+    }
+    data
+}
+
+//Asymmetric on every axis, so any wrong turn or flip shows up
+fn pattern(size: (u32, u32, u32)) -> Vec<u8> {
+    volume(size, |x, y, z| if (x * 3 + y * 5 + z * 7) % 4 != 0 && x + y >= z / 2 { (x % 3 + y % 2 * 3 + 1) as u8 } else { 0 })
+}
+
+//A quarter turn of dense data, counter-clockwise when looking down the axis
+fn dense_quarter_turn(data: &[u8], size: (u32, u32, u32), axis: Axis) -> (Vec<u8>, (u32, u32, u32)) {
+    match axis {
+        Axis::X => {
+            let new_size = (size.0, size.2, size.1);
+            (volume(new_size, |x, y, z| data[idx(size, x, z, size.2 - 1 - y)]), new_size)
+        },
+        Axis::Y => {
+            let new_size = (size.2, size.1, size.0);
+            (volume(new_size, |x, y, z| data[idx(size, size.0 - 1 - z, y, x)]), new_size)
+        },
+        Axis::Z => {
+            let new_size = (size.1, size.0, size.2);
+            (volume(new_size, |x, y, z| data[idx(size, y, size.1 - 1 - x, z)]), new_size)
+        },
+    }
+}
+
+fn dense_mirror(data: &[u8], size: (u32, u32, u32), axis: Axis) -> Vec<u8> {
+    volume(size, |x, y, z| match axis {
+        Axis::X => data[idx(size, size.0 - 1 - x, y, z)],
+        Axis::Y => data[idx(size, x, size.1 - 1 - y, z)],
+        Axis::Z => data[idx(size, x, y, size.2 - 1 - z)],
+    })
+}
+
+//Most common material of every cube, ties go to the lowest material
+fn dense_downscale(data: &[u8], size: (u32, u32, u32), levels: u32) -> (Vec<u8>, (u32, u32, u32)) {
+    let cell = 1 << levels;
+    let new_size = (size.0.div_ceil(cell), size.1.div_ceil(cell), size.2.div_ceil(cell));
+    let data = volume(new_size, |x, y, z| {
+        let mut histogram = [0u32; 256];
+        for vz in z * cell..((z + 1) * cell).min(size.2) {
+            for vy in y * cell..((y + 1) * cell).min(size.1) {
+                for vx in x * cell..((x + 1) * cell).min(size.0) {
+                    let material = data[idx(size, vx, vy, vz)];
+                    if material > 0 { histogram[material as usize] += 1; }
+                }
+            }
+        }
+        let mut best = 0;
+        for material in 1..256 {
+            if histogram[material] > histogram[best] { best = material; }
+        }
+        best as u8
+    });
+    (data, new_size)
+}
+
+fn assert_dense(dag: &DAG, expected: &[u8], size: (u32, u32, u32), what: &str) {
+    assert_eq!(dag.size(), size, "{}", what);
+    assert_eq!(dag.to_voxel_data(), expected, "{}", what);
+    if let Err(err) = dag.validate() {
+        panic!("{}: {}", what, err);
+    }
+    //At the same depth the result has to be as deduplicated as a fresh build, with materials in the same order
+    let fresh = DAG::from_voxel_data(expected, size).unwrap().with_leaf_format(dag.leaf_format()).unwrap();
+    if fresh.depth() == dag.depth() {
+        assert_eq!(dag.get_data(), fresh.get_data(), "{}: nodes", what);
+        assert_eq!(dag.materials(), fresh.materials(), "{}: materials", what);
+    }
+}
+
+const SIZES: &[(u32, u32, u32)] = &[(8, 8, 8), (13, 9, 20), (5, 3, 2), (1, 1, 1), (16, 16, 16)];
+const AXES: &[Axis] = &[Axis::X, Axis::Y, Axis::Z];
+
+//Voxel leaves, and brick leaves where the volume is deep enough for them
+fn dags(data: &[u8], size: (u32, u32, u32)) -> Vec<DAG> {
+    let dag = DAG::from_voxel_data(data, size).unwrap();
+    match dag.with_leaf_format(LeafFormat::Bricks) {
+        Ok(bricks) => vec![dag, bricks],
+        Err(_) => vec![dag],
+    }
+}
+
+#[test]
+fn rotate_matches_dense_data() {
+    for &size in SIZES {
+        let data = pattern(size);
+        for dag in dags(&data, size) {
+            for &axis in AXES {
+                let mut expected = (data.clone(), size);
+                for turns in 0..6 {
+                    let what = format!("{:?} {:?} rotated {} times around {:?}", size, dag.leaf_format(), turns, axis);
+                    let rotated = dag.rotate(axis, turns).unwrap();
+                    assert_eq!(rotated.leaf_format(), dag.leaf_format(), "{}", what);
+                    assert_dense(&rotated, &expected.0, expected.1, &what);
+                    expected = dense_quarter_turn(&expected.0, expected.1, axis);
+                }
+            }
+            //Turns around different axes compose like the dense ones
+            let (expected, expected_size) = dense_quarter_turn(&data, size, Axis::X);
+            let (expected, expected_size) = dense_quarter_turn(&expected, expected_size, Axis::Z);
+            let rotated = dag.rotate(Axis::X, 1).unwrap().rotate(Axis::Z, 1).unwrap();
+            assert_dense(&rotated, &expected, expected_size, &format!("{:?} X then Z", size));
+        }
+    }
+}
+
+#[test]
+fn mirror_matches_dense_data() {
+    for &size in SIZES {
+        let data = pattern(size);
+        for dag in dags(&data, size) {
+            for &axis in AXES {
+                let what = format!("{:?} {:?} mirrored along {:?}", size, dag.leaf_format(), axis);
+                let mirrored = dag.mirror(axis).unwrap();
+                assert_dense(&mirrored, &dense_mirror(&data, size, axis), size, &what);
+                assert_dense(&mirrored.mirror(axis).unwrap(), &data, size, &format!("{} twice", what));
+            }
+        }
+    }
+}
+
+#[test]
+fn translate_matches_dense_data() {
+    for &size in SIZES {
+        let data = pattern(size);
+        for dag in dags(&data, size) {
+            for &offset in &[(0, 0, 0), (1, 0, 0), (3, 7, 2), (-2, 0, 1), (0, -1, -3), (9, -4, 17), (-20, 0, 0)] {
+                let what = format!("{:?} {:?} moved by {:?}", size, dag.leaf_format(), offset);
+                let translated = dag.translate(offset).unwrap();
+                //Voxels below 0 are dropped, the size only ever covers what's left
+                let end = |size: u32, offset: i32| (size as i64 + offset as i64).max(0) as u32;
+                let new_size = (end(size.0, offset.0), end(size.1, offset.1), end(size.2, offset.2));
+                let expected = volume(new_size, |x, y, z| {
+                    let source = (x as i64 - offset.0 as i64, y as i64 - offset.1 as i64, z as i64 - offset.2 as i64);
+                    if source.0 < 0 || source.1 < 0 || source.2 < 0 { return 0; }
+                    data[idx(size, source.0 as u32, source.1 as u32, source.2 as u32)]
+                });
+                assert_dense(&translated, &expected, new_size, &what);
+            }
+        }
+    }
+}
+
+#[test]
+fn upscale_matches_dense_data() {
+    for &size in &[(8, 8, 8), (5, 3, 2), (1, 1, 1), (3, 1, 6)] {
+        let data = pattern(size);
+        for dag in dags(&data, size) {
+            for levels in 0..3 {
+                let what = format!("{:?} {:?} upscaled by {}", size, dag.leaf_format(), levels);
+                let upscaled = dag.upscale(levels).unwrap();
+                assert_eq!(upscaled.depth(), dag.depth() + levels, "{}", what);
+                let new_size = (size.0 << levels, size.1 << levels, size.2 << levels);
+                let expected = volume(new_size, |x, y, z| data[idx(size, x >> levels, y >> levels, z >> levels)]);
+                assert_dense(&upscaled, &expected, new_size, &what);
+            }
+        }
+    }
+}
+
+#[test]
+fn downscale_matches_dense_data() {
+    for &size in SIZES {
+        let data = pattern(size);
+        for dag in dags(&data, size) {
+            for levels in 0..dag.depth() {
+                let what = format!("{:?} {:?} downscaled by {}", size, dag.leaf_format(), levels);
+                let (expected, new_size) = dense_downscale(&data, size, levels);
+                assert_dense(&dag.downscale(levels).unwrap(), &expected, new_size, &what);
+            }
+            match dag.downscale(dag.depth()) {
+                Err(Error::InvalidLevel(_)) => {},
+                other => panic!("{:?} downscaled to nothing: {:?}", size, other.map(|dag| dag.size())),
+            }
+        }
+    }
+}
+
+#[test]
+fn upscale_overflow_is_an_error() {
+    let dag = DAG::from_voxel_data(&[1, 0, 0, 2, 0, 0, 0, 0], (2, 2, 2)).unwrap();
+    let empty = DAG::from_voxel_data(&[0; 8], (2, 2, 2)).unwrap();
+
+    //The depth would overflow, or go past what a u32 coordinate can address
+    for &levels in &[u32::MAX, u32::MAX - dag.depth() + 1, 31] {
+        match dag.upscale(levels) {
+            Err(Error::InvalidLevel(_)) => {},
+            other => panic!("upscale({}): {:?}", levels, other.map(|dag| dag.size())),
+        }
+    }
+
+    //Repeating every material 8^levels times needs more than a u32 attribute index, or more than a usize
+    for &levels in &[11, 21, 22, 29] {
+        match dag.upscale(levels) {
+            Err(Error::NodeIndexOverflow) => {},
+            other => panic!("upscale({}): {:?}", levels, other.map(|dag| dag.size())),
+        }
+    }
+
+    //Nothing to repeat in an empty volume, as long as the size still fits
+    let upscaled = empty.upscale(8).unwrap();
+    assert_eq!(upscaled.size(), (512, 512, 512));
+    assert!(upscaled.materials().is_empty());
+}
@@ -0,0 +1,26 @@
+mod common;
+
+use voxel_dag::dag::DAG;
+use voxel_dag::volume::Volume;
+use voxel_dag::voxel_data_structure::VoxelDAG;
+
+use common::*;
+
+#[test]
+fn volume_constructors_match_voxel_data() {
+    let mut rng = Rng(31);
+    let size = (13, 9, 20);
+    let data = random_volume(&mut rng, size, 30);
+    let volume = Volume::from_data(data.clone(), size).unwrap();
+
+    assert_eq!(DAG::from_volume(&volume).unwrap().get_data(), DAG::from_voxel_data(&data, size).unwrap().get_data());
+    let voxel_dag = VoxelDAG::from_volume(&volume, 5).unwrap();
+    assert_eq!(voxel_dag.get_data(), VoxelDAG::from_voxel_data(&data, size, 5).unwrap().get_data());
+    assert_eq!(voxel_dag.size(), size);
+    assert_eq!(DAG::from_volume(&volume).unwrap().to_volume(), volume);
+}
+
+#[test]
+fn volume_rejects_mismatched_data() {
+    assert!(Volume::from_data(vec![0; 7], (2, 2, 2)).is_err());
+}